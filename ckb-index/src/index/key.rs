@@ -1,4 +1,6 @@
-use super::types::{BlockDeltaInfo, CellIndex, HashType, HeaderInfo, LiveCellInfo, TxInfo};
+use super::types::{
+    BlockDeltaInfo, CellIndex, HashType, HeaderInfo, LiveCellInfo, LockTxInfo, TxInfo,
+};
 use ckb_sdk::NetworkType;
 use ckb_types::{
     packed::{Header, OutPoint, Script},
@@ -18,6 +20,9 @@ pub enum KeyType {
     LastHeader = 2,
     // key => value: {type} => u128
     TotalCapacity = 3,
+    // >> The first block indexed with explorer enabled, the lock tx records since it are complete
+    // key => value: {type} => u64
+    ExplorerStartNumber = 4,
 
     // >> hash-type: block, transaction, lock, data
     // key => value: {type}:{hash} => {hash-type}
@@ -46,7 +51,7 @@ pub enum KeyType {
     LockTotalCapacityIndex = 302,
     // key => value: {type}:{lock-hash}:{block-number}:{CellIndex} => {OutPoint}
     LockLiveCellIndex = 303,
-    // key => value: {type}:{lock-hash}:{block-number}:{tx-index(u32)} => {tx-hash}
+    // key => value: {type}:{lock-hash}:{block-number}:{tx-index(u32)} => {LockTxInfo}
     LockTx = 304,
 
     // key => value: {type}:{type-hash}:{block-number}:{CellIndex} => {OutPoint}
@@ -67,6 +72,7 @@ impl KeyType {
             1 => KeyType::Network,
             2 => KeyType::LastHeader,
             3 => KeyType::TotalCapacity,
+            4 => KeyType::ExplorerStartNumber,
 
             100 => KeyType::GlobalHash,
             101 => KeyType::TxMap,
@@ -96,6 +102,7 @@ pub enum Key {
     Network,
     LastHeader,
     TotalCapacity,
+    ExplorerStartNumber,
 
     GlobalHash(H256),
     TxMap(H256),
@@ -110,6 +117,7 @@ pub enum Key {
     LockTotalCapacityIndex(u64, H256),
     LockLiveCellIndexPrefix(H256, Option<u64>),
     LockLiveCellIndex(H256, u64, CellIndex),
+    LockTxPrefix(H256, Option<u64>),
     LockTx(H256, u64, u32),
 
     TypeLiveCellIndexPrefix(H256, Option<u64>),
@@ -125,6 +133,7 @@ impl Key {
             Key::Network => KeyType::Network.to_bytes(),
             Key::LastHeader => KeyType::LastHeader.to_bytes(),
             Key::TotalCapacity => KeyType::TotalCapacity.to_bytes(),
+            Key::ExplorerStartNumber => KeyType::ExplorerStartNumber.to_bytes(),
             Key::GlobalHash(hash) => {
                 let mut bytes = KeyType::GlobalHash.to_bytes();
                 bytes.extend(hash.as_bytes().to_vec());
@@ -191,6 +200,14 @@ impl Key {
                 bytes.extend(cell_index.to_bytes());
                 bytes
             }
+            Key::LockTxPrefix(lock_hash, number_opt) => {
+                let mut bytes = KeyType::LockTx.to_bytes();
+                bytes.extend(lock_hash.as_bytes().to_vec());
+                if let Some(number) = number_opt {
+                    bytes.extend(number.to_be_bytes().to_vec());
+                }
+                bytes
+            }
             Key::LockTx(lock_hash, number, tx_index) => {
                 let mut bytes = KeyType::LockTx.to_bytes();
                 bytes.extend(lock_hash.as_bytes().to_vec());
@@ -245,6 +262,7 @@ impl Key {
             KeyType::Network => Key::Network,
             KeyType::LastHeader => Key::LastHeader,
             KeyType::TotalCapacity => Key::TotalCapacity,
+            KeyType::ExplorerStartNumber => Key::ExplorerStartNumber,
             KeyType::GlobalHash => {
                 let hash = H256::from_slice(args_bytes).unwrap();
                 Key::GlobalHash(hash)
@@ -349,6 +367,7 @@ impl Key {
             Key::Network => KeyType::Network,
             Key::LastHeader => KeyType::LastHeader,
             Key::TotalCapacity => KeyType::TotalCapacity,
+            Key::ExplorerStartNumber => KeyType::ExplorerStartNumber,
             Key::GlobalHash(..) => KeyType::GlobalHash,
             Key::TxMap(..) => KeyType::TxMap,
             Key::RecentHeader(..) => KeyType::RecentHeader,
@@ -360,6 +379,7 @@ impl Key {
             Key::LockTotalCapacityIndex(..) => KeyType::LockTotalCapacityIndex,
             Key::LockLiveCellIndexPrefix(..) => KeyType::LockLiveCellIndex,
             Key::LockLiveCellIndex(..) => KeyType::LockLiveCellIndex,
+            Key::LockTxPrefix(..) => KeyType::LockTx,
             Key::LockTx(..) => KeyType::LockTx,
             Key::TypeLiveCellIndexPrefix(..) => KeyType::TypeLiveCellIndex,
             Key::TypeLiveCellIndex(..) => KeyType::TypeLiveCellIndex,
@@ -386,6 +406,12 @@ impl Key {
     pub(crate) fn pair_total_capacity(value: &u128) -> (Vec<u8>, Vec<u8>) {
         (Key::TotalCapacity.to_bytes(), value.to_le_bytes().to_vec())
     }
+    pub(crate) fn pair_explorer_start_number(value: u64) -> (Vec<u8>, Vec<u8>) {
+        (
+            Key::ExplorerStartNumber.to_bytes(),
+            value.to_le_bytes().to_vec(),
+        )
+    }
 
     pub(crate) fn pair_global_hash(hash: H256, value: HashType) -> (Vec<u8>, Vec<u8>) {
         (Key::GlobalHash(hash).to_bytes(), vec![value as u8])
@@ -458,13 +484,10 @@ impl Key {
             value.as_slice().to_vec(),
         )
     }
-    pub(crate) fn pair_lock_tx(
-        (lock_hash, number, tx_index): (H256, u64, u32),
-        value: &H256,
-    ) -> (Vec<u8>, Vec<u8>) {
+    pub(crate) fn pair_lock_tx(lock_hash: H256, value: &LockTxInfo) -> (Vec<u8>, Vec<u8>) {
         (
-            Key::LockTx(lock_hash, number, tx_index).to_bytes(),
-            value.as_bytes().to_vec(),
+            Key::LockTx(lock_hash, value.block_number, value.tx_index).to_bytes(),
+            bincode::serialize(value).unwrap(),
         )
     }
 
@@ -15,7 +15,7 @@ use rocksdb::{ColumnFamily, DB};
 
 use crate::{KVReader, KVTxn, RocksReader, RocksTxn};
 pub use key::{Key, KeyMetrics, KeyType};
-pub use types::{CellIndex, HashType, LiveCellInfo, TxInfo};
+pub use types::{CellIndex, HashType, LiveCellInfo, LockTxInfo, TxInfo};
 
 use types::BlockDeltaInfo;
 
@@ -31,6 +31,8 @@ pub struct IndexDatabase<'a> {
     init_block_buf: Vec<BlockView>,
     // Disable record tx info by default
     enable_explorer: bool,
+    // The lock tx records are complete since this block
+    explorer_start_number: Option<u64>,
 }
 
 impl<'a> IndexDatabase<'a> {
@@ -85,6 +87,13 @@ impl<'a> IndexDatabase<'a> {
         let last_header = RocksReader::new(db, cf)
             .get(&Key::LastHeader.to_bytes())
             .map(|bytes| Header::new_unchecked(bytes.into()).into_view());
+        let explorer_start_number = RocksReader::new(db, cf)
+            .get(&Key::ExplorerStartNumber.to_bytes())
+            .map(|bytes| {
+                let mut data = [0u8; 8];
+                data.copy_from_slice(&bytes[..8]);
+                u64::from_le_bytes(data)
+            });
         Ok(IndexDatabase {
             db,
             cf,
@@ -94,6 +103,7 @@ impl<'a> IndexDatabase<'a> {
             tip_header: genesis_header,
             init_block_buf: Vec::new(),
             enable_explorer,
+            explorer_start_number,
         })
     }
 
@@ -125,6 +135,11 @@ impl<'a> IndexDatabase<'a> {
                 };
                 let mut txn = RocksTxn::new(self.db, self.cf);
                 last_block_delta.rollback(&mut txn);
+                if self.explorer_start_number == Some(last_block_delta.number()) {
+                    // Set again when the block of the new fork is applied
+                    txn.remove_ok(Key::ExplorerStartNumber.to_bytes());
+                    self.explorer_start_number = None;
+                }
                 txn.commit();
                 self.last_header = last_block_delta.parent_header();
                 return Ok(());
@@ -170,12 +185,23 @@ impl<'a> IndexDatabase<'a> {
         self.last_number().map(|number| number + 1)
     }
 
+    // The transaction history of locks is only complete since this block, None means the
+    // history is not recorded or some blocks are indexed without explorer enabled.
+    pub fn explorer_start_number(&self) -> Option<u64> {
+        self.explorer_start_number
+    }
+
     fn get_address_inner(&self, reader: &RocksReader, lock_hash: Byte32) -> Option<AddressPayload> {
         reader
             .get(&Key::LockScript(lock_hash.unpack()).to_bytes())
             .map(|bytes| AddressPayload::from(Script::new_unchecked(bytes.into())))
     }
 
+    pub fn get_address(&self, lock_hash: Byte32) -> Option<AddressPayload> {
+        let reader = RocksReader::new(self.db, self.cf);
+        self.get_address_inner(&reader, lock_hash)
+    }
+
     pub fn get_capacity(&self, lock_hash: Byte32) -> Option<u64> {
         let reader = RocksReader::new(self.db, self.cf);
         reader
@@ -263,6 +289,44 @@ impl<'a> IndexDatabase<'a> {
         infos
     }
 
+    // Query transactions related to the lock (only recorded when explorer enabled), the block
+    // range [from_number, to_number] is inclusive. The records are ordered by
+    // (block_number, tx_index), `after` is the exclusive cursor of last page.
+    pub fn get_transactions_by_lock(
+        &self,
+        lock_hash: Byte32,
+        after: Option<(u64, u32)>,
+        from_number: Option<u64>,
+        to_number: Option<u64>,
+        limit: usize,
+    ) -> Vec<LockTxInfo> {
+        let reader = RocksReader::new(self.db, self.cf);
+        let key_prefix = Key::LockTxPrefix(lock_hash.unpack(), None).to_bytes();
+        let key_start = match after {
+            Some((number, tx_index)) if Some(number) >= from_number => {
+                Key::LockTx(lock_hash.unpack(), number, tx_index).to_bytes()
+            }
+            _ => Key::LockTxPrefix(lock_hash.unpack(), from_number).to_bytes(),
+        };
+
+        let mut infos = Vec::new();
+        for (key_bytes, value_bytes) in reader.iter_from(&key_start) {
+            if key_bytes[..key_prefix.len()] != key_prefix[..] {
+                log::debug!("Reach the end of this lock");
+                break;
+            }
+            let info: LockTxInfo = bincode::deserialize(&value_bytes).unwrap();
+            if after == Some((info.block_number, info.tx_index)) {
+                continue;
+            }
+            if infos.len() >= limit || to_number.filter(|n| info.block_number > *n).is_some() {
+                break;
+            }
+            infos.push(info);
+        }
+        infos
+    }
+
     pub fn get_top_n(&self, n: usize) -> Vec<(Byte32, Option<AddressPayload>, u64)> {
         let reader = RocksReader::new(self.db, self.cf);
         let key_prefix: Vec<u8> = KeyType::LockTotalCapacityIndex.to_bytes();
@@ -313,6 +377,14 @@ impl<'a> IndexDatabase<'a> {
             let block_delta_info = BlockDeltaInfo::from_block(&block, &txn, clear_old);
             let number = block_delta_info.number();
             let hash = block_delta_info.hash();
+            if self.enable_explorer && self.explorer_start_number.is_none() {
+                txn.put_pair(Key::pair_explorer_start_number(number));
+                self.explorer_start_number = Some(number);
+            } else if !self.enable_explorer && self.explorer_start_number.is_some() {
+                // The history of this block is not recorded, the recorded history is incomplete
+                txn.remove_ok(Key::ExplorerStartNumber.to_bytes());
+                self.explorer_start_number = None;
+            }
             let result = block_delta_info.apply(&mut txn, self.enable_explorer);
             log::info!(
                 "Block: {} => {:x} (chain_capacity={}, delta={}), txs={}, cell-removed={}, cell-added={}",
@@ -350,7 +422,12 @@ impl<'a> IndexDatabase<'a> {
                 KeyType::CodeLiveCellIndex,
             ];
             if self.enable_explorer {
-                types.extend(vec![KeyType::TxMap, KeyType::LockTx, KeyType::GlobalHash]);
+                types.extend(vec![
+                    KeyType::ExplorerStartNumber,
+                    KeyType::TxMap,
+                    KeyType::LockTx,
+                    KeyType::GlobalHash,
+                ]);
             }
             for key_type in types {
                 key_types.insert(key_type, KeyMetrics::default());
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use ckb_types::{
     bytes::Bytes,
@@ -278,6 +278,9 @@ impl BlockDeltaInfo {
         for tx in &self.txs {
             if enable_explorer {
                 txn.put_pair(Key::pair_tx_map(tx.tx_hash.clone(), &tx.to_thin()));
+                for (lock_hash, lock_tx_info) in tx.lock_txs() {
+                    txn.put_pair(Key::pair_lock_tx(lock_hash, &lock_tx_info));
+                }
             }
 
             for LiveCellInfo {
@@ -291,12 +294,6 @@ impl BlockDeltaInfo {
             } in &tx.inputs
             {
                 let out_point = OutPoint::new(tx_hash.pack(), *output_index);
-                if enable_explorer {
-                    txn.put_pair(Key::pair_lock_tx(
-                        (lock_hash.clone(), *number, index.tx_index),
-                        &tx.tx_hash,
-                    ));
-                }
                 txn.remove(Key::LiveCellMap(out_point.clone()).to_bytes());
                 txn.remove(Key::LiveCellIndex(*number, *index).to_bytes());
                 txn.remove(Key::LockLiveCellIndex(lock_hash.clone(), *number, *index).to_bytes());
@@ -321,12 +318,6 @@ impl BlockDeltaInfo {
                     ..
                 } = live_cell_info;
                 let out_point = OutPoint::new(tx_hash.pack(), *output_index);
-                if enable_explorer {
-                    txn.put_pair(Key::pair_lock_tx(
-                        (lock_hash.clone(), *number, index.tx_index),
-                        &tx.tx_hash,
-                    ));
-                }
                 txn.put_pair(Key::pair_live_cell_map(out_point.clone(), live_cell_info));
                 txn.put_pair(Key::pair_live_cell_index((*number, *index), &out_point));
                 txn.put_pair(Key::pair_lock_live_cell_index(
@@ -420,9 +411,14 @@ impl BlockDeltaInfo {
     pub(crate) fn rollback<'r, T: KVTxn<'r>>(&self, txn: &mut T) {
         log::debug!("rollback block: {:?}", self);
 
-        let mut delete_lock_txs: HashSet<(H256, u64, u32)> = HashSet::default();
         for tx in &self.txs {
             txn.remove_ok(Key::TxMap(tx.tx_hash.clone()).to_bytes());
+            for (lock_hash, lock_tx_info) in tx.lock_txs() {
+                txn.remove_ok(
+                    Key::LockTx(lock_hash, lock_tx_info.block_number, lock_tx_info.tx_index)
+                        .to_bytes(),
+                );
+            }
             for live_cell_info in &tx.inputs {
                 let LiveCellInfo {
                     tx_hash,
@@ -434,7 +430,6 @@ impl BlockDeltaInfo {
                     ..
                 } = live_cell_info;
                 let out_point = OutPoint::new(tx_hash.pack(), *output_index);
-                delete_lock_txs.insert((lock_hash.clone(), *number, index.tx_index));
                 txn.put_pair(Key::pair_live_cell_map(out_point.clone(), live_cell_info));
                 txn.put_pair(Key::pair_live_cell_index((*number, *index), &out_point));
                 txn.put_pair(Key::pair_lock_live_cell_index(
@@ -464,7 +459,6 @@ impl BlockDeltaInfo {
                     ..
                 } = live_cell_info;
                 let out_point = OutPoint::new(tx_hash.pack(), *output_index);
-                delete_lock_txs.insert((lock_hash.clone(), *number, index.tx_index));
                 txn.remove(Key::LiveCellMap(out_point.clone()).to_bytes());
                 txn.remove(Key::LiveCellIndex(*number, *index).to_bytes());
                 txn.remove(Key::LockLiveCellIndex(lock_hash.clone(), *number, *index).to_bytes());
@@ -478,10 +472,6 @@ impl BlockDeltaInfo {
                 }
             }
         }
-        for (lock_hash, number, tx_index) in delete_lock_txs {
-            txn.remove_ok(Key::LockTx(lock_hash, number, tx_index).to_bytes());
-        }
-
         for (lock_hash, info) in &self.locks {
             let LockInfo {
                 old_total_capacity,
@@ -603,6 +593,16 @@ impl LiveCellInfo {
     }
 }
 
+impl ckb_sdk::Coin for LiveCellInfo {
+    fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    fn is_plain(&self) -> bool {
+        self.type_hashes.is_none() && self.data_bytes == 0
+    }
+}
+
 // LiveCell index in a block
 #[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct CellIndex {
@@ -682,6 +682,31 @@ pub(crate) struct RichTxInfo {
 }
 
 impl RichTxInfo {
+    // Group the input and output capacity by lock hash
+    pub(crate) fn lock_txs(&self) -> HashMap<H256, LockTxInfo> {
+        let new_lock_tx = || LockTxInfo {
+            tx_hash: self.tx_hash.clone(),
+            block_number: self.block_number,
+            tx_index: self.tx_index,
+            input_capacity: 0,
+            output_capacity: 0,
+        };
+        let mut lock_txs: HashMap<H256, LockTxInfo> = HashMap::default();
+        for info in &self.inputs {
+            lock_txs
+                .entry(info.lock_hash.clone())
+                .or_insert_with(new_lock_tx)
+                .input_capacity += info.capacity;
+        }
+        for info in &self.outputs {
+            lock_txs
+                .entry(info.lock_hash.clone())
+                .or_insert_with(new_lock_tx)
+                .output_capacity += info.capacity;
+        }
+        lock_txs
+    }
+
     pub(crate) fn to_thin(&self) -> TxInfo {
         TxInfo {
             tx_hash: self.tx_hash.clone(),
@@ -721,9 +746,84 @@ impl TxInfo {
             .collect::<Vec<_>>()
     }
     pub fn outputs(&self) -> Vec<OutPoint> {
-        self.inputs
+        self.outputs
             .iter()
             .map(|data| OutPoint::new_unchecked(data.clone()))
             .collect::<Vec<_>>()
     }
 }
+
+// The capacity changes of a lock in a transaction
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct LockTxInfo {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    // Transaction index in target block
+    pub tx_index: u32,
+    // Total capacity of the input cells with this lock
+    pub input_capacity: u64,
+    // Total capacity of the output cells with this lock
+    pub output_capacity: u64,
+}
+
+impl LockTxInfo {
+    pub fn capacity_delta(&self) -> i128 {
+        i128::from(self.output_capacity) - i128::from(self.input_capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_sdk::{select_coins, CoinSelection, SelectionTarget};
+
+    fn live_cell(output_index: u32, capacity: u64, data_bytes: u64, typed: bool) -> LiveCellInfo {
+        LiveCellInfo {
+            tx_hash: H256::default(),
+            output_index,
+            data_bytes,
+            lock_hash: H256::default(),
+            type_hashes: if typed {
+                Some((H256::default(), H256::default()))
+            } else {
+                None
+            },
+            capacity,
+            number: 1,
+            index: CellIndex::new(0, output_index),
+        }
+    }
+
+    #[test]
+    fn test_select_live_cells() {
+        let cells = vec![
+            live_cell(0, 500, 0, false),
+            // Large cells with data or type script must not be spent as capacity
+            live_cell(1, 2000, 8, false),
+            live_cell(2, 3000, 0, true),
+            live_cell(3, 300, 0, false),
+            live_cell(4, 1000, 0, false),
+        ];
+        let target = SelectionTarget::new(1200, 61, 0);
+        let select = |strategy: CoinSelection| {
+            select_coins(strategy.selector().as_ref(), cells.clone(), &target).map(|cells| {
+                cells
+                    .iter()
+                    .map(|cell| cell.output_index)
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(select(CoinSelection::LargestFirst), Some(vec![4, 0]));
+        assert_eq!(select(CoinSelection::SmallestFirst), Some(vec![3, 0, 4]));
+        assert_eq!(select(CoinSelection::BranchAndBound), None);
+        assert_eq!(select(CoinSelection::MinimizeInputs), Some(vec![4, 3]));
+
+        let target = SelectionTarget::new(2500, 61, 0);
+        assert!(select_coins(
+            CoinSelection::LargestFirst.selector().as_ref(),
+            cells,
+            &target
+        )
+        .is_none());
+    }
+}
@@ -6,7 +6,7 @@ mod util;
 pub use error::Error;
 pub use index::{
     CellIndex, HashType, IndexDatabase, IndexError, Key as IndexKey, KeyMetrics as IndexKeyMetrics,
-    KeyType as IndexKeyType, LiveCellInfo, TxInfo,
+    KeyType as IndexKeyType, LiveCellInfo, LockTxInfo, TxInfo,
 };
 pub use kvdb::{KVReader, KVTxn, RocksReader, RocksTxn};
 pub use util::{with_index_db, with_rocksdb};
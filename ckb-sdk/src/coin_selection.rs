@@ -0,0 +1,425 @@
+use crate::constants::{MIN_SECP_CELL_CAPACITY, ONE_CKB};
+use std::fmt;
+use std::str::FromStr;
+
+// Max tries of branch-and-bound search
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// A live cell can be selected as transaction input
+pub trait Coin {
+    fn capacity(&self) -> u64;
+
+    /// Only the cells without type script and data are selected
+    fn is_plain(&self) -> bool {
+        true
+    }
+}
+
+impl Coin for u64 {
+    fn capacity(&self) -> u64 {
+        *self
+    }
+}
+
+/// The capacity to collect by coin selection
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SelectionTarget {
+    /// Total capacity of outputs and transaction fee
+    pub capacity: u64,
+    /// The change cell must be able to hold at least this capacity
+    pub min_change: u64,
+    /// The rest capacity not larger than this value will be paid as transaction fee (no change cell)
+    pub max_dust: u64,
+}
+
+impl SelectionTarget {
+    pub fn new(capacity: u64, min_change: u64, max_dust: u64) -> SelectionTarget {
+        SelectionTarget {
+            capacity,
+            min_change,
+            max_dust,
+        }
+    }
+
+    /// The target of transferring `capacity` with `tx_fee`, the change must hold a secp cell,
+    /// otherwise the rest is paid as fee while the total fee is less than 1 CKB.
+    pub fn with_fee(capacity: u64, tx_fee: u64) -> SelectionTarget {
+        SelectionTarget::new(
+            capacity.saturating_add(tx_fee),
+            MIN_SECP_CELL_CAPACITY,
+            ONE_CKB.saturating_sub(tx_fee.saturating_add(1)),
+        )
+    }
+
+    pub fn is_satisfied(&self, total: u64) -> bool {
+        total >= self.capacity && {
+            let rest = total - self.capacity;
+            rest <= self.max_dust || rest >= self.min_change
+        }
+    }
+}
+
+/// Select coins by their capacities, return the indexes of selected coins.
+///
+/// Implementations must be deterministic: the same input always gives the same result.
+pub trait CoinSelector {
+    fn select(&self, capacities: &[u64], target: &SelectionTarget) -> Option<Vec<usize>>;
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CoinSelection {
+    LargestFirst,
+    SmallestFirst,
+    BranchAndBound,
+    MinimizeInputs,
+}
+
+impl CoinSelection {
+    pub fn selector(self) -> Box<dyn CoinSelector> {
+        match self {
+            CoinSelection::LargestFirst => Box::new(LargestFirst),
+            CoinSelection::SmallestFirst => Box::new(SmallestFirst),
+            CoinSelection::BranchAndBound => Box::new(BranchAndBound),
+            CoinSelection::MinimizeInputs => Box::new(MinimizeInputs),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CoinSelection::LargestFirst => "largest-first",
+            CoinSelection::SmallestFirst => "smallest-first",
+            CoinSelection::BranchAndBound => "branch-and-bound",
+            CoinSelection::MinimizeInputs => "minimize-inputs",
+        }
+    }
+}
+
+impl FromStr for CoinSelection {
+    type Err = String;
+    fn from_str(input: &str) -> Result<CoinSelection, String> {
+        match input {
+            "largest-first" => Ok(CoinSelection::LargestFirst),
+            "smallest-first" => Ok(CoinSelection::SmallestFirst),
+            "branch-and-bound" => Ok(CoinSelection::BranchAndBound),
+            "minimize-inputs" => Ok(CoinSelection::MinimizeInputs),
+            _ => Err(format!("Invalid coin selection strategy: {}", input)),
+        }
+    }
+}
+
+impl fmt::Display for CoinSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Select coins by the selector, the selected coins are returned in selected order. The coins
+/// not plain (with type script or data) are skipped.
+pub fn select_coins<C: Coin>(
+    selector: &dyn CoinSelector,
+    coins: Vec<C>,
+    target: &SelectionTarget,
+) -> Option<Vec<C>> {
+    let coins = coins
+        .into_iter()
+        .filter(|coin| coin.is_plain())
+        .collect::<Vec<_>>();
+    let capacities = coins.iter().map(Coin::capacity).collect::<Vec<_>>();
+    let idxs = selector.select(&capacities, target)?;
+    let mut coins = coins.into_iter().map(Some).collect::<Vec<_>>();
+    idxs.into_iter()
+        .map(|idx| coins.get_mut(idx).and_then(Option::take))
+        .collect()
+}
+
+// Sort the indexes by capacity, the index is used to break ties
+fn sorted_indexes(capacities: &[u64], descending: bool) -> Vec<usize> {
+    let mut idxs = (0..capacities.len()).collect::<Vec<_>>();
+    if descending {
+        idxs.sort_by(|a, b| capacities[*b].cmp(&capacities[*a]).then(a.cmp(b)));
+    } else {
+        idxs.sort_by(|a, b| capacities[*a].cmp(&capacities[*b]).then(a.cmp(b)));
+    }
+    idxs
+}
+
+fn accumulate(
+    capacities: &[u64],
+    idxs: Vec<usize>,
+    target: &SelectionTarget,
+) -> Option<Vec<usize>> {
+    let mut total: u64 = 0;
+    let mut selected = Vec::new();
+    for idx in idxs {
+        total = total.saturating_add(capacities[idx]);
+        selected.push(idx);
+        if target.is_satisfied(total) {
+            return Some(selected);
+        }
+    }
+    None
+}
+
+/// Spend the largest cells first, result in less inputs and more change
+pub struct LargestFirst;
+
+impl CoinSelector for LargestFirst {
+    fn select(&self, capacities: &[u64], target: &SelectionTarget) -> Option<Vec<usize>> {
+        accumulate(capacities, sorted_indexes(capacities, true), target)
+    }
+}
+
+/// Spend the smallest cells first, consolidate small cells while transfer
+pub struct SmallestFirst;
+
+impl CoinSelector for SmallestFirst {
+    fn select(&self, capacities: &[u64], target: &SelectionTarget) -> Option<Vec<usize>> {
+        accumulate(capacities, sorted_indexes(capacities, false), target)
+    }
+}
+
+/// Search the cells match target capacity exactly (the rest is not larger than
+/// `max_dust`), so no change cell is created. The match with least rest capacity
+/// is returned, then the one with less inputs.
+pub struct BranchAndBound;
+
+impl CoinSelector for BranchAndBound {
+    fn select(&self, capacities: &[u64], target: &SelectionTarget) -> Option<Vec<usize>> {
+        struct Search<'a> {
+            capacities: &'a [u64],
+            idxs: Vec<usize>,
+            // remains[i] is the total capacity of idxs[i..]
+            remains: Vec<u64>,
+            lower: u64,
+            upper: u64,
+            tries: usize,
+            current: Vec<usize>,
+            best: Option<(u64, Vec<usize>)>,
+        }
+
+        impl<'a> Search<'a> {
+            fn search(&mut self, depth: usize, total: u64) {
+                if self.tries >= BNB_MAX_TRIES {
+                    return;
+                }
+                self.tries += 1;
+                if total > self.upper {
+                    return;
+                }
+                if total >= self.lower {
+                    let better = match self.best {
+                        Some((best_total, ref best)) => {
+                            total < best_total
+                                || (total == best_total && self.current.len() < best.len())
+                        }
+                        None => true,
+                    };
+                    if better {
+                        self.best = Some((total, self.current.clone()));
+                    }
+                    return;
+                }
+                if depth >= self.idxs.len()
+                    || total.saturating_add(self.remains[depth]) < self.lower
+                {
+                    return;
+                }
+                // Include current cell first, then exclude it
+                let idx = self.idxs[depth];
+                self.current.push(idx);
+                self.search(depth + 1, total.saturating_add(self.capacities[idx]));
+                self.current.pop();
+                self.search(depth + 1, total);
+            }
+        }
+
+        let idxs = sorted_indexes(capacities, true);
+        let mut remains = vec![0u64; idxs.len() + 1];
+        for (i, idx) in idxs.iter().enumerate().rev() {
+            remains[i] = remains[i + 1].saturating_add(capacities[*idx]);
+        }
+        let mut search = Search {
+            capacities,
+            idxs,
+            remains,
+            lower: target.capacity,
+            upper: target.capacity.saturating_add(target.max_dust),
+            tries: 0,
+            current: Vec::new(),
+            best: None,
+        };
+        search.search(0, 0);
+        search.best.map(|(_, selected)| selected)
+    }
+}
+
+/// Use as few inputs as possible, and among them prefer the smaller cells
+pub struct MinimizeInputs;
+
+impl CoinSelector for MinimizeInputs {
+    fn select(&self, capacities: &[u64], target: &SelectionTarget) -> Option<Vec<usize>> {
+        let idxs = sorted_indexes(capacities, true);
+        // prefix[i] is the total capacity of the largest i cells
+        let mut prefix = vec![0u64; idxs.len() + 1];
+        for (i, idx) in idxs.iter().enumerate() {
+            prefix[i + 1] = prefix[i].saturating_add(capacities[*idx]);
+        }
+        let min_count = (1..=idxs.len()).find(|count| prefix[*count] >= target.capacity)?;
+
+        // Only try the minimal input count and one more, otherwise fallback to largest-first
+        for count in min_count..=std::cmp::min(min_count + 1, idxs.len()) {
+            let mut selected = Vec::with_capacity(count);
+            let mut need = target.capacity;
+            let mut start = 0;
+            for slot in 0..count {
+                let rest_slots = count - slot - 1;
+                // Pick the smallest cell that the rest slots (filled by the largest cells
+                // after it) can still cover the needed capacity.
+                let pos = (start..idxs.len() - rest_slots)
+                    .rev()
+                    .find(|pos| {
+                        let rest_max = prefix[pos + 1 + rest_slots] - prefix[pos + 1];
+                        capacities[idxs[*pos]].saturating_add(rest_max) >= need
+                    })
+                    .unwrap_or(start);
+                selected.push(idxs[pos]);
+                need = need.saturating_sub(capacities[idxs[pos]]);
+                start = pos + 1;
+            }
+            let total = selected
+                .iter()
+                .fold(0u64, |total, idx| total.saturating_add(capacities[*idx]));
+            if target.is_satisfied(total) {
+                return Some(selected);
+            }
+        }
+        LargestFirst.select(capacities, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_CKB: u64 = 100_000_000;
+
+    fn select(strategy: CoinSelection, capacities: &[u64], target: &SelectionTarget) -> Vec<u64> {
+        select_coins(strategy.selector().as_ref(), capacities.to_vec(), target).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_parse_coin_selection() {
+        for strategy in &[
+            CoinSelection::LargestFirst,
+            CoinSelection::SmallestFirst,
+            CoinSelection::BranchAndBound,
+            CoinSelection::MinimizeInputs,
+        ] {
+            assert_eq!(
+                CoinSelection::from_str(&strategy.to_string()).unwrap(),
+                *strategy
+            );
+        }
+        assert!(CoinSelection::from_str("random").is_err());
+    }
+
+    #[test]
+    fn test_largest_and_smallest_first() {
+        let capacities = [100, 500, 300, 61, 300, 1000];
+        let target = SelectionTarget::new(550, 61, 0);
+        assert_eq!(
+            select(CoinSelection::LargestFirst, &capacities, &target),
+            vec![1000]
+        );
+        assert_eq!(
+            select(CoinSelection::SmallestFirst, &capacities, &target),
+            vec![61, 100, 300, 300]
+        );
+
+        // The rest capacity can not hold a change cell
+        let target = SelectionTarget::new(950, 61, 0);
+        assert_eq!(
+            select(CoinSelection::LargestFirst, &capacities, &target),
+            vec![1000, 500]
+        );
+
+        let target = SelectionTarget::new(3000, 61, 0);
+        assert!(select(CoinSelection::LargestFirst, &capacities, &target).is_empty());
+        assert!(select(CoinSelection::SmallestFirst, &capacities, &target).is_empty());
+    }
+
+    #[test]
+    fn test_branch_and_bound() {
+        let capacities = [100, 500, 300, 61, 300, 1000];
+        let target = SelectionTarget::new(661, 61, 0);
+        assert_eq!(
+            select(CoinSelection::BranchAndBound, &capacities, &target),
+            vec![500, 100, 61]
+        );
+
+        let target = SelectionTarget::new(1600 * ONE_CKB, 61 * ONE_CKB, ONE_CKB / 2);
+        let capacities = [
+            1000 * ONE_CKB,
+            200 * ONE_CKB,
+            400 * ONE_CKB + ONE_CKB / 4,
+            999 * ONE_CKB,
+            601 * ONE_CKB,
+        ];
+        assert_eq!(
+            select(CoinSelection::BranchAndBound, &capacities, &target),
+            vec![999 * ONE_CKB, 601 * ONE_CKB]
+        );
+
+        // No exact match
+        let target = SelectionTarget::new(750, 61, 10);
+        assert!(select(CoinSelection::BranchAndBound, &[100, 500, 300], &target).is_empty());
+    }
+
+    #[test]
+    fn test_minimize_inputs() {
+        let capacities = [100, 500, 300, 61, 300, 1000, 700];
+        let target = SelectionTarget::new(550, 61, 0);
+        assert_eq!(
+            select(CoinSelection::MinimizeInputs, &capacities, &target),
+            vec![700]
+        );
+
+        let target = SelectionTarget::new(1200, 61, 0);
+        assert_eq!(
+            select(CoinSelection::MinimizeInputs, &capacities, &target),
+            vec![700, 500]
+        );
+
+        let target = SelectionTarget::new(5000, 61, 0);
+        assert!(select(CoinSelection::MinimizeInputs, &capacities, &target).is_empty());
+    }
+
+    #[test]
+    fn test_target_with_fee() {
+        let target = SelectionTarget::with_fee(1000 * ONE_CKB, ONE_CKB / 10);
+        assert_eq!(target.capacity, 1000 * ONE_CKB + ONE_CKB / 10);
+        assert_eq!(target.min_change, MIN_SECP_CELL_CAPACITY);
+        // The total fee is less than 1 CKB
+        assert!(target.is_satisfied(target.capacity + target.max_dust));
+        assert!(!target.is_satisfied(1001 * ONE_CKB));
+        assert!(target.is_satisfied(target.capacity + MIN_SECP_CELL_CAPACITY));
+
+        let target = SelectionTarget::with_fee(1000 * ONE_CKB, 2 * ONE_CKB);
+        assert_eq!(target.max_dust, 0);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let capacities = [300, 300, 300, 300, 600];
+        let target = SelectionTarget::new(600, 61, 0);
+        let idxs = BranchAndBound.select(&capacities, &target).unwrap();
+        assert_eq!(idxs, vec![4]);
+        for _ in 0..10 {
+            assert_eq!(BranchAndBound.select(&capacities, &target).unwrap(), idxs);
+            assert_eq!(
+                SmallestFirst.select(&capacities, &target).unwrap(),
+                vec![0, 1]
+            );
+        }
+    }
+}
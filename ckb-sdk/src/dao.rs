@@ -0,0 +1,251 @@
+use ckb_dao_utils::extract_dao_data;
+use ckb_types::{
+    core::{Capacity, EpochNumber, EpochNumberWithFraction, HeaderView, ScriptHashType},
+    packed::CellOutput,
+    prelude::*,
+};
+
+use crate::constants::DAO_TYPE_HASH;
+use crate::{Since, SinceType};
+use std::convert::TryFrom;
+
+/// Whether the output is a NervosDAO cell (deposited or prepared)
+pub fn is_dao_cell(output: &CellOutput) -> bool {
+    output
+        .type_()
+        .to_opt()
+        .map(|script| {
+            script.code_hash() == DAO_TYPE_HASH.pack()
+                && script.hash_type() == ScriptHashType::Type.into()
+        })
+        .unwrap_or(false)
+}
+
+/// Whether the `target` epoch is reached at `current` epoch
+pub fn is_epoch_reached(current: EpochNumberWithFraction, target: EpochNumberWithFraction) -> bool {
+    if current.number() != target.number() {
+        return current.number() > target.number();
+    }
+    // Compare the fractions index / length
+    current.index() * target.length() >= target.index() * current.length()
+}
+
+/// The maximum capacity can be withdrawn from a deposited output. The headers and the output
+/// may come from a transaction file, so the malformed ones are reported as errors.
+pub fn calculate_dao_maximum_withdraw4(
+    deposit_header: &HeaderView,
+    prepare_header: &HeaderView,
+    output: &CellOutput,
+    occupied_capacity: u64,
+) -> Result<u64, String> {
+    let (deposit_ar, _, _, _) = extract_dao_data(deposit_header.dao())
+        .map_err(|err| format!("Invalid dao field of deposit header: {}", err))?;
+    let (prepare_ar, _, _, _) = extract_dao_data(prepare_header.dao())
+        .map_err(|err| format!("Invalid dao field of prepare header: {}", err))?;
+    if deposit_ar == 0 {
+        return Err("Invalid dao field of deposit header: zero accumulate rate".to_string());
+    }
+    let output_capacity: Capacity = output.capacity().unpack();
+    let counted_capacity = output_capacity
+        .as_u64()
+        .checked_sub(occupied_capacity)
+        .ok_or_else(|| {
+            format!(
+                "Output capacity {} is less than its occupied capacity {}",
+                output_capacity, occupied_capacity
+            )
+        })?;
+    let withdraw_counted_capacity =
+        u128::from(counted_capacity) * u128::from(prepare_ar) / u128::from(deposit_ar);
+    u64::try_from(withdraw_counted_capacity)
+        .ok()
+        .and_then(|capacity| capacity.checked_add(occupied_capacity))
+        .ok_or_else(|| "Maximum withdraw capacity overflow".to_string())
+}
+
+/// The later one of two absolute epoch since values, 0 means no since
+pub fn later_epoch_since(a: u64, b: u64) -> Result<u64, String> {
+    if a == 0 || b == 0 {
+        return Ok(a.max(b));
+    }
+    let to_epoch = |since: u64| match Since::from_raw_value(since).extract_metric() {
+        Some((SinceType::EpochNumberWithFraction, value)) => {
+            Ok(EpochNumberWithFraction::from_full_value(value))
+        }
+        _ => Err(format!("Invalid epoch since: {:#x}", since)),
+    };
+    if is_epoch_reached(to_epoch(a)?, to_epoch(b)?) {
+        Ok(a)
+    } else {
+        Ok(b)
+    }
+}
+
+/// The earliest epoch a prepared cell can be withdrawn
+pub fn minimal_unlock_point(
+    deposit_header: &HeaderView,
+    prepare_header: &HeaderView,
+) -> EpochNumberWithFraction {
+    const LOCK_PERIOD_EPOCHES: EpochNumber = 180;
+
+    // https://github.com/nervosnetwork/ckb-system-scripts/blob/master/c/dao.c#L182-L223
+    let deposit_point = deposit_header.epoch();
+    let prepare_point = prepare_header.epoch();
+    let prepare_fraction = prepare_point.index() * deposit_point.length();
+    let deposit_fraction = deposit_point.index() * prepare_point.length();
+    let passed_epoch_cnt = if prepare_fraction > deposit_fraction {
+        prepare_point.number() - deposit_point.number() + 1
+    } else {
+        prepare_point.number() - deposit_point.number()
+    };
+    let rest_epoch_cnt =
+        (passed_epoch_cnt + (LOCK_PERIOD_EPOCHES - 1)) / LOCK_PERIOD_EPOCHES * LOCK_PERIOD_EPOCHES;
+    EpochNumberWithFraction::new(
+        deposit_point.number() + rest_epoch_cnt,
+        deposit_point.index(),
+        deposit_point.length(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SinceType;
+    use ckb_dao_utils::pack_dao_data;
+    use ckb_types::{
+        bytes::Bytes,
+        core::{capacity_bytes, HeaderBuilder},
+    };
+
+    #[test]
+    fn test_minimal_unlock_point() {
+        let cases = vec![
+            ((5, 5, 1000), (184, 4, 1000), (5 + 180, 5, 1000)),
+            ((5, 5, 1000), (184, 5, 1000), (5 + 180, 5, 1000)),
+            ((5, 5, 1000), (184, 6, 1000), (5 + 180, 5, 1000)),
+            ((5, 5, 1000), (185, 4, 1000), (5 + 180, 5, 1000)),
+            ((5, 5, 1000), (185, 5, 1000), (5 + 180, 5, 1000)),
+            ((5, 5, 1000), (185, 6, 1000), (5 + 180 * 2, 5, 1000)), // 6/1000 > 5/1000
+            ((5, 5, 1000), (186, 4, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (186, 5, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (186, 6, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (364, 4, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (364, 5, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (364, 6, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (365, 4, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (365, 5, 1000), (5 + 180 * 2, 5, 1000)),
+            ((5, 5, 1000), (365, 6, 1000), (5 + 180 * 3, 5, 1000)),
+            ((5, 5, 1000), (366, 4, 1000), (5 + 180 * 3, 5, 1000)),
+            ((5, 5, 1000), (366, 5, 1000), (5 + 180 * 3, 5, 1000)),
+            ((5, 5, 1000), (366, 6, 1000), (5 + 180 * 3, 5, 1000)),
+        ];
+        for (deposit_point, prepare_point, expected) in cases {
+            let deposit_point =
+                EpochNumberWithFraction::new(deposit_point.0, deposit_point.1, deposit_point.2);
+            let prepare_point =
+                EpochNumberWithFraction::new(prepare_point.0, prepare_point.1, prepare_point.2);
+            let expected = EpochNumberWithFraction::new(expected.0, expected.1, expected.2);
+            let deposit_header = HeaderBuilder::default()
+                .epoch(deposit_point.full_value().pack())
+                .build();
+            let prepare_header = HeaderBuilder::default()
+                .epoch(prepare_point.full_value().pack())
+                .build();
+            let actual = minimal_unlock_point(&deposit_header, &prepare_header);
+            assert_eq!(
+                expected, actual,
+                "minimal_unlock_point deposit_point: {}, prepare_point: {}, expected: {}, actual: {}",
+                deposit_point, prepare_point, expected, actual,
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_epoch_reached() {
+        let epoch = |number, index, length| EpochNumberWithFraction::new(number, index, length);
+        assert!(is_epoch_reached(epoch(185, 5, 1000), epoch(185, 5, 1000)));
+        assert!(is_epoch_reached(epoch(185, 3, 500), epoch(185, 5, 1000)));
+        assert!(is_epoch_reached(epoch(186, 0, 1000), epoch(185, 999, 1000)));
+        assert!(!is_epoch_reached(epoch(185, 4, 1000), epoch(185, 5, 1000)));
+        assert!(!is_epoch_reached(epoch(185, 2, 500), epoch(185, 5, 1000)));
+        assert!(!is_epoch_reached(
+            epoch(184, 999, 1000),
+            epoch(185, 0, 1000)
+        ));
+    }
+
+    #[test]
+    fn test_later_epoch_since() {
+        let since = |number, index, length| {
+            Since::new(
+                SinceType::EpochNumberWithFraction,
+                EpochNumberWithFraction::new(number, index, length).full_value(),
+                false,
+            )
+            .value()
+        };
+        assert_eq!(later_epoch_since(0, since(5, 1, 2)), Ok(since(5, 1, 2)));
+        assert_eq!(later_epoch_since(since(5, 1, 2), 0), Ok(since(5, 1, 2)));
+        assert_eq!(
+            later_epoch_since(since(5, 1, 2), since(5, 2, 3)),
+            Ok(since(5, 2, 3))
+        );
+        assert_eq!(
+            later_epoch_since(since(6, 0, 1800), since(5, 2, 3)),
+            Ok(since(6, 0, 1800))
+        );
+        let block_since = Since::new(SinceType::BlockNumber, 100, false).value();
+        assert!(later_epoch_since(block_since, since(5, 1, 2)).is_err());
+    }
+
+    #[test]
+    fn check_withdraw_calculation() {
+        let data = Bytes::from(vec![1; 10]);
+        let output = CellOutput::new_builder()
+            .capacity(capacity_bytes!(1000000).pack())
+            .build();
+        let deposit_header = HeaderBuilder::default()
+            .number(100.pack())
+            .dao(pack_dao_data(
+                10_000_000_000_123_456,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            ))
+            .build();
+        let prepare_header = HeaderBuilder::default()
+            .number(200.pack())
+            .dao(pack_dao_data(
+                10_000_000_001_123_456,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            ))
+            .build();
+
+        let result = calculate_dao_maximum_withdraw4(
+            &deposit_header,
+            &prepare_header,
+            &output,
+            Capacity::bytes(data.len()).unwrap().as_u64(),
+        );
+        assert_eq!(result, Ok(100_000_000_009_999));
+
+        // The occupied capacity is larger than the output capacity
+        assert!(calculate_dao_maximum_withdraw4(
+            &deposit_header,
+            &prepare_header,
+            &output,
+            capacity_bytes!(1000001).as_u64(),
+        )
+        .is_err());
+        // The dao field is not set
+        assert!(calculate_dao_maximum_withdraw4(
+            &HeaderBuilder::default().build(),
+            &prepare_header,
+            &output,
+            0,
+        )
+        .is_err());
+    }
+}
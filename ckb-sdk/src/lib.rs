@@ -1,4 +1,6 @@
 mod chain;
+mod coin_selection;
+mod dao;
 mod error;
 mod transaction;
 mod tx_helper;
@@ -9,13 +11,24 @@ pub mod rpc;
 pub mod wallet;
 
 pub use chain::{calc_max_mature_number, GenesisInfo};
+pub use coin_selection::{
+    select_coins, BranchAndBound, Coin, CoinSelection, CoinSelector, LargestFirst, MinimizeInputs,
+    SelectionTarget, SmallestFirst,
+};
+pub use dao::{
+    calculate_dao_maximum_withdraw4, is_dao_cell, is_epoch_reached, later_epoch_since,
+    minimal_unlock_point,
+};
 pub use error::Error;
 pub use rpc::HttpRpcClient;
 pub use transaction::{
     MockCellDep, MockInfo, MockInput, MockResourceLoader, MockTransaction, MockTransactionHelper,
     ReprMockCellDep, ReprMockInfo, ReprMockInput, ReprMockTransaction,
 };
-pub use tx_helper::{build_signature, MultisigConfig, SignerFn, TxHelper};
+pub use tx_helper::{
+    build_signature, calculate_fee_rate, calculate_tx_fee, check_lock_script, MultisigConfig,
+    SignerFn, TxHelper,
+};
 pub use types::{
     Address, AddressPayload, AddressType, CodeHashIndex, HumanCapacity, NetworkType, OldAddress,
     OldAddressFormat, Since, SinceType,
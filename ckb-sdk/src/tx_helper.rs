@@ -2,7 +2,7 @@ use ckb_hash::{blake2b_256, new_blake2b};
 use ckb_jsonrpc_types as rpc_types;
 use ckb_types::{
     bytes::{Bytes, BytesMut},
-    core::{ScriptHashType, TransactionBuilder, TransactionView},
+    core::{Capacity, HeaderView, ScriptHashType, TransactionBuilder, TransactionView},
     h256,
     packed::{
         self, Byte32, CellDep, CellInput, CellOutput, OutPoint, Script, Transaction, WitnessArgs,
@@ -14,13 +14,15 @@ use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 
 use crate::constants::{MULTISIG_TYPE_HASH, SECP_SIGNATURE_SIZE, SIGHASH_TYPE_HASH};
-use crate::{AddressPayload, AddressType, CodeHashIndex, GenesisInfo, Since};
-
-// TODO: Add dao support
+use crate::dao::{
+    calculate_dao_maximum_withdraw4, is_dao_cell, later_epoch_since, minimal_unlock_point,
+};
+use crate::{AddressPayload, AddressType, CodeHashIndex, GenesisInfo, Since, SinceType};
 
 /// A transaction helper handle input/output with secp256k1(sighash/multisg) lock
 ///  1. Sign transaction
 ///  2. Inspect transaction information
+///  3. Spend NervosDAO cells (prepare and withdraw)
 #[derive(Clone)]
 pub struct TxHelper {
     transaction: TransactionView,
@@ -59,10 +61,13 @@ impl TxHelper {
     }
 
     pub fn clear_inputs(&mut self) {
+        // The witnesses and header deps are only filled for inputs
         self.transaction = self
             .transaction
             .as_advanced_builder()
             .set_inputs(Vec::new())
+            .set_witnesses(Vec::new())
+            .set_header_deps(Vec::new())
             .build();
     }
     pub fn clear_outputs(&mut self) {
@@ -88,29 +93,25 @@ impl TxHelper {
         genesis_info: &GenesisInfo,
         skip_check: bool,
     ) -> Result<(), String> {
-        let lock = get_live_cell(out_point.clone(), false)?.lock();
+        let output = get_live_cell(out_point.clone(), false)?;
+        if is_dao_cell(&output) {
+            return Err(format!(
+                "NervosDAO cell must be added by `add_dao_input`: {}",
+                out_point
+            ));
+        }
+        let lock = output.lock();
         check_lock_script(&lock, skip_check)?;
 
-        let since = if let Some(number) = since_absolute_epoch_opt {
-            Since::new_absolute_epoch(number).value()
-        } else {
-            let lock_arg = lock.args().raw_data();
-            if lock.code_hash() == MULTISIG_TYPE_HASH.pack() && lock_arg.len() == 28 {
-                let mut since_bytes = [0u8; 8];
-                since_bytes.copy_from_slice(&lock_arg[20..]);
-                u64::from_le_bytes(since_bytes)
-            } else {
-                0
-            }
-        };
-
+        let since = input_since(&lock, since_absolute_epoch_opt);
         let input = CellInput::new_builder()
             .previous_output(out_point)
             .since(since.pack())
             .build();
 
         self.transaction = self.transaction.as_advanced_builder().input(input).build();
-        let mut cell_deps: HashSet<CellDep> = HashSet::default();
+        // Keep the cell deps of DAO inputs
+        let mut cell_deps: HashSet<CellDep> = self.transaction.cell_deps().into_iter().collect();
         for ((code_hash, _), _) in self.input_group(get_live_cell, skip_check)?.into_iter() {
             let code_hash: H256 = code_hash.unpack();
             if code_hash == SIGHASH_TYPE_HASH {
@@ -129,6 +130,134 @@ impl TxHelper {
         Ok(())
     }
 
+    /// Add a deposited (to prepare) or prepared (to withdraw) NervosDAO cell as input.
+    ///
+    /// `get_tx_with_header` returns the transaction and the header of the block committed it.
+    /// The deposit header is added to header deps, and for a prepared cell the prepare header is
+    /// also added, the witness `input_type` is the index of the deposit header and the since is
+    /// the minimal unlock point.
+    pub fn add_dao_input<G>(
+        &mut self,
+        out_point: OutPoint,
+        since_absolute_epoch_opt: Option<u64>,
+        mut get_tx_with_header: G,
+        genesis_info: &GenesisInfo,
+        skip_check: bool,
+    ) -> Result<(), String>
+    where
+        G: FnMut(Byte32) -> Result<(TransactionView, HeaderView), String>,
+    {
+        let dao_input = resolve_dao_input(&out_point, &mut get_tx_with_header)?
+            .ok_or_else(|| format!("Not a NervosDAO cell: {}", out_point))?;
+        let lock = dao_input.output.lock();
+        check_lock_script(&lock, skip_check)?;
+        if self
+            .transaction
+            .inputs()
+            .into_iter()
+            .any(|input| input.previous_output() == out_point)
+        {
+            return Err(format!("Already have input: {}", out_point));
+        }
+
+        let mut since = input_since(&lock, since_absolute_epoch_opt);
+        let input_type = if let Some(deposit_header) = dao_input.deposit_header.as_ref() {
+            if since != 0
+                && Since::from_raw_value(since)
+                    .extract_metric()
+                    .map(|(ty, _)| ty)
+                    != Some(SinceType::EpochNumberWithFraction)
+            {
+                return Err(format!(
+                    "Only support epoch since for NervosDAO withdraw, got: {:#x}",
+                    since
+                ));
+            }
+            let unlock_point = minimal_unlock_point(deposit_header, &dao_input.header);
+            let dao_since = Since::new(
+                SinceType::EpochNumberWithFraction,
+                unlock_point.full_value(),
+                false,
+            );
+            since = later_epoch_since(since, dao_since.value())?;
+            let deposit_header_index = self.add_header_dep(deposit_header.hash());
+            self.add_header_dep(dao_input.header.hash());
+            Some(Bytes::from(
+                (deposit_header_index as u64).to_le_bytes().to_vec(),
+            ))
+        } else {
+            self.add_header_dep(dao_input.header.hash());
+            None
+        };
+
+        // The witnesses after the inputs (if any) are shifted, not overwritten
+        let input_index = self.transaction.inputs().len();
+        let mut witnesses = self.init_witnesses();
+        let witness = if let Some(input_type) = input_type {
+            WitnessArgs::new_builder()
+                .input_type(Some(input_type).pack())
+                .build()
+                .as_bytes()
+                .pack()
+        } else {
+            Bytes::new().pack()
+        };
+        witnesses.insert(input_index, witness);
+        let input = CellInput::new_builder()
+            .previous_output(out_point)
+            .since(since.pack())
+            .build();
+        self.transaction = self
+            .transaction
+            .as_advanced_builder()
+            .input(input)
+            .set_witnesses(witnesses)
+            .build();
+
+        self.add_cell_dep(genesis_info.dao_dep());
+        let code_hash: H256 = lock.code_hash().unpack();
+        if code_hash == SIGHASH_TYPE_HASH {
+            self.add_cell_dep(genesis_info.sighash_dep());
+        } else if code_hash == MULTISIG_TYPE_HASH {
+            self.add_cell_dep(genesis_info.multisig_dep());
+        }
+        Ok(())
+    }
+
+    // Return the index of the header dep, the existing header deps are never reordered since
+    // the DAO witnesses refer to them by index
+    fn add_header_dep(&mut self, block_hash: Byte32) -> usize {
+        let header_deps = self.transaction.header_deps();
+        if let Some(index) = header_deps
+            .clone()
+            .into_iter()
+            .position(|hash| hash == block_hash)
+        {
+            return index;
+        }
+        self.transaction = self
+            .transaction
+            .as_advanced_builder()
+            .header_dep(block_hash)
+            .build();
+        header_deps.len()
+    }
+
+    fn add_cell_dep(&mut self, cell_dep: CellDep) {
+        if !self
+            .transaction
+            .cell_deps()
+            .into_iter()
+            .any(|dep| dep == cell_dep)
+        {
+            self.transaction = self
+                .transaction
+                .as_advanced_builder()
+                .cell_dep(cell_dep)
+                .build();
+        }
+    }
+
     pub fn add_output(&mut self, output: CellOutput, data: Bytes) {
         // TODO: Check output(lock-script/type-script)
         self.transaction = self
@@ -329,10 +458,96 @@ impl TxHelper {
             .build())
     }
 
-    pub fn check_tx<F: FnMut(OutPoint, bool) -> Result<CellOutput, String>>(
+    /// Estimate the serialized size (in block) of the final signed transaction,
+    /// sighash/multisig signatures are replaced by zero bytes placeholder.
+    pub fn estimate_tx_size<F: FnMut(OutPoint, bool) -> Result<CellOutput, String>>(
+        &self,
+        get_live_cell: F,
+        skip_check: bool,
+    ) -> Result<usize, String> {
+        let mut witnesses = self.init_witnesses();
+        for ((code_hash, lock_arg), idxs) in
+            self.input_group(get_live_cell, skip_check)?.into_iter()
+        {
+            let lock_field = if code_hash == MULTISIG_TYPE_HASH.pack() {
+                let hash160 = H160::from_slice(&lock_arg[..20]).unwrap();
+                build_placeholder_lock(self.multisig_configs.get(&hash160))
+            } else if code_hash == SIGHASH_TYPE_HASH.pack() {
+                build_placeholder_lock(None)
+            } else {
+                continue;
+            };
+            let init_witness = if witnesses[idxs[0]].raw_data().is_empty() {
+                WitnessArgs::default()
+            } else {
+                WitnessArgs::from_slice(witnesses[idxs[0]].raw_data().as_ref())
+                    .map_err(|err| err.to_string())?
+            };
+            witnesses[idxs[0]] = init_witness
+                .as_builder()
+                .lock(Some(lock_field).pack())
+                .build()
+                .as_bytes()
+                .pack();
+        }
+        Ok(self
+            .transaction
+            .as_advanced_builder()
+            .set_witnesses(witnesses)
+            .build()
+            .data()
+            .serialized_size_in_block())
+    }
+
+    /// The compensation of the withdrawing NervosDAO inputs, they are the inputs with witness
+    /// `input_type` (the index of the deposit header).
+    pub fn dao_compensation<G>(&self, mut get_tx_with_header: G) -> Result<u64, String>
+    where
+        G: FnMut(Byte32) -> Result<(TransactionView, HeaderView), String>,
+    {
+        let witnesses = self.init_witnesses();
+        let mut compensation = 0;
+        for (input, witness) in self.transaction.inputs().into_iter().zip(witnesses) {
+            let has_input_type = WitnessArgs::from_slice(witness.raw_data().as_ref())
+                .map(|witness| witness.input_type().to_opt().is_some())
+                .unwrap_or(false);
+            if !has_input_type {
+                continue;
+            }
+            let out_point = input.previous_output();
+            let dao_input = match resolve_dao_input(&out_point, &mut get_tx_with_header)? {
+                Some(dao_input) => dao_input,
+                None => continue,
+            };
+            if let Some(deposit_header) = dao_input.deposit_header.as_ref() {
+                let occupied_capacity = dao_input
+                    .output
+                    .occupied_capacity(Capacity::bytes(DAO_DATA_LEN).unwrap())
+                    .map_err(|err| err.to_string())?;
+                let maximum_withdraw = calculate_dao_maximum_withdraw4(
+                    deposit_header,
+                    &dao_input.header,
+                    &dao_input.output,
+                    occupied_capacity.as_u64(),
+                )?;
+                let capacity: u64 = dao_input.output.capacity().unpack();
+                compensation += maximum_withdraw.saturating_sub(capacity);
+            }
+        }
+        Ok(compensation)
+    }
+
+    /// Check inputs and outputs, return the total capacity of inputs (include the DAO
+    /// compensation) and outputs.
+    pub fn check_tx<F, G>(
         &self,
         mut get_live_cell: F,
-    ) -> Result<(u64, u64), String> {
+        get_tx_with_header: G,
+    ) -> Result<(u64, u64), String>
+    where
+        F: FnMut(OutPoint, bool) -> Result<CellOutput, String>,
+        G: FnMut(Byte32) -> Result<(TransactionView, HeaderView), String>,
+    {
         // Check inputs
         let mut previous_outputs: HashSet<OutPoint> = HashSet::default();
         let mut input_total: u64 = 0;
@@ -350,6 +565,7 @@ impl TxHelper {
             check_lock_script(&output.lock(), false)
                 .map_err(|err| format!("Input(no.{}) {}", i + 1, err))?;
         }
+        input_total += self.dao_compensation(get_tx_with_header)?;
 
         // Check output
         let mut output_total: u64 = 0;
@@ -365,6 +581,83 @@ impl TxHelper {
     }
 }
 
+const DAO_DATA_LEN: usize = 8;
+
+// A NervosDAO cell with the header of the block committed it, the deposit header is only for
+// a prepared cell
+struct DaoInput {
+    output: CellOutput,
+    header: HeaderView,
+    deposit_header: Option<HeaderView>,
+}
+
+fn resolve_dao_input<G>(
+    out_point: &OutPoint,
+    get_tx_with_header: &mut G,
+) -> Result<Option<DaoInput>, String>
+where
+    G: FnMut(Byte32) -> Result<(TransactionView, HeaderView), String>,
+{
+    let (tx, header) = get_tx_with_header(out_point.tx_hash())?;
+    let index: u32 = out_point.index().unpack();
+    let (output, data) = tx
+        .output_with_data(index as usize)
+        .ok_or_else(|| format!("Cell not found: {}", out_point))?;
+    if !is_dao_cell(&output) {
+        return Ok(None);
+    }
+    if data.len() != DAO_DATA_LEN {
+        return Err(format!("Invalid NervosDAO cell data: {}", out_point));
+    }
+    // The data of a deposited cell is zero, a prepared cell saves the deposit block number
+    let deposit_header = if data.iter().all(|byte| *byte == 0) {
+        None
+    } else {
+        let deposit_out_point = tx
+            .inputs()
+            .get(index as usize)
+            .ok_or_else(|| format!("Invalid NervosDAO prepare transaction: {}", out_point))?
+            .previous_output();
+        let (_, deposit_header) = get_tx_with_header(deposit_out_point.tx_hash())?;
+        Some(deposit_header)
+    };
+    Ok(Some(DaoInput {
+        output,
+        header,
+        deposit_header,
+    }))
+}
+
+// The since of the multisig lock with time lock, or the given absolute epoch
+fn input_since(lock: &Script, since_absolute_epoch_opt: Option<u64>) -> u64 {
+    if let Some(number) = since_absolute_epoch_opt {
+        Since::new_absolute_epoch(number).value()
+    } else {
+        let lock_arg = lock.args().raw_data();
+        if lock.code_hash() == MULTISIG_TYPE_HASH.pack() && lock_arg.len() == 28 {
+            let mut since_bytes = [0u8; 8];
+            since_bytes.copy_from_slice(&lock_arg[20..]);
+            u64::from_le_bytes(since_bytes)
+        } else {
+            0
+        }
+    }
+}
+
+/// Calculate the transaction fee by fee rate (unit: shannons/KB), round up
+pub fn calculate_tx_fee(fee_rate: u64, tx_size: usize) -> u64 {
+    let fee = u128::from(fee_rate) * tx_size as u128;
+    ((fee + 999) / 1000) as u64
+}
+
+/// Calculate the effective fee rate (unit: shannons/KB) of a transaction
+pub fn calculate_fee_rate(tx_fee: u64, tx_size: usize) -> u64 {
+    if tx_size == 0 {
+        return 0;
+    }
+    (u128::from(tx_fee) * 1000 / tx_size as u128) as u64
+}
+
 pub type SignerFn = Box<
     dyn FnMut(&HashSet<H160>, &H256, &rpc_types::Transaction) -> Result<Option<[u8; 65]>, String>,
 >;
@@ -542,23 +835,10 @@ pub fn build_signature<
             .map_err(|err| err.to_string())?
     };
 
-    let init_witness = if let Some(multisig_config) = multisig_config_opt {
-        let lock_without_sig = {
-            let sig_len = (multisig_config.threshold() as usize) * SECP_SIGNATURE_SIZE;
-            let mut data = BytesMut::from(&multisig_config.to_witness_data()[..]);
-            data.extend_from_slice(vec![0u8; sig_len].as_slice());
-            data.freeze()
-        };
-        init_witness
-            .as_builder()
-            .lock(Some(lock_without_sig).pack())
-            .build()
-    } else {
-        init_witness
-            .as_builder()
-            .lock(Some(Bytes::from(vec![0u8; SECP_SIGNATURE_SIZE])).pack())
-            .build()
-    };
+    let init_witness = init_witness
+        .as_builder()
+        .lock(Some(build_placeholder_lock(multisig_config_opt)).pack())
+        .build();
 
     let mut blake2b = new_blake2b();
     blake2b.update(tx.hash().as_slice());
@@ -579,6 +859,18 @@ pub fn build_signature<
     signer(&message, &tx.data().into()).map(|data| Bytes::from(data.to_vec()))
 }
 
+// The witness lock field with all signatures filled by zero
+fn build_placeholder_lock(multisig_config_opt: Option<&MultisigConfig>) -> Bytes {
+    if let Some(multisig_config) = multisig_config_opt {
+        let sig_len = (multisig_config.threshold() as usize) * SECP_SIGNATURE_SIZE;
+        let mut data = BytesMut::from(&multisig_config.to_witness_data()[..]);
+        data.extend_from_slice(vec![0u8; sig_len].as_slice());
+        data.freeze()
+    } else {
+        Bytes::from(vec![0u8; SECP_SIGNATURE_SIZE])
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -671,4 +963,282 @@ mod tests {
             assert_eq!(check_lock_script(script, *skip_check).is_ok(), *is_ok);
         }
     }
+
+    #[test]
+    fn test_calculate_tx_fee() {
+        assert_eq!(calculate_tx_fee(1000, 0), 0);
+        assert_eq!(calculate_tx_fee(1000, 464), 464);
+        assert_eq!(calculate_tx_fee(1, 464), 1);
+        assert_eq!(calculate_tx_fee(1500, 1001), 1502);
+        assert_eq!(calculate_fee_rate(464, 464), 1000);
+        assert_eq!(calculate_fee_rate(1502, 1001), 1500);
+        assert_eq!(calculate_fee_rate(1000, 0), 0);
+    }
+
+    #[test]
+    fn test_estimate_tx_size() {
+        let lock_arg = h160!("0x33");
+        let lock = packed::Script::new_builder()
+            .args(Bytes::from(lock_arg.as_bytes().to_vec()).pack())
+            .code_hash(SIGHASH_TYPE_HASH.pack())
+            .hash_type(ScriptHashType::Type.into())
+            .build();
+        let input_cell = CellOutput::new_builder().lock(lock.clone()).build();
+        let tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(h256!("0x1").pack(), 0), 0))
+            .input(CellInput::new(OutPoint::new(h256!("0x1").pack(), 1), 0))
+            .output(input_cell.clone())
+            .output_data(Bytes::new().pack())
+            .build();
+        let mut helper = TxHelper::new(tx);
+        let get_live_cell = |_: OutPoint, _: bool| Ok(input_cell.clone());
+        let estimated_size = helper.estimate_tx_size(get_live_cell, false).unwrap();
+
+        helper
+            .add_signature(
+                Bytes::from(lock_arg.as_bytes().to_vec()),
+                Bytes::from(vec![1u8; SECP_SIGNATURE_SIZE]),
+            )
+            .unwrap();
+        let signed_tx = helper.build_tx(get_live_cell, false).unwrap();
+        assert_eq!(estimated_size, signed_tx.data().serialized_size_in_block());
+    }
+
+    #[test]
+    fn test_dao_compensation() {
+        use crate::constants::DAO_TYPE_HASH;
+        use ckb_dao_utils::pack_dao_data;
+        use ckb_types::core::{capacity_bytes, HeaderBuilder};
+
+        let header = |number: u64, ar: u64| {
+            HeaderBuilder::default()
+                .number(number.pack())
+                .dao(pack_dao_data(
+                    ar,
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                ))
+                .build()
+        };
+        let deposit_header = header(100, 10_000_000_000_123_456);
+        let prepare_header = header(200, 10_000_000_001_123_456);
+        let dao_output = CellOutput::new_builder()
+            .capacity(capacity_bytes!(1000000).pack())
+            .type_(
+                Some(
+                    Script::new_builder()
+                        .code_hash(DAO_TYPE_HASH.pack())
+                        .hash_type(ScriptHashType::Type.into())
+                        .build(),
+                )
+                .pack(),
+            )
+            .build();
+        let deposit_tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(h256!("0x1").pack(), 0), 0))
+            .output(dao_output.clone())
+            .output_data(Bytes::from(vec![0u8; 8]).pack())
+            .build();
+        let prepare_tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(deposit_tx.hash(), 0), 0))
+            .output(dao_output)
+            .output_data(Bytes::from(100u64.to_le_bytes().to_vec()).pack())
+            .build();
+        let get_tx_with_header = |tx_hash: Byte32| {
+            if tx_hash == deposit_tx.hash() {
+                Ok((deposit_tx.clone(), deposit_header.clone()))
+            } else if tx_hash == prepare_tx.hash() {
+                Ok((prepare_tx.clone(), prepare_header.clone()))
+            } else {
+                Err(format!("transaction not found: {}", tx_hash))
+            }
+        };
+
+        let dao_witness = WitnessArgs::new_builder()
+            .input_type(Some(Bytes::from(0u64.to_le_bytes().to_vec())).pack())
+            .build();
+        let withdraw_tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(prepare_tx.hash(), 0), 0))
+            .input(CellInput::new(OutPoint::new(h256!("0x2").pack(), 0), 0))
+            .header_dep(deposit_header.hash())
+            .header_dep(prepare_header.hash())
+            .witness(dao_witness.as_bytes().pack())
+            .build();
+        let helper = TxHelper::new(withdraw_tx);
+        assert_eq!(helper.dao_compensation(get_tx_with_header).unwrap(), 9999);
+
+        // The deposited cell is not compensated
+        let prepare_helper = TxHelper::new(
+            TransactionBuilder::default()
+                .input(CellInput::new(OutPoint::new(deposit_tx.hash(), 0), 0))
+                .witness(dao_witness.as_bytes().pack())
+                .build(),
+        );
+        assert_eq!(
+            prepare_helper.dao_compensation(get_tx_with_header).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_add_dao_input() {
+        use crate::constants::DAO_TYPE_HASH;
+        use ckb_types::core::{BlockBuilder, EpochNumberWithFraction, HeaderBuilder};
+
+        // Only the system cell locations matter
+        let system_cell = CellOutput::new_builder()
+            .type_(Some(Script::default()).pack())
+            .build();
+        let genesis_block = BlockBuilder::default()
+            .transaction(
+                TransactionBuilder::default()
+                    .outputs(vec![system_cell; 5])
+                    .outputs_data(vec![Bytes::new().pack(); 5])
+                    .build(),
+            )
+            .transaction(
+                TransactionBuilder::default()
+                    .outputs(vec![CellOutput::default(); 2])
+                    .outputs_data(vec![Bytes::new().pack(); 2])
+                    .build(),
+            )
+            .build();
+        let genesis_info = GenesisInfo::from_block(&genesis_block).unwrap();
+
+        let header = |number: u64, epoch: EpochNumberWithFraction| {
+            HeaderBuilder::default()
+                .number(number.pack())
+                .epoch(epoch.full_value().pack())
+                .build()
+        };
+        let other_deposit_header = header(50, EpochNumberWithFraction::new(3, 0, 1000));
+        let deposit_header = header(100, EpochNumberWithFraction::new(5, 5, 1000));
+        let prepare_header = header(200, EpochNumberWithFraction::new(184, 4, 1000));
+        let dao_output = CellOutput::new_builder()
+            .lock(
+                Script::new_builder()
+                    .code_hash(SIGHASH_TYPE_HASH.pack())
+                    .hash_type(ScriptHashType::Type.into())
+                    .args(Bytes::from(h160!("0x33").as_bytes().to_vec()).pack())
+                    .build(),
+            )
+            .type_(
+                Some(
+                    Script::new_builder()
+                        .code_hash(DAO_TYPE_HASH.pack())
+                        .hash_type(ScriptHashType::Type.into())
+                        .build(),
+                )
+                .pack(),
+            )
+            .build();
+        let deposit_tx = |input_tx_hash: H256| {
+            TransactionBuilder::default()
+                .input(CellInput::new(OutPoint::new(input_tx_hash.pack(), 0), 0))
+                .output(dao_output.clone())
+                .output_data(Bytes::from(vec![0u8; 8]).pack())
+                .build()
+        };
+        let other_deposit_tx = deposit_tx(h256!("0x1"));
+        let deposit_tx = deposit_tx(h256!("0x2"));
+        let prepare_tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(deposit_tx.hash(), 0), 0))
+            .output(dao_output.clone())
+            .output_data(Bytes::from(100u64.to_le_bytes().to_vec()).pack())
+            .build();
+        let get_tx_with_header = |tx_hash: Byte32| {
+            if tx_hash == other_deposit_tx.hash() {
+                Ok((other_deposit_tx.clone(), other_deposit_header.clone()))
+            } else if tx_hash == deposit_tx.hash() {
+                Ok((deposit_tx.clone(), deposit_header.clone()))
+            } else if tx_hash == prepare_tx.hash() {
+                Ok((prepare_tx.clone(), prepare_header.clone()))
+            } else {
+                Err(format!("transaction not found: {}", tx_hash))
+            }
+        };
+
+        // A witness not for inputs (e.g. for output type script) is kept at the end
+        let extra_witness = Bytes::from(vec![7u8; 3]).pack();
+        let mut helper = TxHelper::new(
+            TransactionBuilder::default()
+                .witness(extra_witness.clone())
+                .build(),
+        );
+        helper
+            .add_dao_input(
+                OutPoint::new(other_deposit_tx.hash(), 0),
+                None,
+                get_tx_with_header,
+                &genesis_info,
+                false,
+            )
+            .unwrap();
+        helper
+            .add_dao_input(
+                OutPoint::new(prepare_tx.hash(), 0),
+                None,
+                get_tx_with_header,
+                &genesis_info,
+                false,
+            )
+            .unwrap();
+        assert!(helper
+            .add_dao_input(
+                OutPoint::new(prepare_tx.hash(), 0),
+                None,
+                get_tx_with_header,
+                &genesis_info,
+                false,
+            )
+            .is_err());
+
+        let tx = helper.transaction();
+        assert_eq!(
+            tx.header_deps().into_iter().collect::<Vec<_>>(),
+            vec![
+                other_deposit_header.hash(),
+                deposit_header.hash(),
+                prepare_header.hash()
+            ]
+        );
+        let since_list = tx
+            .inputs()
+            .into_iter()
+            .map(|input| input.since().unpack())
+            .collect::<Vec<u64>>();
+        let unlock_point = EpochNumberWithFraction::new(185, 5, 1000);
+        assert_eq!(
+            since_list,
+            vec![
+                0,
+                Since::new(
+                    SinceType::EpochNumberWithFraction,
+                    unlock_point.full_value(),
+                    false
+                )
+                .value()
+            ]
+        );
+        // The input type of the withdraw witness is the index of the deposit header
+        let dao_witness = WitnessArgs::new_builder()
+            .input_type(Some(Bytes::from(1u64.to_le_bytes().to_vec())).pack())
+            .build();
+        assert_eq!(
+            tx.witnesses()
+                .into_iter()
+                .map(|witness| witness.raw_data())
+                .collect::<Vec<_>>(),
+            vec![
+                Bytes::new(),
+                dao_witness.as_bytes(),
+                extra_witness.raw_data()
+            ]
+        );
+        assert!(tx
+            .cell_deps()
+            .into_iter()
+            .any(|dep| dep == genesis_info.dao_dep()));
+    }
 }
@@ -32,12 +32,33 @@ pub enum Error {
     #[fail(display = "Unsupported kdf: {}", _0)]
     UnsupportedKdf(String),
 
+    #[fail(display = "Invalid kdf parameters: {}", _0)]
+    InvalidKdfParams(String),
+
     #[fail(display = "Generate secp256k1 secret failed, tried: {}", _0)]
     GenSecpFailed(u16),
 
     #[fail(display = "Invalid secp256k1 secret key")]
     InvalidSecpSecret,
 
+    #[fail(display = "Account {:x} is watch-only, it has no private key", _0)]
+    WatchOnly(H160),
+
+    #[fail(display = "Invalid mnemonic: {}", _0)]
+    InvalidMnemonic(String),
+
+    #[fail(display = "Account {:x} is not created from mnemonic words", _0)]
+    NoMnemonic(H160),
+
+    #[fail(display = "Invalid label {:?}: {}", _0, _1)]
+    InvalidLabel(String, &'static str),
+
+    #[fail(display = "Label {:?} is already used by account {:x}", _0, _1)]
+    LabelExists(String, H160),
+
+    #[fail(display = "Invalid share: {}", _0)]
+    InvalidShare(String),
+
     #[fail(display = "Search derived address failed")]
     SearchDerivedAddrFailed,
 
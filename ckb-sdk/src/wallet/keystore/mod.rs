@@ -1,17 +1,20 @@
 mod error;
 mod passphrase;
+mod shamir;
 mod util;
 
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fmt;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
 use super::bip32::{ChainCode, ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey};
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
 use chrono::{Datelike, Timelike, Utc};
 use ckb_crypto::secp::SECP256K1;
 use ckb_hash::blake2b_256;
@@ -19,21 +22,38 @@ use ckb_types::{H160, H256};
 use faster_hex::{hex_decode, hex_string};
 use rand::Rng;
 use secp256k1::recovery::RecoverableSignature;
+use serde_derive::{Deserialize, Serialize};
 use uuid::Uuid;
 
 pub use error::Error;
-pub use passphrase::{CipherParams, Crypto, KdfParams, ScryptParams, ScryptType};
+pub use passphrase::{
+    Argon2Params, CipherParams, Crypto, KdfParams, KdfType, ScryptParams, ScryptType,
+};
+pub use shamir::{combine_shares, split_secret, Share, MAX_SHARE_COUNT};
 pub use util::{zeroize_privkey, zeroize_slice};
 
 const KEYSTORE_VERSION: u32 = 3;
 const KEYSTORE_ORIGIN: &str = "ckb-cli";
+// Metadata of all accounts is stored in this file under the keys directory, so that setting the
+// metadata never rewrites a key file.
+const METADATA_FILENAME: &str = "metadata.json";
 pub const CKB_ROOT_PATH: &str = "m/44'/309'/0'";
 
+// The BIP-44 account path of CKB, account 0 is CKB_ROOT_PATH
+pub fn ckb_root_path(account_index: u32) -> String {
+    format!("m/44'/309'/{}'", account_index)
+}
+
 pub struct KeyStore {
     keys_dir: PathBuf,
     storage: PassphraseKeyStore,
     files: HashMap<H160, PathBuf>,
     ckb_roots: HashMap<H160, CkbRoot>,
+    // The roots of other BIP-44 account indices (m/44'/309'/n', n > 0) added to key file
+    sub_ckb_roots: HashMap<H160, BTreeMap<u32, CkbRoot>>,
+    // Accounts only have the extended public key of CKB root path
+    watch_only: HashSet<H160>,
+    metadata: HashMap<H160, AccountMetadata>,
     unlocked_keys: HashMap<H160, TimedKey>,
 }
 
@@ -44,6 +64,9 @@ impl Clone for KeyStore {
             storage: self.storage.clone(),
             files: self.files.clone(),
             ckb_roots: self.ckb_roots.clone(),
+            sub_ckb_roots: self.sub_ckb_roots.clone(),
+            watch_only: self.watch_only.clone(),
+            metadata: self.metadata.clone(),
             unlocked_keys: HashMap::default(),
         }
     }
@@ -56,16 +79,27 @@ impl KeyStore {
             keys_dir: abs_dir.clone(),
             storage: PassphraseKeyStore {
                 keys_dir_path: abs_dir,
-                scrypt_type,
+                kdf_type: scrypt_type.into(),
             },
             files: HashMap::default(),
             ckb_roots: HashMap::default(),
+            sub_ckb_roots: HashMap::default(),
+            watch_only: HashSet::default(),
+            metadata: HashMap::default(),
             unlocked_keys: HashMap::default(),
         };
         key_store.refresh_dir()?;
         Ok(key_store)
     }
 
+    // The KDF used when encrypting new (or rewritten) key files
+    pub fn kdf_type(&self) -> KdfType {
+        self.storage.kdf_type
+    }
+    pub fn set_kdf_type(&mut self, kdf_type: KdfType) {
+        self.storage.kdf_type = kdf_type;
+    }
+
     pub fn new_account(&mut self, password: &[u8]) -> Result<H160, Error> {
         let privkey = MasterPrivKey::try_new(1024)?;
         let key = Key::new(privkey);
@@ -74,6 +108,21 @@ impl KeyStore {
         self.files.insert(hash160.clone(), abs_path);
         Ok(hash160)
     }
+    // Create an account from newly generated BIP-39 mnemonic words, the mnemonic phrase is
+    // returned for the user to backup.
+    pub fn new_account_with_mnemonic(
+        &mut self,
+        password: &[u8],
+        word_count: usize,
+        passphrase: &str,
+    ) -> Result<(H160, String), Error> {
+        let mnemonic_type = MnemonicType::for_word_count(word_count)
+            .map_err(|err| Error::InvalidMnemonic(err.to_string()))?;
+        let mnemonic = Mnemonic::new(mnemonic_type, Language::English);
+        let key = Key::from_mnemonic(mnemonic.phrase(), passphrase)?;
+        let hash160 = self.import_key(&key, password)?;
+        Ok((hash160, mnemonic.into_phrase()))
+    }
     pub fn get_accounts(&mut self) -> &HashMap<H160, PathBuf> {
         self.refresh_dir().ok();
         &self.files
@@ -84,12 +133,97 @@ impl KeyStore {
         }
         self.ckb_roots.get(hash160)
     }
+    pub fn get_ckb_root_by_index(
+        &mut self,
+        hash160: &H160,
+        account_index: u32,
+        refresh: bool,
+    ) -> Option<&CkbRoot> {
+        if account_index == 0 {
+            return self.get_ckb_root(hash160, refresh);
+        }
+        if refresh {
+            self.refresh_dir().ok();
+        }
+        self.sub_ckb_roots
+            .get(hash160)
+            .and_then(|roots| roots.get(&account_index))
+    }
+    // The added account indices (not include 0) ordered by index
+    pub fn get_sub_ckb_roots(&mut self, hash160: &H160, refresh: bool) -> Vec<CkbRoot> {
+        if refresh {
+            self.refresh_dir().ok();
+        }
+        self.sub_ckb_roots
+            .get(hash160)
+            .map(|roots| roots.values().cloned().collect())
+            .unwrap_or_default()
+    }
     pub fn has_account(&mut self, hash160: &H160, refresh: bool) -> bool {
         if refresh {
             self.refresh_dir().ok();
         }
         self.files.contains_key(hash160)
     }
+    pub fn is_watch_only(&mut self, hash160: &H160, refresh: bool) -> bool {
+        if refresh {
+            self.refresh_dir().ok();
+        }
+        self.watch_only.contains(hash160)
+    }
+    pub fn get_metadata(&mut self, hash160: &H160, refresh: bool) -> Option<&AccountMetadata> {
+        if refresh {
+            self.refresh_dir().ok();
+        }
+        self.metadata.get(hash160)
+    }
+    pub fn find_by_label(&mut self, label: &str) -> Option<H160> {
+        self.refresh_dir().ok();
+        self.metadata
+            .iter()
+            .find(|(_, metadata)| metadata.label.as_deref() == Some(label))
+            .map(|(hash160, _)| hash160.clone())
+    }
+    pub fn get_labels(&mut self) -> Vec<String> {
+        self.refresh_dir().ok();
+        let mut labels = self
+            .metadata
+            .values()
+            .filter_map(|metadata| metadata.label.clone())
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels
+    }
+    // The metadata is plain text in the metadata file, no password is required. Empty metadata
+    // removes the account from the file.
+    pub fn set_metadata(&mut self, hash160: &H160, metadata: AccountMetadata) -> Result<(), Error> {
+        if let Some(label) = metadata.label.as_ref() {
+            AccountMetadata::check_label(label)?;
+            if let Some(other) = self.find_by_label(label).filter(|other| other != hash160) {
+                return Err(Error::LabelExists(label.clone(), other));
+            }
+        }
+        self.get_filepath(hash160)?;
+        let filepath = self.keys_dir.join(METADATA_FILENAME);
+        let mut all_metadata = read_metadata_file(&filepath)?;
+        if metadata.is_empty() {
+            all_metadata.remove(hash160);
+        } else {
+            all_metadata.insert(hash160.clone(), metadata);
+        }
+        let content = serde_json::to_string(&metadata_to_json(&all_metadata))
+            .map_err(|err| Error::Other(err.to_string()))?;
+        // Write to a temporary file then rename it, the old file is kept if anything goes wrong
+        let tmp_filepath = self.keys_dir.join(format!("{}.tmp", METADATA_FILENAME));
+        let mut file = fs::File::create(&tmp_filepath)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_filepath, &filepath)?;
+        let files = &self.files;
+        all_metadata.retain(|hash160, _| files.contains_key(hash160));
+        self.metadata = all_metadata;
+        Ok(())
+    }
 
     pub fn update(
         &mut self,
@@ -130,6 +264,22 @@ impl KeyStore {
             .get(hash160)
             .map(|timed_key| timed_key.timeout)
     }
+    pub fn is_unlocked(&mut self, hash160: &H160) -> bool {
+        self.get_timed_key(hash160).is_ok()
+    }
+    // Lock all the expired keys (the private keys are zeroized when dropped)
+    pub fn lock_expired(&mut self) -> Vec<H160> {
+        let expired = self
+            .unlocked_keys
+            .iter()
+            .filter(|(_, timed_key)| timed_key.is_expired())
+            .map(|(hash160, _)| hash160.clone())
+            .collect::<Vec<_>>();
+        for hash160 in &expired {
+            self.unlocked_keys.remove(hash160);
+        }
+        expired
+    }
 
     pub fn import(
         &mut self,
@@ -160,6 +310,15 @@ impl KeyStore {
             Ok(key.hash160().clone())
         }
     }
+    pub fn import_mnemonic(
+        &mut self,
+        phrase: &str,
+        passphrase: &str,
+        password: &[u8],
+    ) -> Result<H160, Error> {
+        let key = Key::from_mnemonic(phrase, passphrase)?;
+        self.import_key(&key, password)
+    }
     pub fn import_key(&mut self, key: &Key, password: &[u8]) -> Result<H160, Error> {
         if self.files.contains_key(key.hash160()) {
             Err(Error::KeyExists(key.hash160().clone()))
@@ -169,7 +328,71 @@ impl KeyStore {
             Ok(key.hash160().clone())
         }
     }
-    pub fn upgrade(&self, hash160: &H160, password: &[u8]) -> Result<(), Error> {
+    // Import the extended public key of CKB root path as a watch-only account, the account
+    // identifier is the blake160 hash of the account-level (m/44'/309'/<account_index>') public
+    // key. It is NOT the lock arg of the master key which exported the extended public key, the
+    // key file of master key is identified by blake160 of the master public key.
+    pub fn import_watch_only(&mut self, ckb_root: CkbRoot) -> Result<H160, Error> {
+        let hash160 = ckb_root.hash160();
+        if self.files.contains_key(&hash160) {
+            return Err(Error::KeyExists(hash160));
+        }
+        let id = Uuid::new_v4();
+        let mut buf = Uuid::encode_buffer();
+        let id_str = id.to_hyphenated().encode_lower(&mut buf);
+        let json_value = serde_json::json!({
+            "origin": KEYSTORE_ORIGIN,
+            "id": id_str,
+            "version": KEYSTORE_VERSION,
+            "hash160": format!("{:x}", hash160),
+            "watch_only": true,
+            "ckb_root": ckb_root.to_json(),
+        });
+        let filepath = self.keys_dir.join(key_filename(&hash160));
+        let mut file = fs::File::create(&filepath)?;
+        serde_json::to_writer(&mut file, &json_value).map_err(|err| Error::Io(err.to_string()))?;
+        self.files.insert(hash160.clone(), filepath);
+        self.ckb_roots.insert(hash160.clone(), ckb_root);
+        self.watch_only.insert(hash160.clone());
+        Ok(hash160)
+    }
+    // Store the extended public key of m/44'/309'/<account_index>' in key file, so the derived
+    // addresses of the account index can be listed without password.
+    pub fn add_account_index(
+        &mut self,
+        hash160: &H160,
+        account_index: u32,
+        password: &[u8],
+    ) -> Result<CkbRoot, Error> {
+        if account_index == 0 {
+            return Err(Error::Other(
+                "Account index 0 is the default account".to_owned(),
+            ));
+        }
+        self.refresh_dir()?;
+        let filepath = self.get_filepath(hash160)?;
+        let mut key = self.storage.get_key(hash160, &filepath, password)?;
+        key.add_account_index(account_index)?;
+        self.storage.store_key(&filepath, &key, password)?;
+        let ckb_root = key.ckb_root_by_index(account_index);
+        self.sub_ckb_roots
+            .entry(hash160.clone())
+            .or_default()
+            .insert(account_index, ckb_root.clone());
+        Ok(ckb_root)
+    }
+    pub fn upgrade(&mut self, hash160: &H160, password: &[u8]) -> Result<(), Error> {
+        self.rekey(hash160, password, self.storage.kdf_type)
+    }
+    // Re-encrypt the key file with the password and new KDF parameters, the old file is kept as
+    // backup until the new file is written.
+    pub fn rekey(
+        &mut self,
+        hash160: &H160,
+        password: &[u8],
+        kdf_type: KdfType,
+    ) -> Result<(), Error> {
+        self.refresh_dir()?;
         let filepath = self.get_filepath(hash160)?;
         let backup_path = filepath.with_file_name(format!("{:#x}.upgrade-backup", hash160));
         if backup_path.exists() {
@@ -178,13 +401,16 @@ impl KeyStore {
                 backup_path
             )));
         }
-        fs::copy(&filepath, &backup_path)?;
+        // Check the password before writing anything
         let key = self.storage.get_key(hash160, &filepath, password)?;
+        fs::copy(&filepath, &backup_path)?;
         let filename = filepath
             .file_name()
             .and_then(OsStr::to_str)
             .expect("file_name");
-        let _filepath = self.storage.store_key(filename, &key, password)?;
+        let _filepath = self
+            .storage
+            .store_key_with_kdf(filename, &key, password, kdf_type)?;
         fs::remove_file(backup_path)?;
         Ok(())
     }
@@ -204,6 +430,14 @@ impl KeyStore {
         let key = self.storage.get_key(hash160, &filepath, password)?;
         Ok(key.master_privkey)
     }
+    // Only available for the accounts created (or imported) from mnemonic words, the BIP-39
+    // passphrase is not stored.
+    pub fn export_mnemonic(&self, hash160: &H160, password: &[u8]) -> Result<String, Error> {
+        let filepath = self.get_filepath(hash160)?;
+        let key = self.storage.get_key(hash160, &filepath, password)?;
+        key.mnemonic()?
+            .ok_or_else(|| Error::NoMnemonic(hash160.clone()))
+    }
 
     pub fn sign<P>(
         &mut self,
@@ -283,26 +517,35 @@ impl KeyStore {
         let key = self.storage.get_key(hash160, &filepath, password)?;
         Ok(key.master_privkey.extended_pubkey(path))
     }
+    pub fn ckb_root(&mut self, hash160: &H160, account_index: u32) -> Result<CkbRoot, Error> {
+        Ok(self
+            .get_timed_key(hash160)?
+            .key
+            .ckb_root_by_index(account_index))
+    }
     pub fn ckb_root_with_password(
         &mut self,
         hash160: &H160,
+        account_index: u32,
         password: &[u8],
     ) -> Result<CkbRoot, Error> {
         let filepath = self.get_filepath(hash160)?;
         let key = self.storage.get_key(hash160, &filepath, password)?;
-        Ok(key.ckb_root())
+        Ok(key.ckb_root_by_index(account_index))
     }
 
     // NOTE: assume refresh keystore directory is not a hot action
     fn refresh_dir(&mut self) -> Result<(), Error> {
         let mut files = HashMap::default();
         let mut ckb_roots = HashMap::default();
+        let mut sub_ckb_roots = HashMap::default();
+        let mut watch_only = HashSet::default();
         for entry in fs::read_dir(&self.keys_dir)? {
             let entry = entry?;
             let path = entry.path();
             if path.is_file() {
                 let filename = path.file_name().and_then(OsStr::to_str).expect("file_name");
-                if let Some((hash160, ckb_root_opt)) = filename
+                if let Some((hash160, ckb_root_opt, sub_roots, is_watch_only)) = filename
                     .rsplitn(2, "--")
                     .next()
                     .and_then(|hash160_hex| {
@@ -321,19 +564,33 @@ impl KeyStore {
                                 let ckb_root_opt = util::get_value(&value, "ckb_root")
                                     .ok()
                                     .and_then(|value| CkbRoot::from_json(value).ok());
-                                (hash160, ckb_root_opt)
+                                let sub_roots = sub_ckb_roots_from_json(&value).unwrap_or_default();
+                                (hash160, ckb_root_opt, sub_roots, is_watch_only_json(&value))
                             })
                     })
                 {
                     files.insert(hash160.clone(), path.to_path_buf());
+                    if is_watch_only {
+                        watch_only.insert(hash160.clone());
+                    }
+                    if !sub_roots.is_empty() {
+                        sub_ckb_roots.insert(hash160.clone(), sub_roots);
+                    }
                     if let Some(ckb_root) = ckb_root_opt {
                         ckb_roots.insert(hash160, ckb_root);
                     }
                 }
             }
         }
+        // The metadata of removed key files is ignored
+        let mut metadata =
+            read_metadata_file(&self.keys_dir.join(METADATA_FILENAME)).unwrap_or_default();
+        metadata.retain(|hash160, _| files.contains_key(hash160));
         self.files = files;
         self.ckb_roots = ckb_roots;
+        self.sub_ckb_roots = sub_ckb_roots;
+        self.watch_only = watch_only;
+        self.metadata = metadata;
         Ok(())
     }
 
@@ -380,11 +637,84 @@ impl KeyStore {
     }
 }
 
+// The metadata file is a json object: {"<hash160 hex>": <metadata>, ...}
+fn read_metadata_file(path: &Path) -> Result<HashMap<H160, AccountMetadata>, Error> {
+    if !path.exists() {
+        return Ok(HashMap::default());
+    }
+    let value: serde_json::Value = serde_json::from_reader(&mut fs::File::open(path)?)
+        .map_err(|err| Error::ParseJsonFailed(err.to_string()))?;
+    metadata_from_json(value)
+}
+
+fn metadata_from_json(value: serde_json::Value) -> Result<HashMap<H160, AccountMetadata>, Error> {
+    let items: BTreeMap<String, AccountMetadata> =
+        serde_json::from_value(value).map_err(|err| Error::ParseJsonFailed(err.to_string()))?;
+    items
+        .into_iter()
+        .map(|(hash160_hex, metadata)| {
+            let mut hash160_bin = [0u8; 20];
+            if hash160_hex.len() != 40
+                || hex_decode(hash160_hex.as_bytes(), &mut hash160_bin).is_err()
+            {
+                return Err(Error::ParseJsonFailed(format!(
+                    "invalid account in metadata file: {}",
+                    hash160_hex
+                )));
+            }
+            let hash160 = H160::from_slice(&hash160_bin).expect("H160 from 20 bytes");
+            Ok((hash160, metadata))
+        })
+        .collect()
+}
+
+fn metadata_to_json(metadata: &HashMap<H160, AccountMetadata>) -> serde_json::Value {
+    let items = metadata
+        .iter()
+        .map(|(hash160, metadata)| (format!("{:x}", hash160), metadata))
+        .collect::<BTreeMap<_, _>>();
+    serde_json::json!(items)
+}
+
+fn is_watch_only_json(data: &serde_json::Value) -> bool {
+    data.get("watch_only").and_then(serde_json::Value::as_bool) == Some(true)
+}
+
+fn sub_ckb_roots_from_json(data: &serde_json::Value) -> Result<BTreeMap<u32, CkbRoot>, Error> {
+    match data
+        .get("sub_accounts")
+        .and_then(serde_json::Value::as_array)
+    {
+        Some(values) => values
+            .iter()
+            .map(|value| CkbRoot::from_json(value).map(|root| (root.account_index(), root)))
+            .collect(),
+        None => Ok(BTreeMap::default()),
+    }
+}
+
+fn key_filename(hash160: &H160) -> String {
+    let utc_now = Utc::now();
+    let date = utc_now.date();
+    let time = utc_now.time();
+    format!(
+        "UTC--{:04}-{:02}-{:02}T{:02}-{:02}-{:02}.{:09}Z--{:x}",
+        date.year(),
+        date.month(),
+        date.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+        time.nanosecond(),
+        hash160,
+    )
+}
+
 /// KeyStore protected by password
 #[derive(Clone)]
 struct PassphraseKeyStore {
     keys_dir_path: PathBuf,
-    scrypt_type: ScryptType,
+    kdf_type: KdfType,
 }
 
 impl PassphraseKeyStore {
@@ -397,8 +727,11 @@ impl PassphraseKeyStore {
     ) -> Result<Key, Error> {
         let filepath = self.join_path(filename);
         let mut file = fs::File::open(&filepath)?;
-        let data = serde_json::from_reader(&mut file)
+        let data: serde_json::Value = serde_json::from_reader(&mut file)
             .map_err(|err| Error::ParseJsonFailed(err.to_string()))?;
+        if is_watch_only_json(&data) {
+            return Err(Error::WatchOnly(hash160.clone()));
+        }
         let key = Key::from_json(&data, password)?;
         if key.hash160() != hash160 {
             return Err(Error::KeyMismatch {
@@ -415,11 +748,28 @@ impl PassphraseKeyStore {
         filename: P,
         key: &Key,
         password: &[u8],
+    ) -> Result<PathBuf, Error> {
+        self.store_key_with_kdf(filename, key, password, self.kdf_type)
+    }
+
+    fn store_key_with_kdf<P: AsRef<Path>>(
+        &self,
+        filename: P,
+        key: &Key,
+        password: &[u8],
+        kdf_type: KdfType,
     ) -> Result<PathBuf, Error> {
         let filepath = self.join_path(filename);
-        let json_value = key.to_json(password, self.scrypt_type);
-        let mut file = fs::File::create(&filepath)?;
+        let json_value = key.to_json_with_kdf(password, kdf_type)?;
+        // Write to a temporary file then rename it, the existing key file is kept if anything
+        // goes wrong. The temporary file name is not a key file name so it is never loaded.
+        let mut tmp_filename = filepath.file_name().expect("file_name").to_os_string();
+        tmp_filename.push(".tmp");
+        let tmp_filepath = filepath.with_file_name(tmp_filename);
+        let mut file = fs::File::create(&tmp_filepath)?;
         serde_json::to_writer(&mut file, &json_value).map_err(|err| Error::Io(err.to_string()))?;
+        file.sync_all()?;
+        fs::rename(&tmp_filepath, &filepath)?;
         Ok(filepath)
     }
 
@@ -433,6 +783,36 @@ impl PassphraseKeyStore {
     }
 }
 
+// Plain text information of an account, it is stored in the metadata file (not encrypted)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl AccountMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.label.is_none() && self.notes.is_none() && self.tags.is_empty()
+    }
+
+    // A label is used in place of lock arg, so it must not look like a hex string
+    pub fn check_label(label: &str) -> Result<(), Error> {
+        if label.is_empty() {
+            Err(Error::InvalidLabel(label.to_owned(), "empty label"))
+        } else if label.chars().any(char::is_whitespace) {
+            Err(Error::InvalidLabel(label.to_owned(), "contains whitespace"))
+        } else if label.starts_with("0x") {
+            Err(Error::InvalidLabel(label.to_owned(), "starts with 0x"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum KeyTimeout {
     Infinite,
@@ -459,7 +839,7 @@ impl fmt::Display for KeyTimeout {
                 format!("lock after: {}", time)
             }
             KeyTimeout::Timeout(_) => "locked".to_owned(),
-            KeyTimeout::Infinite => "locked after exit interactive mode".to_owned(),
+            KeyTimeout::Infinite => "locked after the keystore process exit".to_owned(),
         };
         write!(f, "{}", output)
     }
@@ -535,16 +915,56 @@ impl DerivedKeySet {
 
 #[derive(Clone)]
 pub struct CkbRoot {
-    pub path: &'static str,
+    pub path: String,
     extended_pubkey: ExtendedPubKey,
 }
 
 impl CkbRoot {
+    /// Build from the public key and chain code of path m/44'/309'/0'
+    pub fn new(public_key: secp256k1::PublicKey, chain_code: [u8; 32]) -> CkbRoot {
+        CkbRoot::new_with_index(0, public_key, chain_code)
+    }
+
+    /// Build from the public key and chain code of path m/44'/309'/<account_index>'
+    pub fn new_with_index(
+        account_index: u32,
+        public_key: secp256k1::PublicKey,
+        chain_code: [u8; 32],
+    ) -> CkbRoot {
+        let extended_pubkey = ExtendedPubKey {
+            depth: 3,
+            parent_fingerprint: Default::default(),
+            child_number: ChildNumber::from_hardened_idx(account_index).expect("child number"),
+            public_key,
+            chain_code: ChainCode(chain_code),
+        };
+        CkbRoot {
+            path: ckb_root_path(account_index),
+            extended_pubkey,
+        }
+    }
+
+    pub fn account_index(&self) -> u32 {
+        match self.extended_pubkey.child_number {
+            ChildNumber::Hardened { index } => index,
+            ChildNumber::Normal { .. } => unreachable!("ckb root is hardened"),
+        }
+    }
+
+    pub fn extended_pubkey(&self) -> &ExtendedPubKey {
+        &self.extended_pubkey
+    }
+
+    pub fn hash160(&self) -> H160 {
+        let pubkey = self.extended_pubkey.public_key;
+        H160::from_slice(&blake2b_256(&pubkey.serialize()[..])[0..20])
+            .expect("Generate hash(H160) from pubkey failed")
+    }
+
     pub fn to_json(&self) -> serde_json::Value {
         assert_eq!(self.extended_pubkey.depth, 3, "depth not 3");
-        assert_eq!(
-            self.extended_pubkey.child_number,
-            ChildNumber::from_hardened_idx(0).expect("child number"),
+        assert!(
+            self.extended_pubkey.child_number.is_hardened(),
             "child_number is wrong",
         );
         let pubkey_hex = hex_string(&self.extended_pubkey.public_key.serialize()[..]).expect("hex");
@@ -558,15 +978,15 @@ impl CkbRoot {
 
     pub fn from_json(value: &serde_json::Value) -> Result<CkbRoot, Error> {
         let path = util::get_str(value, "path")?;
-        if path != CKB_ROOT_PATH {
-            return Err(Error::ParseJsonFailed(format!(
-                "Invalid path for ckb root: {}",
-                path
-            )));
-        }
-        let depth = 3;
-        let parent_fingerprint = Default::default();
-        let child_number = ChildNumber::from_hardened_idx(0).expect("child number");
+        let account_index = path
+            .trim_start_matches("m/44'/309'/")
+            .trim_end_matches('\'')
+            .parse::<u32>()
+            .ok()
+            .filter(|index| ckb_root_path(*index) == path && *index < (1 << 31))
+            .ok_or_else(|| {
+                Error::ParseJsonFailed(format!("Invalid path for ckb root: {}", path))
+            })?;
         let pubkey_bin = util::get_hex_bin(value, "pubkey")?;
         let public_key = secp256k1::PublicKey::from_slice(&pubkey_bin[..]).map_err(|err| {
             Error::ParseJsonFailed(format!("Invalid pubkey for ckb root: {}", err))
@@ -580,19 +1000,11 @@ impl CkbRoot {
         }
         let mut chain_code = [0u8; 32];
         chain_code.copy_from_slice(&chain_code_bin[..]);
-        let extended_pubkey = ExtendedPubKey {
-            depth,
-            parent_fingerprint,
-            child_number,
+        Ok(CkbRoot::new_with_index(
+            account_index,
             public_key,
-            chain_code: ChainCode(chain_code),
-        };
-
-        // let pubkey
-        Ok(CkbRoot {
-            path: CKB_ROOT_PATH,
-            extended_pubkey,
-        })
+            chain_code,
+        ))
     }
 
     pub fn derived_key_set(
@@ -663,7 +1075,7 @@ impl CkbRoot {
             .extended_pubkey
             .derive_pub(&SECP256K1, &path)
             .expect("derive_pub");
-        let full_path_string = format!("{}/{}/{}", CKB_ROOT_PATH, chain as u8, index);
+        let full_path_string = format!("{}/{}/{}", self.path, chain as u8, index);
         let full_path =
             DerivationPath::from_str(full_path_string.as_str()).expect("parse full path");
         (full_path, extended_pubkey)
@@ -683,6 +1095,59 @@ impl CkbRoot {
     }
 }
 
+// The keystore json formats can be imported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeystoreFormat {
+    // Created by ckb-cli, with `hash160` and `ckb_root` fields
+    CkbCli,
+    // Created by Neuron, the ciphertext is the master private key and chain code
+    Neuron,
+    // Ethereum V3 keystore, the ciphertext is a single secp256k1 private key
+    EthereumV3,
+}
+
+impl KeystoreFormat {
+    pub fn detect(data: &serde_json::Value) -> Result<KeystoreFormat, Error> {
+        if data.get("hash160").is_some()
+            || data.get("origin").and_then(serde_json::Value::as_str) == Some(KEYSTORE_ORIGIN)
+        {
+            return Ok(KeystoreFormat::CkbCli);
+        }
+        let ciphertext = util::get_hex_bin(keystore_crypto(data)?, "ciphertext")?;
+        match ciphertext.len() {
+            64 => Ok(KeystoreFormat::Neuron),
+            32 => Ok(KeystoreFormat::EthereumV3),
+            len => Err(Error::ParseJsonFailed(format!(
+                "Unknown keystore format, ciphertext length: {}",
+                len
+            ))),
+        }
+    }
+
+    // The key is a BIP-32 master key, accounts are derived from path m/44'/309'/0'
+    pub fn is_hd(self) -> bool {
+        self != KeystoreFormat::EthereumV3
+    }
+}
+
+impl fmt::Display for KeystoreFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            KeystoreFormat::CkbCli => "ckb-cli",
+            KeystoreFormat::Neuron => "neuron",
+            KeystoreFormat::EthereumV3 => "ethereum-v3",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Some old Ethereum keystore files use `Crypto` as the field name
+fn keystore_crypto(data: &serde_json::Value) -> Result<&serde_json::Value, Error> {
+    data.get("crypto")
+        .or_else(|| data.get("Crypto"))
+        .ok_or_else(|| Error::ParseJsonFailed("crypto field not found".to_owned()))
+}
+
 pub struct Key {
     // randomly generate uuid v4
     id: Uuid,
@@ -690,6 +1155,10 @@ pub struct Key {
     hash160: H160,
     // The extended secp256k1 private key (privkey + chaincode)
     master_privkey: MasterPrivKey,
+    // The BIP-39 entropy when the key is generated from mnemonic words
+    mnemonic_entropy: Option<MnemonicEntropy>,
+    // Added BIP-44 account indices other than 0
+    account_indices: BTreeSet<u32>,
 }
 
 impl Key {
@@ -700,12 +1169,54 @@ impl Key {
             id,
             hash160,
             master_privkey,
+            mnemonic_entropy: None,
+            account_indices: BTreeSet::default(),
         }
     }
 
+    // The master key is derived from the BIP-39 seed of the mnemonic and passphrase
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<Key, Error> {
+        let mnemonic = Mnemonic::from_phrase(phrase, Language::English)
+            .map_err(|err| Error::InvalidMnemonic(err.to_string()))?;
+        let seed = Seed::new(&mnemonic, passphrase);
+        let master_privkey = MasterPrivKey::from_seed(seed.as_bytes())?;
+        let mut key = Key::new(master_privkey);
+        key.mnemonic_entropy = Some(MnemonicEntropy(mnemonic.entropy().to_vec()));
+        Ok(key)
+    }
+
+    pub fn mnemonic(&self) -> Result<Option<String>, Error> {
+        self.mnemonic_entropy
+            .as_ref()
+            .map(|entropy| {
+                Mnemonic::from_entropy(&entropy.0, Language::English)
+                    .map(Mnemonic::into_phrase)
+                    .map_err(|err| Error::InvalidMnemonic(err.to_string()))
+            })
+            .transpose()
+    }
+
     pub fn ckb_root(&self) -> CkbRoot {
         self.master_privkey.ckb_root()
     }
+    pub fn ckb_root_by_index(&self, account_index: u32) -> CkbRoot {
+        self.master_privkey.ckb_root_by_index(account_index)
+    }
+    pub fn account_indices(&self) -> &BTreeSet<u32> {
+        &self.account_indices
+    }
+    pub fn add_account_index(&mut self, account_index: u32) -> Result<(), Error> {
+        if account_index >= (1 << 31) {
+            return Err(Error::Other(format!(
+                "Invalid account index: {}",
+                account_index
+            )));
+        }
+        if account_index > 0 {
+            self.account_indices.insert(account_index);
+        }
+        Ok(())
+    }
     pub fn master_privkey(&self) -> &MasterPrivKey {
         &self.master_privkey
     }
@@ -714,20 +1225,7 @@ impl Key {
     }
 
     pub fn filename(&self) -> String {
-        let utc_now = Utc::now();
-        let date = utc_now.date();
-        let time = utc_now.time();
-        format!(
-            "UTC--{:04}-{:02}-{:02}T{:02}-{:02}-{:02}.{:09}Z--{:x}",
-            date.year(),
-            date.month(),
-            date.day(),
-            time.hour(),
-            time.minute(),
-            time.second(),
-            time.nanosecond(),
-            self.hash160(),
-        )
+        key_filename(self.hash160())
     }
 
     pub fn from_json(data: &serde_json::Value, password: &[u8]) -> Result<Key, Error> {
@@ -756,29 +1254,112 @@ impl Key {
         key_bytes[..].copy_from_slice(&key_vec[..]);
         let master_privkey = MasterPrivKey::from_bytes(key_bytes)?;
 
+        let mnemonic_entropy = match data.get("mnemonic") {
+            Some(value) => {
+                let crypto = Crypto::from_json(value)?;
+                let entropy = MnemonicEntropy(crypto.decrypt(password)?);
+                if ![16, 20, 24, 28, 32].contains(&entropy.0.len()) {
+                    return Err(Error::ParseJsonFailed(format!(
+                        "Invalid mnemonic entropy length: {}",
+                        entropy.0.len()
+                    )));
+                }
+                Some(entropy)
+            }
+            None => None,
+        };
+
+        let account_indices = sub_ckb_roots_from_json(data)?.keys().cloned().collect();
+
         let hash160 = master_privkey.hash160(&[]);
         Ok(Key {
             id,
             hash160,
             master_privkey,
+            mnemonic_entropy,
+            account_indices,
         })
     }
 
+    // Load a keystore json of any supported format
+    pub fn from_keystore_json(
+        data: &serde_json::Value,
+        password: &[u8],
+    ) -> Result<(Key, KeystoreFormat), Error> {
+        let format = KeystoreFormat::detect(data)?;
+        let key = match format {
+            KeystoreFormat::CkbCli | KeystoreFormat::Neuron => Key::from_json(data, password)?,
+            KeystoreFormat::EthereumV3 => {
+                let crypto = Crypto::from_json(keystore_crypto(data)?)?;
+                let secret = crypto.decrypt(password)?;
+                let secp_secret_key = secp256k1::SecretKey::from_slice(&secret)
+                    .map_err(|_| Error::InvalidSecpSecret)?;
+                if let Some(address) = data.get("address").and_then(serde_json::Value::as_str) {
+                    let pubkey =
+                        secp256k1::PublicKey::from_secret_key(&SECP256K1, &secp_secret_key);
+                    let pubkey_hash = tiny_keccak::keccak256(&pubkey.serialize_uncompressed()[1..]);
+                    let expected = hex_string(&pubkey_hash[12..]).expect("hex string");
+                    if address.trim_start_matches("0x").to_lowercase() != expected {
+                        return Err(Error::ParseJsonFailed(format!(
+                            "Ethereum address mismatch, got: {}, expected: 0x{}",
+                            address, expected
+                        )));
+                    }
+                }
+                // Same default chain code as importing a raw private key
+                let mut bytes = [255u8; 64];
+                bytes[0..32].copy_from_slice(&secp_secret_key[..]);
+                Key::new(MasterPrivKey::from_bytes(bytes)?)
+            }
+        };
+        Ok((key, format))
+    }
+
     pub fn to_json(&self, password: &[u8], scrypt_type: ScryptType) -> serde_json::Value {
+        self.to_json_with_kdf(password, scrypt_type.into())
+            .expect("encrypt key scrypt failed")
+    }
+
+    pub fn to_json_with_kdf(
+        &self,
+        password: &[u8],
+        kdf_type: KdfType,
+    ) -> Result<serde_json::Value, Error> {
         let mut buf = Uuid::encode_buffer();
         let id_str = self.id.to_hyphenated().encode_lower(&mut buf);
         let hash160_hex = format!("{:x}", self.hash160);
         let master_privkey = self.master_privkey.to_bytes();
-        let crypto = Crypto::encrypt_key_scrypt(&master_privkey, password, scrypt_type);
+        let crypto = Crypto::encrypt_key_with_kdf(&master_privkey, password, kdf_type)?;
         let ckb_root = self.master_privkey.ckb_root();
-        serde_json::json!({
+        let mut json_value = serde_json::json!({
             "origin": KEYSTORE_ORIGIN,
             "id": id_str,
             "version": KEYSTORE_VERSION,
             "hash160": hash160_hex,
             "crypto": crypto.to_json(),
             "ckb_root": ckb_root.to_json(),
-        })
+        });
+        if !self.account_indices.is_empty() {
+            json_value["sub_accounts"] = self
+                .account_indices
+                .iter()
+                .map(|index| self.ckb_root_by_index(*index).to_json())
+                .collect::<Vec<_>>()
+                .into();
+        }
+        if let Some(entropy) = self.mnemonic_entropy.as_ref() {
+            let mnemonic_crypto = Crypto::encrypt_key_with_kdf(&entropy.0, password, kdf_type)?;
+            json_value["mnemonic"] = mnemonic_crypto.to_json();
+        }
+        Ok(json_value)
+    }
+}
+
+struct MnemonicEntropy(Vec<u8>);
+
+impl Drop for MnemonicEntropy {
+    fn drop(&mut self) {
+        zeroize_slice(&mut self.0);
     }
 }
 
@@ -814,6 +1395,16 @@ impl MasterPrivKey {
         }
     }
 
+    // See: BIP-32 master key generation
+    pub fn from_seed(seed: &[u8]) -> Result<MasterPrivKey, Error> {
+        let extended_privkey =
+            ExtendedPrivKey::new_master(seed).map_err(|_| Error::InvalidSecpSecret)?;
+        Ok(MasterPrivKey {
+            secp_secret_key: extended_privkey.private_key,
+            chain_code: extended_privkey.chain_code.0,
+        })
+    }
+
     pub fn from_bytes(bytes: [u8; 64]) -> Result<MasterPrivKey, Error> {
         let secp_secret_key = secp256k1::SecretKey::from_slice(&bytes[0..32])
             .map_err(|_| Error::InvalidSecpSecret)?;
@@ -876,10 +1467,15 @@ impl MasterPrivKey {
     }
 
     pub fn ckb_root(&self) -> CkbRoot {
-        let path = DerivationPath::from_str(CKB_ROOT_PATH).expect("parse ckb root path");
+        self.ckb_root_by_index(0)
+    }
+
+    pub fn ckb_root_by_index(&self, account_index: u32) -> CkbRoot {
+        let path_string = ckb_root_path(account_index);
+        let path = DerivationPath::from_str(&path_string).expect("parse ckb root path");
         let extended_pubkey = self.extended_pubkey(&path);
         CkbRoot {
-            path: CKB_ROOT_PATH,
+            path: path_string,
             extended_pubkey,
         }
     }
@@ -958,4 +1554,253 @@ mod tests {
         assert_eq!(key_set, key_set_by_index);
         assert_eq!(key_set, expected_key_set);
     }
+
+    #[test]
+    fn test_watch_only_ckb_root() {
+        let mut data = [0u8; 64];
+        data[0..32].copy_from_slice(
+            h256!("0xd00c06bfd800d27397002dca6fb0993d5ba6399b4238b2f29ee9deb97593d2bc").as_bytes(),
+        );
+        data[32..64].copy_from_slice(
+            h256!("0xcf4ebc9849b0466e82b82075b6b2ffa0b13f85e0825859996776a7350734024a").as_bytes(),
+        );
+        let ckb_root = MasterPrivKey::from_bytes(data).unwrap().ckb_root();
+        let extended_pubkey = ckb_root.extended_pubkey();
+        let watch_only_root =
+            CkbRoot::new(extended_pubkey.public_key, extended_pubkey.chain_code.0);
+        assert_eq!(watch_only_root.to_json(), ckb_root.to_json());
+        assert_eq!(
+            watch_only_root.derived_key_set_by_index(0, 5, 0, 5),
+            ckb_root.derived_key_set_by_index(0, 5, 0, 5)
+        );
+    }
+
+    #[test]
+    fn test_mnemonic_vectors() {
+        // From BIP-39 test vectors (passphrase: "TREZOR")
+        let vectors = vec![
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+            (
+                "80808080808080808080808080808080",
+                "letter advice cage absurd amount doctor acoustic avoid letter advice cage above",
+                "d71de856f81a8acc65e6fc851a38d4d7ec216fd0796d0a6827a3ad6ed5511a30fa280f12eb2e47ed2ac03b5c462a0358d18d69fe4f985ec81778c1b370b652a8",
+            ),
+            (
+                "ffffffffffffffffffffffffffffffff",
+                "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+                "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f0f8e5a589620c6f15b11c61dee327651a14c34e18231052e48c069",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+                "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+            ),
+        ];
+        for (entropy_hex, phrase, seed_hex) in vectors {
+            let mut entropy = vec![0u8; entropy_hex.len() / 2];
+            hex_decode(entropy_hex.as_bytes(), &mut entropy).unwrap();
+            let mut seed = vec![0u8; 64];
+            hex_decode(seed_hex.as_bytes(), &mut seed).unwrap();
+
+            let mnemonic = Mnemonic::from_entropy(&entropy, Language::English).unwrap();
+            assert_eq!(mnemonic.phrase(), phrase);
+            assert_eq!(Seed::new(&mnemonic, "TREZOR").as_bytes(), &seed[..]);
+
+            let key = Key::from_mnemonic(phrase, "TREZOR").unwrap();
+            let expected = MasterPrivKey::from_seed(&seed).unwrap();
+            assert_eq!(
+                &key.master_privkey().to_bytes()[..],
+                &expected.to_bytes()[..]
+            );
+            assert_eq!(key.mnemonic().unwrap().as_deref(), Some(phrase));
+        }
+    }
+
+    #[test]
+    fn test_mnemonic_key() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let key = Key::from_mnemonic(phrase, "").unwrap();
+        let bytes = key.master_privkey().to_bytes();
+        assert_eq!(
+            H256::from_slice(&bytes[0..32]).unwrap(),
+            h256!("0x1837c1be8e2995ec11cda2b066151be2cfb48adf9e47b151d46adab3a21cdf67")
+        );
+        assert_eq!(
+            H256::from_slice(&bytes[32..64]).unwrap(),
+            h256!("0x7923408dadd3c7b56eed15567707ae5e5dca089de972e07f3b860450e2a3b70e")
+        );
+        assert_eq!(
+            key.hash160(),
+            &h160!("0xc439c37837473519841d67c4a147505760eda082")
+        );
+
+        // The mnemonic is kept after encrypt/decrypt the key json
+        let json_value = key.to_json(b"123", ScryptType::Light);
+        let loaded_key = Key::from_json(&json_value, b"123").unwrap();
+        assert_eq!(loaded_key.hash160(), key.hash160());
+        assert_eq!(loaded_key.mnemonic().unwrap().as_deref(), Some(phrase));
+
+        // Both the master key and the mnemonic are encrypted by the given KDF
+        let kdf_type = KdfType::Argon2id { m: 64, t: 1, p: 1 };
+        let json_value = key.to_json_with_kdf(b"123", kdf_type).unwrap();
+        assert_eq!(json_value["crypto"]["kdf"], "argon2id");
+        assert_eq!(json_value["mnemonic"]["kdf"], "argon2id");
+        let loaded_key = Key::from_json(&json_value, b"123").unwrap();
+        assert_eq!(loaded_key.mnemonic().unwrap().as_deref(), Some(phrase));
+
+        let secp_key = MasterPrivKey::from_bytes(bytes).unwrap();
+        assert_eq!(Key::new(secp_key).mnemonic(), Ok(None));
+
+        assert!(Key::from_mnemonic("abandon abandon abandon", "").is_err());
+    }
+    #[test]
+    fn test_import_keystore_formats() {
+        let secret = h256!("0x7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d");
+        let crypto = Crypto::encrypt_key_scrypt(secret.as_bytes(), b"123", ScryptType::Light);
+        let mut eth_json = serde_json::json!({
+            "address": "008aeeda4d805471df9b2a5b0f38a0c3bcba786b",
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3,
+            "crypto": crypto.to_json(),
+        });
+        let (key, format) = Key::from_keystore_json(&eth_json, b"123").unwrap();
+        assert_eq!(format, KeystoreFormat::EthereumV3);
+        assert_eq!(
+            key.hash160(),
+            &h160!("0xcd4d8da37be6003dda9894112640b1bdbf97022b")
+        );
+        eth_json["address"] = "008aeeda4d805471df9b2a5b0f38a0c3bcba7800".into();
+        assert!(Key::from_keystore_json(&eth_json, b"123").is_err());
+
+        let key = Key::new(MasterPrivKey::try_new(1024).unwrap());
+        let mut json_value = key.to_json(b"123", ScryptType::Light);
+        let (cli_key, format) = Key::from_keystore_json(&json_value, b"123").unwrap();
+        assert_eq!(format, KeystoreFormat::CkbCli);
+        assert_eq!(cli_key.hash160(), key.hash160());
+
+        // Neuron keystore has no ckb-cli specific fields
+        let object = json_value.as_object_mut().unwrap();
+        object.remove("origin");
+        object.remove("hash160");
+        object.remove("ckb_root");
+        let (neuron_key, format) = Key::from_keystore_json(&json_value, b"123").unwrap();
+        assert_eq!(format, KeystoreFormat::Neuron);
+        assert_eq!(neuron_key.hash160(), key.hash160());
+        assert!(Key::from_keystore_json(&json_value, b"456").is_err());
+    }
+    #[test]
+    fn test_account_indices() {
+        let mut key = Key::new(MasterPrivKey::try_new(1024).unwrap());
+        key.add_account_index(0).unwrap();
+        key.add_account_index(2).unwrap();
+        assert!(key.add_account_index(1 << 31).is_err());
+        assert_eq!(key.account_indices().iter().collect::<Vec<_>>(), vec![&2]);
+
+        let json_value = key.to_json(b"123", ScryptType::Light);
+        let loaded_key = Key::from_json(&json_value, b"123").unwrap();
+        assert_eq!(loaded_key.account_indices(), key.account_indices());
+        let sub_roots = sub_ckb_roots_from_json(&json_value).unwrap();
+        let ckb_root = &sub_roots[&2];
+        assert_eq!(ckb_root.path, "m/44'/309'/2'");
+        assert_eq!(ckb_root.account_index(), 2);
+        assert_eq!(ckb_root.to_json(), key.ckb_root_by_index(2).to_json());
+
+        let (path, hash160) = ckb_root.derived_hash160(KeyChain::Change, 3);
+        assert_eq!(path.to_string(), "m/44'/309'/2'/1/3");
+        assert_eq!(hash160, key.master_privkey().hash160(&path));
+        assert_ne!(
+            key.ckb_root().derived_hash160(KeyChain::Change, 3).1,
+            hash160
+        );
+    }
+
+    #[test]
+    fn test_timed_key() {
+        let key = Key::new(MasterPrivKey::try_new(1024).unwrap());
+        let mut timed_key = TimedKey::new_timed(key, Duration::from_secs(0));
+        assert!(timed_key.is_expired());
+        timed_key.extend(Some(Duration::from_secs(60)));
+        assert!(!timed_key.is_expired());
+        timed_key.extend(None);
+        assert!(!timed_key.is_expired());
+        assert!(matches!(timed_key.timeout, KeyTimeout::Infinite));
+    }
+
+    #[test]
+    fn test_account_metadata() {
+        assert!(AccountMetadata::check_label("savings").is_ok());
+        assert!(AccountMetadata::check_label("").is_err());
+        assert!(AccountMetadata::check_label("my savings").is_err());
+        assert!(AccountMetadata::check_label("0xsavings").is_err());
+
+        let metadata = AccountMetadata {
+            label: Some("savings".to_owned()),
+            notes: None,
+            tags: vec!["cold".to_owned()],
+        };
+        let value = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"label": "savings", "tags": ["cold"]})
+        );
+        let loaded: AccountMetadata = serde_json::from_value(value).unwrap();
+        assert_eq!(loaded, metadata);
+        assert!(AccountMetadata::default().is_empty());
+
+        let hash160 = h160!("0xe8b7cfc565396a49efe154e81fe02c2bca9f3621");
+        let mut all_metadata = HashMap::default();
+        all_metadata.insert(hash160.clone(), metadata);
+        let value = metadata_to_json(&all_metadata);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "e8b7cfc565396a49efe154e81fe02c2bca9f3621": {"label": "savings", "tags": ["cold"]}
+            })
+        );
+        assert_eq!(metadata_from_json(value).unwrap(), all_metadata);
+        assert!(metadata_from_json(serde_json::json!({"0x00": {}})).is_err());
+    }
+
+    #[test]
+    fn test_add_account_index() {
+        let dir = std::env::temp_dir().join(format!(
+            "ckb-sdk-keystore-account-index-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut key_store = KeyStore::from_dir(dir.clone(), ScryptType::Light).unwrap();
+        let hash160 = key_store.new_account(b"123").unwrap();
+        assert!(key_store.add_account_index(&hash160, 1, b"456").is_err());
+        let ckb_root = key_store.add_account_index(&hash160, 1, b"123").unwrap();
+
+        // The key file is replaced by rename, no temporary file is left
+        let filenames = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(filenames.len(), 1);
+        assert!(!filenames[0].ends_with(".tmp"));
+
+        let mut key_store = KeyStore::from_dir(dir.clone(), ScryptType::Light).unwrap();
+        let sub_roots = key_store.get_sub_ckb_roots(&hash160, false);
+        assert_eq!(
+            sub_roots
+                .into_iter()
+                .map(|root| root.path)
+                .collect::<Vec<_>>(),
+            vec![ckb_root.path]
+        );
+        assert!(key_store.export_key(&hash160, b"123").is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
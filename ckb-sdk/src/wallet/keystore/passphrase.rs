@@ -4,15 +4,18 @@
 use aes_ctr::stream_cipher::generic_array::GenericArray;
 use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
 use aes_ctr::Aes128Ctr;
+use bitcoin_hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
 use faster_hex::hex_string;
 use rand::Rng;
 
 use super::error::Error;
 use super::util;
 
-const SUPPORT_KDF_TYPE: &str = "scrypt";
+const KDF_TYPE_SCRYPT: &str = "scrypt";
+const KDF_TYPE_PBKDF2: &str = "pbkdf2";
+const KDF_TYPE_ARGON2ID: &str = "argon2id";
 const SUPPORT_CIPHER_TYPE: &str = "aes-128-ctr";
-// const SUPPORT_PBKDF2_PRF: &str = "hmac-sha256";
+const SUPPORT_PBKDF2_PRF: &str = "hmac-sha256";
 
 const SCRYPT_DK_STD_LOG_N: u8 = 18;
 const SCRYPT_DK_STD_P: u32 = 1;
@@ -21,6 +24,16 @@ const SCRYPT_DK_LIGHT_P: u32 = 6;
 const SCRYPT_DK_R: u32 = 8;
 const SCRYPT_DK_LEN: u32 = 32;
 
+// Memory cost in KiB (64 MiB)
+const ARGON2_DK_STD_M: u32 = 65536;
+const ARGON2_DK_STD_T: u32 = 3;
+const ARGON2_DK_STD_P: u32 = 4;
+// Upper bounds of the parameters read from key file, a crafted key file must not exhaust the
+// memory or CPU when decrypting (4 GiB, 64 passes, 64 lanes)
+const ARGON2_MAX_M: u32 = 4 * 1024 * 1024;
+const ARGON2_MAX_T: u32 = 64;
+const ARGON2_MAX_P: u32 = 64;
+
 // Example:
 // ========
 // {
@@ -176,8 +189,8 @@ impl Default for ScryptType {
 //     "version" : 3
 // }
 //
-// TODO: supported pbkdf2
-#[allow(dead_code)]
+// Only used for decrypting keystore files from other wallets, new keys are encrypted by scrypt
+// or argon2id.
 #[derive(Debug, Clone)]
 pub struct Pbkdf2Params {
     prf: &'static str,
@@ -186,10 +199,60 @@ pub struct Pbkdf2Params {
     dklen: u32,
 }
 
-#[allow(dead_code)]
 impl Pbkdf2Params {
-    fn kdf_key(&self, _password: &[u8]) -> [u8; 32] {
-        unimplemented!()
+    // PBKDF2-HMAC-SHA256, the output is exactly one block
+    fn kdf_key(&self, password: &[u8]) -> [u8; 32] {
+        let hmac = |data: &[&[u8]]| {
+            let mut engine: HmacEngine<sha256::Hash> = HmacEngine::new(password);
+            for item in data {
+                engine.input(item);
+            }
+            let mut output = [0u8; 32];
+            output.copy_from_slice(&Hmac::from_engine(engine)[..]);
+            output
+        };
+        let mut block = hmac(&[&self.salt[..], &1u32.to_be_bytes()[..]]);
+        let mut output = block;
+        for _ in 1..self.c {
+            block = hmac(&[&block[..]]);
+            for (out, byte) in output.iter_mut().zip(block.iter()) {
+                *out ^= byte;
+            }
+        }
+        output
+    }
+
+    pub fn from_json(value: &serde_json::Value) -> Result<Pbkdf2Params, Error> {
+        let prf = util::get_str(value, "prf").and_then(|prf_str| {
+            if prf_str == SUPPORT_PBKDF2_PRF {
+                Ok(SUPPORT_PBKDF2_PRF)
+            } else {
+                Err(Error::UnsupportedKdf(format!(
+                    "pbkdf2 with prf {}",
+                    prf_str
+                )))
+            }
+        })?;
+        let salt_bin = util::get_hex_bin(value, "salt")?;
+        if salt_bin.len() != 32 {
+            return Err(Error::ParseJsonFailed("Invalid pbkdf2 salt".to_owned()));
+        }
+        let mut salt = [0u8; 32];
+        salt[..].copy_from_slice(&salt_bin[..]);
+        let c = util::get_u64(value, "c")? as usize;
+        let dklen = util::get_u64(value, "dklen")? as u32;
+        if dklen != SCRYPT_DK_LEN {
+            return Err(Error::ParseJsonFailed(format!(
+                "Unsupported pbkdf2 dklen: {}",
+                dklen
+            )));
+        }
+        Ok(Pbkdf2Params {
+            prf,
+            salt,
+            c,
+            dklen,
+        })
     }
 
     pub fn to_json(&self) -> serde_json::Value {
@@ -203,11 +266,148 @@ impl Pbkdf2Params {
     }
 }
 
+// Argon2id (version 0x13) without secret and associated data
+//
+// Example:
+// ========
+// {
+//     "kdf": "argon2id",
+//     "kdfparams": {
+//         "salt": "be3d86c99f4895f99d1a0048afb61a34153fa83d5edd033fc914de2c502f57e7",
+//         "m": 65536,
+//         "t": 3,
+//         "p": 4,
+//         "dklen": 32
+//     }
+// }
+#[derive(Debug, Clone)]
+pub struct Argon2Params {
+    salt: [u8; 32],
+    // Memory cost in KiB
+    m: u32,
+    // Number of passes
+    t: u32,
+    // Degree of parallelism (lanes)
+    p: u32,
+    dklen: u32,
+}
+
+impl Argon2Params {
+    pub fn new(m: u32, t: u32, p: u32) -> Result<Argon2Params, Error> {
+        Self::new_with_salt(rand::thread_rng().gen(), m, t, p)
+    }
+
+    pub fn new_with_salt(salt: [u8; 32], m: u32, t: u32, p: u32) -> Result<Argon2Params, Error> {
+        Self::check(m, t, p)?;
+        Ok(Argon2Params {
+            salt,
+            m,
+            t,
+            p,
+            dklen: SCRYPT_DK_LEN,
+        })
+    }
+
+    fn check(m: u32, t: u32, p: u32) -> Result<(), Error> {
+        if t == 0 {
+            Err(Error::InvalidKdfParams(
+                "argon2id t must be positive".to_owned(),
+            ))
+        } else if p == 0 || p > 0x00ff_ffff {
+            Err(Error::InvalidKdfParams(format!(
+                "argon2id p out of range: {}",
+                p
+            )))
+        } else if u64::from(m) < 8 * u64::from(p) {
+            Err(Error::InvalidKdfParams(format!(
+                "argon2id m must be at least 8 * p, got m={}, p={}",
+                m, p
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn kdf_key(&self, password: &[u8]) -> Result<[u8; 32], Error> {
+        let config = argon2::Config {
+            ad: &[],
+            hash_length: self.dklen,
+            lanes: self.p,
+            mem_cost: self.m,
+            secret: &[],
+            thread_mode: argon2::ThreadMode::Sequential,
+            time_cost: self.t,
+            variant: argon2::Variant::Argon2id,
+            version: argon2::Version::Version13,
+        };
+        let hash = argon2::hash_raw(password, &self.salt, &config)
+            .map_err(|err| Error::InvalidKdfParams(err.to_string()))?;
+        let mut output = [0u8; 32];
+        output.copy_from_slice(&hash);
+        Ok(output)
+    }
+
+    pub fn from_json(value: &serde_json::Value) -> Result<Argon2Params, Error> {
+        let salt_bin = util::get_hex_bin(value, "salt")?;
+        if salt_bin.len() != 32 {
+            return Err(Error::ParseJsonFailed("Invalid argon2id salt".to_owned()));
+        }
+        let mut salt = [0u8; 32];
+        salt[..].copy_from_slice(&salt_bin[..]);
+        let get_u32 = |field: &str| {
+            let value = util::get_u64(value, field)?;
+            if value > u64::from(u32::max_value()) {
+                Err(Error::ParseJsonFailed(format!(
+                    "argon2id {} too large: {}",
+                    field, value
+                )))
+            } else {
+                Ok(value as u32)
+            }
+        };
+        let m = get_u32("m")?;
+        let t = get_u32("t")?;
+        let p = get_u32("p")?;
+        let dklen = get_u32("dklen")?;
+        if dklen != SCRYPT_DK_LEN {
+            return Err(Error::ParseJsonFailed(format!(
+                "Unsupported argon2id dklen: {}",
+                dklen
+            )));
+        }
+        Self::check(m, t, p).map_err(|err| Error::ParseJsonFailed(err.to_string()))?;
+        if m > ARGON2_MAX_M || t > ARGON2_MAX_T || p > ARGON2_MAX_P {
+            return Err(Error::InvalidKdfParams(format!(
+                "argon2id parameters too large: m={}, t={}, p={} (max: m={}, t={}, p={})",
+                m, t, p, ARGON2_MAX_M, ARGON2_MAX_T, ARGON2_MAX_P
+            )));
+        }
+        Ok(Argon2Params {
+            salt,
+            m,
+            t,
+            p,
+            dklen,
+        })
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let salt_hex = hex_string(&self.salt).unwrap();
+        serde_json::json!({
+            "salt": salt_hex,
+            "m": self.m,
+            "t": self.t,
+            "p": self.p,
+            "dklen": self.dklen,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum KdfParams {
     Scrypt(ScryptParams),
-    #[allow(dead_code)]
     Pbkdf2(Pbkdf2Params),
+    Argon2id(Argon2Params),
 }
 
 impl Default for KdfParams {
@@ -217,24 +417,154 @@ impl Default for KdfParams {
 }
 
 impl KdfParams {
+    pub fn new(kdf_type: KdfType) -> Result<KdfParams, Error> {
+        match kdf_type {
+            KdfType::Scrypt(scrypt_type) => Ok(KdfParams::Scrypt(ScryptParams::new(scrypt_type))),
+            KdfType::Argon2id { m, t, p } => Argon2Params::new(m, t, p).map(KdfParams::Argon2id),
+        }
+    }
+
     pub fn to_json(&self) -> serde_json::Value {
         match self {
             KdfParams::Scrypt(params) => params.to_json(),
             KdfParams::Pbkdf2(params) => params.to_json(),
+            KdfParams::Argon2id(params) => params.to_json(),
         }
     }
 
     pub fn from_json(value: &serde_json::Value, kdf: &str) -> Result<KdfParams, Error> {
         match kdf {
-            "scrypt" => ScryptParams::from_json(value).map(KdfParams::Scrypt),
+            KDF_TYPE_SCRYPT => ScryptParams::from_json(value).map(KdfParams::Scrypt),
+            KDF_TYPE_PBKDF2 => Pbkdf2Params::from_json(value).map(KdfParams::Pbkdf2),
+            KDF_TYPE_ARGON2ID => Argon2Params::from_json(value).map(KdfParams::Argon2id),
             _ => Err(Error::UnsupportedKdf(kdf.to_owned())),
         }
     }
 
+    pub fn kdf(&self) -> &'static str {
+        match self {
+            KdfParams::Scrypt(_) => KDF_TYPE_SCRYPT,
+            KdfParams::Pbkdf2(_) => KDF_TYPE_PBKDF2,
+            KdfParams::Argon2id(_) => KDF_TYPE_ARGON2ID,
+        }
+    }
+
     fn kdf_key(&self, password: &[u8]) -> Result<[u8; 32], Error> {
         match self {
             KdfParams::Scrypt(params) => Ok(params.kdf_key(password)),
-            KdfParams::Pbkdf2(_) => Err(Error::UnsupportedKdf("pbkdf2".to_owned())),
+            KdfParams::Pbkdf2(params) => Ok(params.kdf_key(password)),
+            KdfParams::Argon2id(params) => params.kdf_key(password),
+        }
+    }
+}
+
+// The KDF (and its cost parameters) used when encrypting new key files
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KdfType {
+    Scrypt(ScryptType),
+    // m: memory cost in KiB, t: number of passes, p: degree of parallelism
+    Argon2id { m: u32, t: u32, p: u32 },
+}
+
+impl Default for KdfType {
+    fn default() -> KdfType {
+        KdfType::Scrypt(ScryptType::default())
+    }
+}
+
+impl From<ScryptType> for KdfType {
+    fn from(scrypt_type: ScryptType) -> KdfType {
+        KdfType::Scrypt(scrypt_type)
+    }
+}
+
+impl KdfType {
+    pub fn argon2id_standard() -> KdfType {
+        KdfType::Argon2id {
+            m: ARGON2_DK_STD_M,
+            t: ARGON2_DK_STD_T,
+            p: ARGON2_DK_STD_P,
+        }
+    }
+
+    // Parse kdf name and optional comma separated parameters, example:
+    //   * scrypt: "n=262144,p=1"
+    //   * argon2id: "m=65536,t=3,p=4"
+    // The parameters not given use the standard value.
+    pub fn parse(kdf: &str, params: Option<&str>) -> Result<KdfType, Error> {
+        let mut items = Vec::new();
+        for item in params
+            .unwrap_or("")
+            .split(',')
+            .filter(|item| !item.is_empty())
+        {
+            let mut parts = item.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| Error::InvalidKdfParams(format!("missing value: {}", item)))?
+                .trim()
+                .parse::<u32>()
+                .map_err(|err| Error::InvalidKdfParams(format!("{}: {}", item, err)))?;
+            items.push((name, value));
+        }
+        match kdf {
+            KDF_TYPE_SCRYPT => {
+                let (mut n, mut p) = (1u32 << SCRYPT_DK_STD_LOG_N, SCRYPT_DK_STD_P);
+                for (name, value) in items {
+                    match name {
+                        "n" => n = value,
+                        "p" => p = value,
+                        _ => {
+                            return Err(Error::InvalidKdfParams(format!(
+                                "unknown scrypt parameter: {}",
+                                name
+                            )))
+                        }
+                    }
+                }
+                if n < 2 || !n.is_power_of_two() {
+                    return Err(Error::InvalidKdfParams(format!(
+                        "scrypt n must be a power of two: {}",
+                        n
+                    )));
+                }
+                let log_n = n.trailing_zeros() as u8;
+                scrypt::ScryptParams::new(log_n, SCRYPT_DK_R, p).map_err(|_| {
+                    Error::InvalidKdfParams(format!("invalid scrypt parameters: n={}, p={}", n, p))
+                })?;
+                if (log_n, p) == (SCRYPT_DK_STD_LOG_N, SCRYPT_DK_STD_P) {
+                    Ok(KdfType::Scrypt(ScryptType::Standard))
+                } else {
+                    Ok(KdfType::Scrypt(ScryptType::Custom { log_n, p }))
+                }
+            }
+            KDF_TYPE_ARGON2ID => {
+                let (mut m, mut t, mut p) = (ARGON2_DK_STD_M, ARGON2_DK_STD_T, ARGON2_DK_STD_P);
+                for (name, value) in items {
+                    match name {
+                        "m" => m = value,
+                        "t" => t = value,
+                        "p" => p = value,
+                        _ => {
+                            return Err(Error::InvalidKdfParams(format!(
+                                "unknown argon2id parameter: {}",
+                                name
+                            )))
+                        }
+                    }
+                }
+                Argon2Params::check(m, t, p)?;
+                Ok(KdfType::Argon2id { m, t, p })
+            }
+            _ => Err(Error::UnsupportedKdf(kdf.to_owned())),
+        }
+    }
+
+    pub fn kdf(&self) -> &'static str {
+        match self {
+            KdfType::Scrypt(_) => KDF_TYPE_SCRYPT,
+            KdfType::Argon2id { .. } => KDF_TYPE_ARGON2ID,
         }
     }
 }
@@ -309,7 +639,7 @@ impl Crypto {
             cipher: SUPPORT_CIPHER_TYPE,
             cipherparams,
             ciphertext,
-            kdf: SUPPORT_KDF_TYPE,
+            kdf: kdfparams.kdf(),
             kdfparams,
             mac,
         })
@@ -323,6 +653,15 @@ impl Crypto {
             .expect("encrypt key scrypt failed")
     }
 
+    pub fn encrypt_key_with_kdf(
+        key: &[u8],
+        password: &[u8],
+        kdf_type: KdfType,
+    ) -> Result<Crypto, Error> {
+        let kdfparams = KdfParams::new(kdf_type)?;
+        Self::encrypt_key(key, password, kdfparams, CipherParams::default())
+    }
+
     pub fn decrypt(&self, password: &[u8]) -> Result<Vec<u8>, Error> {
         let kdf_key = self.kdfparams.kdf_key(password)?;
         if !self.check_password_inner(&kdf_key) {
@@ -357,15 +696,10 @@ impl Crypto {
         let cipherparams_data = util::get_value(data, "cipherparams")?;
         let cipherparams = CipherParams::from_json(cipherparams_data)?;
 
-        let kdf = util::get_str(data, "kdf").and_then(|kdf_str| {
-            if kdf_str == SUPPORT_KDF_TYPE {
-                Ok(SUPPORT_KDF_TYPE)
-            } else {
-                Err(Error::UnsupportedKdf(kdf_str.to_owned()))
-            }
-        })?;
+        let kdf_str = util::get_str(data, "kdf")?;
         let kdfparams_data = util::get_value(data, "kdfparams")?;
-        let kdfparams = KdfParams::from_json(kdfparams_data, kdf)?;
+        let kdfparams = KdfParams::from_json(kdfparams_data, kdf_str)?;
+        let kdf = kdfparams.kdf();
 
         let mac_bin = util::get_hex_bin(data, "mac")?;
         if mac_bin.len() != 32 {
@@ -491,4 +825,101 @@ mod tests {
         assert_eq!(data.crypto.check_password(&data.password).unwrap(), true);
         assert_eq!(data.crypto.check_password(b"xyz.1234").unwrap(), false);
     }
+    #[test]
+    fn test_decrypt_pbkdf2() {
+        // From Web3 Secret Storage Definition test vectors
+        let json_data = serde_json::json!({
+            "cipher": "aes-128-ctr",
+            "cipherparams": {
+                "iv": "6087dab2f9fdbbfaddc31a909735c1e6"
+            },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        });
+        let crypto = Crypto::from_json(&json_data).unwrap();
+        let mut secret_key = [0u8; 32];
+        hex_decode(
+            b"7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d",
+            &mut secret_key,
+        )
+        .unwrap();
+        assert_eq!(
+            crypto.decrypt(b"testpassword").unwrap(),
+            secret_key.to_vec()
+        );
+        assert_eq!(crypto.to_json(), json_data);
+    }
+
+    #[test]
+    fn test_argon2id() {
+        let mut salt = [0u8; 32];
+        hex_decode(
+            b"be3d86c99f4895f99d1a0048afb61a34153fa83d5edd033fc914de2c502f57e7",
+            &mut salt,
+        )
+        .unwrap();
+        let params = Argon2Params::new_with_salt(salt, 64, 2, 2).unwrap();
+        let mut kdf_key = [0u8; 32];
+        hex_decode(
+            b"fc088272b6ab524202505d30b8578da43c6c88b7e6d40bc3528167a1e2725222",
+            &mut kdf_key,
+        )
+        .unwrap();
+        assert_eq!(params.kdf_key(b"123").unwrap(), kdf_key);
+
+        let secret_key = [7u8; 32];
+        let kdf_type = KdfType::Argon2id { m: 64, t: 2, p: 2 };
+        let crypto = Crypto::encrypt_key_with_kdf(&secret_key, b"123", kdf_type).unwrap();
+        let json_data = crypto.to_json();
+        assert_eq!(json_data["kdf"], "argon2id");
+        assert_eq!(json_data["kdfparams"]["m"], 64);
+        let crypto = Crypto::from_json(&json_data).unwrap();
+        assert_eq!(crypto.decrypt(b"123").unwrap(), secret_key.to_vec());
+        assert_eq!(crypto.decrypt(b"1234"), Err(Error::CheckPasswordFailed));
+        assert!(Argon2Params::new(8, 1, 2).is_err());
+
+        let mut json_params = json_data["kdfparams"].clone();
+        json_params["m"] = serde_json::json!(ARGON2_MAX_M + 1);
+        assert!(Argon2Params::from_json(&json_params).is_err());
+        json_params["m"] = serde_json::json!(64);
+        json_params["t"] = serde_json::json!(ARGON2_MAX_T + 1);
+        assert!(Argon2Params::from_json(&json_params).is_err());
+        json_params["t"] = serde_json::json!(2);
+        json_params["p"] = serde_json::json!(ARGON2_MAX_P + 1);
+        assert!(Argon2Params::from_json(&json_params).is_err());
+        json_params["p"] = serde_json::json!(2);
+        assert!(Argon2Params::from_json(&json_params).is_ok());
+    }
+
+    #[test]
+    fn test_parse_kdf_type() {
+        assert_eq!(KdfType::parse("scrypt", None).unwrap(), KdfType::default());
+        assert_eq!(
+            KdfType::parse("scrypt", Some("n=4096,p=6")).unwrap(),
+            KdfType::Scrypt(ScryptType::Custom { log_n: 12, p: 6 })
+        );
+        assert_eq!(
+            KdfType::parse("argon2id", None).unwrap(),
+            KdfType::argon2id_standard()
+        );
+        assert_eq!(
+            KdfType::parse("argon2id", Some("m=1024, t=1")).unwrap(),
+            KdfType::Argon2id {
+                m: 1024,
+                t: 1,
+                p: ARGON2_DK_STD_P
+            }
+        );
+        assert!(KdfType::parse("scrypt", Some("n=1000")).is_err());
+        assert!(KdfType::parse("argon2id", Some("m=16")).is_err());
+        assert!(KdfType::parse("argon2id", Some("r=8")).is_err());
+        assert!(KdfType::parse("pbkdf2", None).is_err());
+    }
 }
@@ -0,0 +1,346 @@
+//! Shamir's secret sharing over GF(256), the polynomial construction follows the single group
+//! scheme of SLIP-39: https://github.com/satoshilabs/slips/blob/master/slip-0039.md
+//!
+//! The secret is the polynomial value at x=255 and a digest of the secret is the value at
+//! x=254, so a wrong combination of shares is detected when recovering. The shares are NOT
+//! SLIP-39 mnemonics: they are encoded as hex strings with a blake2b checksum to detect typos,
+//! and can only be combined by ckb-cli.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin_hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
+use ckb_hash::blake2b_256;
+use faster_hex::{hex_decode, hex_string};
+
+use super::error::Error;
+use super::util::zeroize_slice;
+
+pub const MAX_SHARE_COUNT: u8 = 16;
+
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+const DIGEST_LEN: usize = 4;
+// identifier(2) + threshold(1) + index(1)
+const HEADER_LEN: usize = 4;
+const CHECKSUM_LEN: usize = 4;
+
+// Encoded as hex string: identifier | threshold | index | value | checksum
+// (checksum = blake2b_256(identifier | threshold | index | value)[0..4])
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    // Random number shared by all the shares of one split
+    pub identifier: u16,
+    pub threshold: u8,
+    pub index: u8,
+    pub value: Vec<u8>,
+}
+
+impl Share {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.value.len() + CHECKSUM_LEN);
+        bytes.extend_from_slice(&self.identifier.to_be_bytes());
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.value);
+        let checksum = blake2b_256(&bytes);
+        bytes.extend_from_slice(&checksum[0..CHECKSUM_LEN]);
+        bytes
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        zeroize_slice(&mut self.value);
+    }
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bytes = self.to_bytes();
+        let result = write!(f, "{}", hex_string(&bytes).expect("hex share"));
+        zeroize_slice(&mut bytes);
+        result
+    }
+}
+
+impl FromStr for Share {
+    type Err = Error;
+    fn from_str(input: &str) -> Result<Share, Error> {
+        let input = input.trim();
+        let input = if input.starts_with("0x") {
+            &input[2..]
+        } else {
+            input
+        };
+        if input.len() % 2 != 0 {
+            return Err(Error::InvalidShare("odd hex string length".to_owned()));
+        }
+        let mut bytes = vec![0u8; input.len() / 2];
+        hex_decode(input.as_bytes(), &mut bytes)
+            .map_err(|err| Error::InvalidShare(err.to_string()))?;
+        if bytes.len() <= HEADER_LEN + DIGEST_LEN + CHECKSUM_LEN {
+            return Err(Error::InvalidShare("share is too short".to_owned()));
+        }
+        let data_len = bytes.len() - CHECKSUM_LEN;
+        let checksum = blake2b_256(&bytes[0..data_len]);
+        if bytes[data_len..] != checksum[0..CHECKSUM_LEN] {
+            zeroize_slice(&mut bytes);
+            return Err(Error::InvalidShare(
+                "checksum mismatch, the share may be mistyped".to_owned(),
+            ));
+        }
+        let share = Share {
+            identifier: u16::from_be_bytes([bytes[0], bytes[1]]),
+            threshold: bytes[2],
+            index: bytes[3],
+            value: bytes[HEADER_LEN..data_len].to_vec(),
+        };
+        zeroize_slice(&mut bytes);
+        if share.threshold == 0 || share.threshold > MAX_SHARE_COUNT {
+            return Err(Error::InvalidShare(format!(
+                "invalid threshold: {}",
+                share.threshold
+            )));
+        }
+        if share.index >= MAX_SHARE_COUNT {
+            return Err(Error::InvalidShare(format!(
+                "invalid index: {}",
+                share.index
+            )));
+        }
+        Ok(share)
+    }
+}
+
+// Split the secret into `count` shares, any `threshold` of them can recover the secret.
+pub fn split_secret(secret: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>, Error> {
+    if secret.len() <= DIGEST_LEN {
+        return Err(Error::InvalidShare(format!(
+            "secret is too short: {} bytes",
+            secret.len()
+        )));
+    }
+    if threshold == 0 || threshold > count || count > MAX_SHARE_COUNT {
+        return Err(Error::InvalidShare(format!(
+            "invalid threshold {} of {} shares (1 <= threshold <= shares <= {})",
+            threshold, count, MAX_SHARE_COUNT
+        )));
+    }
+    let identifier: u16 = rand::random();
+    let new_share = |index: u8, value: Vec<u8>| Share {
+        identifier,
+        threshold,
+        index,
+        value,
+    };
+    if threshold == 1 {
+        return Ok((0..count)
+            .map(|index| new_share(index, secret.to_vec()))
+            .collect());
+    }
+
+    let random_value = |len: usize| (0..len).map(|_| rand::random()).collect::<Vec<u8>>();
+    let random_part = random_value(secret.len() - DIGEST_LEN);
+    let mut digest = share_digest(&random_part, secret).to_vec();
+    digest.extend_from_slice(&random_part);
+
+    // threshold - 2 random shares plus the digest and the secret define the polynomial
+    let mut points = (0..threshold - 2)
+        .map(|index| (index, random_value(secret.len())))
+        .collect::<Vec<_>>();
+    points.push((DIGEST_INDEX, digest));
+    points.push((SECRET_INDEX, secret.to_vec()));
+    let shares = (0..count)
+        .map(|index| new_share(index, interpolate(&points, index)))
+        .collect();
+    for (_, value) in points.iter_mut() {
+        zeroize_slice(value);
+    }
+    Ok(shares)
+}
+
+// Recover the secret from at least `threshold` shares of the same split.
+pub fn combine_shares(shares: &[Share]) -> Result<Vec<u8>, Error> {
+    let first = shares
+        .first()
+        .ok_or_else(|| Error::InvalidShare("no shares given".to_owned()))?;
+    for share in shares {
+        if share.identifier != first.identifier
+            || share.threshold != first.threshold
+            || share.value.len() != first.value.len()
+        {
+            return Err(Error::InvalidShare(
+                "shares are not from the same backup".to_owned(),
+            ));
+        }
+    }
+    let mut points: Vec<(u8, Vec<u8>)> = Vec::new();
+    for share in shares {
+        if points.iter().all(|(index, _)| *index != share.index) {
+            points.push((share.index, share.value.clone()));
+        }
+    }
+    if points.len() < first.threshold as usize {
+        return Err(Error::InvalidShare(format!(
+            "need {} different shares, got {}",
+            first.threshold,
+            points.len()
+        )));
+    }
+    points.truncate(first.threshold as usize);
+    if first.threshold == 1 {
+        return Ok(points.remove(0).1);
+    }
+
+    let secret = interpolate(&points, SECRET_INDEX);
+    let mut digest = interpolate(&points, DIGEST_INDEX);
+    let is_valid = digest[0..DIGEST_LEN] == share_digest(&digest[DIGEST_LEN..], &secret);
+    zeroize_slice(&mut digest);
+    for (_, value) in points.iter_mut() {
+        zeroize_slice(value);
+    }
+    if is_valid {
+        Ok(secret)
+    } else {
+        Err(Error::InvalidShare(
+            "digest mismatch, the shares are invalid".to_owned(),
+        ))
+    }
+}
+
+fn share_digest(random_part: &[u8], secret: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut engine: HmacEngine<sha256::Hash> = HmacEngine::new(random_part);
+    engine.input(secret);
+    let mut digest = [0u8; DIGEST_LEN];
+    digest.copy_from_slice(&Hmac::from_engine(engine)[0..DIGEST_LEN]);
+    digest
+}
+
+// Multiplication in GF(2^8) with the Rijndael polynomial x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 = a^-1
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+// Lagrange interpolation of the polynomial at x, the x of points must be distinct
+fn interpolate(points: &[(u8, Vec<u8>)], x: u8) -> Vec<u8> {
+    if let Some((_, value)) = points.iter().find(|(xi, _)| *xi == x) {
+        return value.clone();
+    }
+    let mut result = vec![0u8; points[0].1.len()];
+    for (i, (xi, yi)) in points.iter().enumerate() {
+        let mut basis = 1u8;
+        for (j, (xj, _)) in points.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(x ^ xj, gf_inv(xi ^ xj)));
+            }
+        }
+        for (out, y) in result.iter_mut().zip(yi.iter()) {
+            *out ^= gf_mul(basis, *y);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf256() {
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_split_and_combine() {
+        let secret: Vec<u8> = (0..64u8).collect();
+        for (threshold, count) in &[(1, 1), (1, 3), (2, 3), (3, 5), (5, 5)] {
+            let shares = split_secret(&secret, *threshold, *count).unwrap();
+            assert_eq!(shares.len(), *count as usize);
+            // Any `threshold` shares recover the secret
+            for start in 0..=(count - threshold) {
+                let selected = &shares[start as usize..(start + threshold) as usize];
+                assert_eq!(combine_shares(selected).unwrap(), secret);
+            }
+            let encoded = shares.iter().map(Share::to_string).collect::<Vec<_>>();
+            let decoded = encoded
+                .iter()
+                .map(|input| input.parse::<Share>().unwrap())
+                .collect::<Vec<_>>();
+            assert!(decoded == shares);
+            if *threshold > 1 {
+                // SLIP-39: the secret is at x=255 and the digest is at x=254
+                let points = shares[0..*threshold as usize]
+                    .iter()
+                    .map(|share| (share.index, share.value.clone()))
+                    .collect::<Vec<_>>();
+                assert_eq!(interpolate(&points, 255), secret);
+                let digest = interpolate(&points, 254);
+                assert_eq!(
+                    digest[0..DIGEST_LEN],
+                    share_digest(&digest[DIGEST_LEN..], &secret)
+                );
+
+                let too_few = &shares[0..(threshold - 1) as usize];
+                assert!(combine_shares(too_few).is_err());
+            }
+        }
+        assert!(split_secret(&secret, 3, 2).is_err());
+        assert!(split_secret(&secret, 2, 17).is_err());
+        assert!(split_secret(&secret[0..4], 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_invalid_shares() {
+        let secret = [7u8; 64];
+        let shares = split_secret(&secret, 2, 3).unwrap();
+
+        // A mistyped share is rejected by checksum
+        let mut encoded = shares[0].to_string();
+        let typo = if &encoded[20..21] == "0" { "1" } else { "0" };
+        encoded.replace_range(20..21, typo);
+        assert!(encoded.parse::<Share>().is_err());
+
+        // A share with valid checksum but wrong value is detected by the digest
+        let mut bad_share = shares[1].clone();
+        bad_share.value[0] ^= 1;
+        let bad_share: Share = bad_share.to_string().parse().unwrap();
+        assert!(combine_shares(&[shares[0].clone(), bad_share]).is_err());
+
+        // Shares of different backups can not be combined
+        let other_shares = split_secret(&secret, 2, 3).unwrap();
+        if other_shares[1].identifier != shares[0].identifier {
+            assert!(combine_shares(&[shares[0].clone(), other_shares[1].clone()]).is_err());
+        }
+    }
+}
@@ -8,7 +8,8 @@ pub use bip32::{
 };
 pub use error::Error as WalletError;
 pub use keystore::{
-    zeroize_privkey, zeroize_slice, CipherParams, Crypto, DerivedKeySet, Error as KeyStoreError,
-    KdfParams, Key, KeyChain, KeyStore, KeyTimeout, MasterPrivKey, ScryptParams, ScryptType,
-    CKB_ROOT_PATH,
+    ckb_root_path, combine_shares, split_secret, zeroize_privkey, zeroize_slice, AccountMetadata,
+    Argon2Params, CipherParams, CkbRoot, Crypto, DerivedKeySet, Error as KeyStoreError, KdfParams,
+    KdfType, Key, KeyChain, KeyStore, KeyTimeout, KeystoreFormat, MasterPrivKey, ScryptParams,
+    ScryptType, Share, CKB_ROOT_PATH, MAX_SHARE_COUNT,
 };
@@ -41,6 +41,7 @@ fn handle(request: PluginRequest) -> Option<PluginResponse> {
                 daemon: true,
                 roles: vec![PluginRole::KeyStore {
                     require_password: true,
+                    support_account_index: false,
                 }],
             };
             Some(PluginResponse::PluginConfig(config))
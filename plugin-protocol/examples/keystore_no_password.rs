@@ -42,6 +42,7 @@ fn handle(request: PluginRequest) -> Option<PluginResponse> {
                 daemon: true,
                 roles: vec![PluginRole::KeyStore {
                     require_password: false,
+                    support_account_index: false,
                 }],
             };
             Some(PluginResponse::PluginConfig(config))
@@ -127,6 +127,10 @@ impl From<KeyStoreRequest> for (&'static str, Vec<serde_json::Value>) {
                 method::KEYSTORE_HAS_ACCOUNT,
                 vec![serde_json::json!(hash160)],
             ),
+            KeyStoreRequest::IsWatchOnly(hash160) => (
+                method::KEYSTORE_IS_WATCH_ONLY,
+                vec![serde_json::json!(hash160)],
+            ),
             KeyStoreRequest::CreateAccount(password) => (
                 method::KEYSTORE_CREATE_ACCOUNT,
                 vec![serde_json::json!(password)],
@@ -197,6 +201,7 @@ impl From<KeyStoreRequest> for (&'static str, Vec<serde_json::Value>) {
                 change_last,
                 change_max_len,
                 password,
+                account_index,
             } => {
                 let params = vec![
                     serde_json::json!(hash160),
@@ -204,6 +209,7 @@ impl From<KeyStoreRequest> for (&'static str, Vec<serde_json::Value>) {
                     serde_json::json!(change_last),
                     serde_json::json!(change_max_len),
                     serde_json::json!(password),
+                    serde_json::json!(account_index),
                 ];
                 (method::KEYSTORE_DERIVED_KEY_SET, params)
             }
@@ -214,6 +220,7 @@ impl From<KeyStoreRequest> for (&'static str, Vec<serde_json::Value>) {
                 change_start,
                 change_length,
                 password,
+                account_index,
             } => {
                 let params = vec![
                     serde_json::json!(hash160),
@@ -222,9 +229,29 @@ impl From<KeyStoreRequest> for (&'static str, Vec<serde_json::Value>) {
                     serde_json::json!(change_start),
                     serde_json::json!(change_length),
                     serde_json::json!(password),
+                    serde_json::json!(account_index),
                 ];
                 (method::KEYSTORE_DERIVED_KEY_SET_BY_INDEX, params)
             }
+            KeyStoreRequest::Unlock {
+                hash160,
+                password,
+                keep_secs,
+            } => {
+                let params = vec![
+                    serde_json::json!(hash160),
+                    serde_json::json!(password),
+                    serde_json::json!(keep_secs),
+                ];
+                (method::KEYSTORE_UNLOCK, params)
+            }
+            KeyStoreRequest::Lock(hash160) => {
+                (method::KEYSTORE_LOCK, vec![serde_json::json!(hash160)])
+            }
+            KeyStoreRequest::IsUnlocked(hash160) => (
+                method::KEYSTORE_IS_UNLOCKED,
+                vec![serde_json::json!(hash160)],
+            ),
             KeyStoreRequest::Any(value) => (method::KEYSTORE_ANY, vec![value]),
         }
     }
@@ -237,6 +264,9 @@ impl TryFrom<&JsonrpcRequest> for KeyStoreRequest {
             method::KEYSTORE_HAS_ACCOUNT => {
                 KeyStoreRequest::HasAccount(parse_param(data, 0, "hash160")?)
             }
+            method::KEYSTORE_IS_WATCH_ONLY => {
+                KeyStoreRequest::IsWatchOnly(parse_param(data, 0, "hash160")?)
+            }
             method::KEYSTORE_CREATE_ACCOUNT => {
                 KeyStoreRequest::CreateAccount(parse_param(data, 0, "password")?)
             }
@@ -290,6 +320,7 @@ impl TryFrom<&JsonrpcRequest> for KeyStoreRequest {
                 change_last: parse_param(data, 2, "change_last")?,
                 change_max_len: parse_param(data, 3, "change_max_len")?,
                 password: parse_param(data, 4, "password")?,
+                account_index: parse_param_or_default(data, 5, "account_index")?,
             },
             method::KEYSTORE_DERIVED_KEY_SET_BY_INDEX => KeyStoreRequest::DerivedKeySetByIndex {
                 hash160: parse_param(data, 0, "hash160")?,
@@ -298,7 +329,17 @@ impl TryFrom<&JsonrpcRequest> for KeyStoreRequest {
                 change_start: parse_param(data, 3, "change_start")?,
                 change_length: parse_param(data, 4, "change_length")?,
                 password: parse_param(data, 5, "password")?,
+                account_index: parse_param_or_default(data, 6, "account_index")?,
+            },
+            method::KEYSTORE_UNLOCK => KeyStoreRequest::Unlock {
+                hash160: parse_param(data, 0, "hash160")?,
+                password: parse_param(data, 1, "password")?,
+                keep_secs: parse_param(data, 2, "keep_secs")?,
             },
+            method::KEYSTORE_LOCK => KeyStoreRequest::Lock(parse_param(data, 0, "hash160")?),
+            method::KEYSTORE_IS_UNLOCKED => {
+                KeyStoreRequest::IsUnlocked(parse_param(data, 0, "hash160")?)
+            }
             method::KEYSTORE_ANY => KeyStoreRequest::Any(parse_param(data, 0, "value")?),
             _ => {
                 return Err(format!("Invalid request method: {}", data.method));
@@ -442,6 +483,19 @@ fn parse_param<T: DeserializeOwned>(
         })
 }
 
+// For the parameters appended in later versions
+fn parse_param_or_default<T: DeserializeOwned + Default>(
+    data: &JsonrpcRequest,
+    index: usize,
+    field_name: &str,
+) -> Result<T, String> {
+    if data.params.len() > index {
+        parse_param(data, index, field_name)
+    } else {
+        Ok(T::default())
+    }
+}
+
 impl From<(u64, PluginResponse)> for JsonrpcResponse {
     fn from((id, response): (u64, PluginResponse)) -> JsonrpcResponse {
         let (result, error) = match response {
@@ -52,13 +52,22 @@ impl PluginConfig {
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "role", rename_all = "snake_case")]
 pub enum PluginRole {
-    // The argument is for if keystore need password
-    KeyStore { require_password: bool },
+    // The arguments are for if keystore need password, and if keystore supports the
+    // `account_index` parameter of derived key set methods
+    KeyStore {
+        require_password: bool,
+        #[serde(default)]
+        support_account_index: bool,
+    },
     Indexer,
     // The argument is for where the sub-command is injected to.
-    SubCommand { name: String },
+    SubCommand {
+        name: String,
+    },
     // The argument is for the callback function name
-    Callback { name: CallbackName },
+    Callback {
+        name: CallbackName,
+    },
 }
 
 impl PluginRole {
@@ -183,6 +192,9 @@ pub enum KeyStoreRequest {
     ListAccount,
     // return: PluginResponse::Boolean
     HasAccount(H160),
+    // Only the accounts of default keystore can be watch-only
+    // return: PluginResponse::Boolean
+    IsWatchOnly(H160),
     // return: PluginResponse::H160
     CreateAccount(Option<String>),
     // return: PluginResponse::Ok
@@ -224,6 +236,9 @@ pub enum KeyStoreRequest {
         change_last: H160,
         change_max_len: u32,
         password: Option<String>,
+        // The BIP-44 account index, keys are derived from m/44'/309'/<account_index>'
+        #[serde(default)]
+        account_index: u32,
     },
     // return: PluginResponse::DerivedKeySet
     DerivedKeySetByIndex {
@@ -233,7 +248,20 @@ pub enum KeyStoreRequest {
         change_start: u32,
         change_length: u32,
         password: Option<String>,
+        #[serde(default)]
+        account_index: u32,
     },
+    // Keep the account unlocked for `keep_secs` seconds (until the keystore exit if None)
+    // return: PluginResponse::String (the lock timeout)
+    Unlock {
+        hash160: H160,
+        password: Option<String>,
+        keep_secs: Option<u64>,
+    },
+    // return: PluginResponse::Boolean (false if the account is not unlocked)
+    Lock(H160),
+    // return: PluginResponse::Boolean
+    IsUnlocked(H160),
     // For plugin to use custom keystore
     // return: PluginResponse::JsonValue
     Any(serde_json::Value),
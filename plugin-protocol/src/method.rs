@@ -28,6 +28,7 @@ pub const INDEXER_ANY: &str = "indexer_any";
 pub const KEYSTORE_PREFIX: &str = "keystore_";
 pub const KEYSTORE_LIST_ACCOUNT: &str = "keystore_list_account";
 pub const KEYSTORE_HAS_ACCOUNT: &str = "keystore_has_account";
+pub const KEYSTORE_IS_WATCH_ONLY: &str = "keystore_is_watch_only";
 pub const KEYSTORE_CREATE_ACCOUNT: &str = "keystore_create_account";
 pub const KEYSTORE_UPDATE_PASSWORD: &str = "keystore_update_password";
 pub const KEYSTORE_IMPORT: &str = "keystore_import";
@@ -36,4 +37,7 @@ pub const KEYSTORE_SIGN: &str = "keystore_sign";
 pub const KEYSTORE_EXTENDED_PUBKEY: &str = "keystore_extended_pubkey";
 pub const KEYSTORE_DERIVED_KEY_SET: &str = "keystore_derived_key_set";
 pub const KEYSTORE_DERIVED_KEY_SET_BY_INDEX: &str = "keystore_derived_key_set_by_index";
+pub const KEYSTORE_UNLOCK: &str = "keystore_unlock";
+pub const KEYSTORE_LOCK: &str = "keystore_lock";
+pub const KEYSTORE_IS_UNLOCKED: &str = "keystore_is_unlocked";
 pub const KEYSTORE_ANY: &str = "keystore_any";
@@ -1,10 +1,12 @@
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use ansi_term::Colour::Green;
 use ckb_types::{core::service::Request, core::BlockView};
+use ckb_util::RwLock;
 use regex::Regex;
 use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
@@ -141,7 +143,8 @@ impl InteractiveEnv {
             .edit_mode(EditMode::Emacs)
             .build();
         let mut rl = Editor::with_config(rl_config);
-        let helper = CkbCompleter::new(parser.clone());
+        let account_labels = Arc::new(RwLock::new(self.key_store.get_labels()));
+        let helper = CkbCompleter::new(parser.clone(), account_labels.clone());
         rl.set_helper(Some(helper));
         rl.bind_sequence(KeyPress::Meta('N'), Cmd::HistorySearchForward);
         rl.bind_sequence(KeyPress::Meta('P'), Cmd::HistorySearchBackward);
@@ -172,6 +175,7 @@ impl InteractiveEnv {
                         }
                     }
                     rl.add_history_entry(line.as_str());
+                    *account_labels.write() = self.key_store.get_labels();
                 }
                 Err(ReadlineError::Interrupted) => {
                     println!("CTRL-C");
@@ -314,6 +318,7 @@ impl InteractiveEnv {
                         "color": self.config.color(),
                         "debug": self.config.debug(),
                         "no-sync": self.config.no_sync(),
+                        "enable-explorer": self.config.enable_explorer(),
                         "output_format": self.config.output_format().to_string(),
                         "completion_style": self.config.completion_style(),
                         "edit_style": self.config.edit_style(),
@@ -346,8 +351,9 @@ impl InteractiveEnv {
                     Ok(())
                 }
                 ("account", Some(sub_matches)) => {
-                    let output = AccountSubCommand::new(&mut self.plugin_mgr, &mut self.key_store)
-                        .process(&sub_matches, debug)?;
+                    let output =
+                        AccountSubCommand::new(&mut self.plugin_mgr, &mut self.key_store, true)
+                            .process(&sub_matches, debug)?;
                     output.print(format, color);
                     Ok(())
                 }
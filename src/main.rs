@@ -94,6 +94,7 @@ fn main() -> Result<(), io::Error> {
         }
         config.set_debug(configs["debug"].as_bool().unwrap_or(false));
         config.set_no_sync(configs["no-sync"].as_bool().unwrap_or(false));
+        config.set_enable_explorer(configs["enable-explorer"].as_bool().unwrap_or(false));
         config.set_color(ansi_support && configs["color"].as_bool().unwrap_or(true));
         output_format =
             OutputFormat::from_str(&configs["output_format"].as_str().unwrap_or("yaml"))
@@ -103,8 +104,17 @@ fn main() -> Result<(), io::Error> {
         config.set_edit_style(configs["edit_style"].as_bool().unwrap_or(true));
     }
 
+    if matches.is_present("enable-explorer") {
+        config.set_enable_explorer(true);
+    }
+
     let api_uri = config.get_url().to_string();
-    let index_controller = start_index_thread(api_uri.as_str(), index_dir.clone(), index_state);
+    let index_controller = start_index_thread(
+        api_uri.as_str(),
+        index_dir.clone(),
+        index_state,
+        config.enable_explorer(),
+    );
     let mut rpc_client = HttpRpcClient::new(api_uri.clone());
     let mut raw_rpc_client = RawHttpRpcClient::new(api_uri.as_str());
     check_alerts(&mut rpc_client);
@@ -133,7 +143,8 @@ fn main() -> Result<(), io::Error> {
             RpcSubCommand::new(&mut rpc_client, &mut raw_rpc_client).process(&sub_matches, debug)
         }
         ("account", Some(sub_matches)) => {
-            AccountSubCommand::new(&mut plugin_mgr, &mut key_store).process(&sub_matches, debug)
+            AccountSubCommand::new(&mut plugin_mgr, &mut key_store, false)
+                .process(&sub_matches, debug)
         }
         ("mock-tx", Some(sub_matches)) => {
             MockTxSubCommand::new(&mut rpc_client, &mut plugin_mgr, None)
@@ -305,6 +316,12 @@ pub fn build_cli<'a>(version_short: &'a str, version_long: &'a str) -> App<'a> {
                 .conflicts_with("wait-for-sync")
                 .global(true)
                 .about("Don't wait index database sync to tip"),
+        )
+        .arg(
+            Arg::with_name("enable-explorer")
+                .long("enable-explorer")
+                .global(true)
+                .about("Record transaction history of every lock script in index database (required by `wallet get-transactions`)"),
         );
 
     #[cfg(unix)]
@@ -0,0 +1,238 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ckb_types::H160;
+use plugin_protocol::{KeyStoreRequest, PluginResponse};
+
+#[cfg(unix)]
+use std::{
+    convert::TryInto,
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    sync::Arc,
+    thread,
+};
+
+#[cfg(unix)]
+use ckb_util::Mutex;
+#[cfg(unix)]
+use plugin_protocol::{JsonrpcError, JsonrpcRequest, JsonrpcResponse, PluginRequest};
+
+#[cfg(unix)]
+use super::builtin::handle_request;
+#[cfg(unix)]
+use crate::utils::other::get_key_store;
+
+pub const AGENT_SOCK_ENV: &str = "CKB_CLI_AGENT_SOCK";
+pub const AGENT_SOCK_FILENAME: &str = "agent.sock";
+
+// The keystore agent socket path: $CKB_CLI_AGENT_SOCK or <ckb-cli-dir>/agent.sock
+pub fn agent_socket_path(ckb_cli_dir: &PathBuf) -> PathBuf {
+    env::var_os(AGENT_SOCK_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ckb_cli_dir.join(AGENT_SOCK_FILENAME))
+}
+
+// Client of a running keystore agent (`ckb-cli account agent`)
+#[derive(Clone)]
+pub struct AgentClient {
+    path: PathBuf,
+}
+
+impl AgentClient {
+    pub fn new(path: PathBuf) -> AgentClient {
+        AgentClient { path }
+    }
+
+    // The agent is used only when its socket file exists
+    pub fn from_dir(ckb_cli_dir: &PathBuf) -> Option<AgentClient> {
+        let path = agent_socket_path(ckb_cli_dir);
+        if cfg!(unix) && path.exists() {
+            Some(AgentClient::new(path))
+        } else {
+            None
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    #[cfg(unix)]
+    pub fn call(&self, request: KeyStoreRequest) -> Result<PluginResponse, String> {
+        let mut stream = UnixStream::connect(&self.path)
+            .map_err(|err| format!("Connect to keystore agent {:?} failed: {}", self.path, err))?;
+        let jsonrpc_request = JsonrpcRequest::from((0, PluginRequest::KeyStore(request)));
+        let request_string =
+            serde_json::to_string(&jsonrpc_request).expect("Serialize request error");
+        stream
+            .write_all(format!("{}\n", request_string).as_bytes())
+            .map_err(|err| err.to_string())?;
+        stream.flush().map_err(|err| err.to_string())?;
+        let mut response_string = String::new();
+        BufReader::new(stream)
+            .read_line(&mut response_string)
+            .map_err(|err| err.to_string())?;
+        let jsonrpc_response: JsonrpcResponse =
+            serde_json::from_str(&response_string).map_err(|err| err.to_string())?;
+        let (_id, response) = jsonrpc_response.try_into()?;
+        match response {
+            PluginResponse::Error(error) => Err(error.message),
+            response => Ok(response),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn call(&self, _request: KeyStoreRequest) -> Result<PluginResponse, String> {
+        Err(String::from("Keystore agent is only supported on unix"))
+    }
+
+    // An unreachable agent (stale socket file) is treated as all accounts locked
+    pub fn is_unlocked(&self, hash160: &H160) -> bool {
+        match self.call(KeyStoreRequest::IsUnlocked(hash160.clone())) {
+            Ok(PluginResponse::Boolean(unlocked)) => unlocked,
+            Ok(_) => false,
+            Err(err) => {
+                log::debug!("Keystore agent is not available: {}", err);
+                false
+            }
+        }
+    }
+
+    pub fn unlock(
+        &self,
+        hash160: H160,
+        password: String,
+        keep: Option<Duration>,
+    ) -> Result<String, String> {
+        let request = KeyStoreRequest::Unlock {
+            hash160,
+            password: Some(password),
+            keep_secs: keep.map(|keep| keep.as_secs()),
+        };
+        if let PluginResponse::String(timeout) = self.call(request)? {
+            Ok(timeout)
+        } else {
+            Err("Mismatch keystore agent response".to_string())
+        }
+    }
+
+    pub fn lock(&self, hash160: H160) -> Result<bool, String> {
+        if let PluginResponse::Boolean(locked) = self.call(KeyStoreRequest::Lock(hash160))? {
+            Ok(locked)
+        } else {
+            Err("Mismatch keystore agent response".to_string())
+        }
+    }
+}
+
+// Run the keystore agent in foreground, the unlocked keys are only kept in memory of this
+// process and other ckb-cli processes (include the API server) ask it to sign.
+#[cfg(unix)]
+pub fn run_agent(ckb_cli_dir: &PathBuf, path: PathBuf) -> Result<(), String> {
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(format!("Keystore agent is already running at {:?}", path));
+        }
+        // Stale socket file of a crashed agent
+        fs::remove_file(&path).map_err(|err| err.to_string())?;
+    }
+    let keystore = Arc::new(Mutex::new(get_key_store(ckb_cli_dir)?));
+    let listener = UnixListener::bind(&path)
+        .map_err(|err| format!("Bind keystore agent socket {:?} failed: {}", path, err))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).map_err(|err| err.to_string())?;
+
+    let sweeper_keystore = Arc::clone(&keystore);
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+        for hash160 in sweeper_keystore.lock().lock_expired() {
+            log::info!("Account {:x} locked (expired)", hash160);
+        }
+    });
+
+    eprintln!("Keystore agent listening on {:?}", path);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let keystore = Arc::clone(&keystore);
+                thread::spawn(move || {
+                    if let Err(err) = serve_connection(&keystore, stream) {
+                        log::warn!("Keystore agent connection error: {}", err);
+                    }
+                });
+            }
+            Err(err) => log::warn!("Keystore agent accept error: {}", err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run_agent(_ckb_cli_dir: &PathBuf, _path: PathBuf) -> Result<(), String> {
+    Err(String::from("Keystore agent is only supported on unix"))
+}
+
+// NOTE: the requests may contain password, never log them
+#[cfg(unix)]
+fn serve_connection(
+    keystore: &Mutex<ckb_sdk::wallet::KeyStore>,
+    stream: UnixStream,
+) -> Result<(), String> {
+    let mut writer = stream.try_clone().map_err(|err| err.to_string())?;
+    let mut buf_reader = BufReader::new(stream);
+    loop {
+        let mut content = String::new();
+        if buf_reader
+            .read_line(&mut content)
+            .map_err(|err| err.to_string())?
+            == 0
+        {
+            return Ok(());
+        }
+        let jsonrpc_request: JsonrpcRequest =
+            serde_json::from_str(&content).map_err(|err| err.to_string())?;
+        let (id, request) = jsonrpc_request.try_into()?;
+        let response = match request {
+            PluginRequest::KeyStore(request) if is_agent_request(&request) => {
+                handle_request(&mut keystore.lock(), request, None)
+            }
+            _ => Err(String::from("Request not supported by keystore agent")),
+        }
+        .unwrap_or_else(|err| {
+            PluginResponse::Error(JsonrpcError {
+                code: 0,
+                message: err,
+                data: None,
+            })
+        });
+        let jsonrpc_response = JsonrpcResponse::from((id, response));
+        let response_string =
+            serde_json::to_string(&jsonrpc_response).expect("Serialize response error");
+        writer
+            .write_all(format!("{}\n", response_string).as_bytes())
+            .map_err(|err| err.to_string())?;
+        writer.flush().map_err(|err| err.to_string())?;
+    }
+}
+
+// The agent only signs and derives keys, it never creates, exports or changes a key
+#[cfg(unix)]
+fn is_agent_request(request: &KeyStoreRequest) -> bool {
+    match request {
+        KeyStoreRequest::ListAccount
+        | KeyStoreRequest::HasAccount(_)
+        | KeyStoreRequest::Sign { .. }
+        | KeyStoreRequest::ExtendedPubkey { .. }
+        | KeyStoreRequest::DerivedKeySet { .. }
+        | KeyStoreRequest::DerivedKeySetByIndex { .. }
+        | KeyStoreRequest::Unlock { .. }
+        | KeyStoreRequest::Lock(_)
+        | KeyStoreRequest::IsUnlocked(_) => true,
+        _ => false,
+    }
+}
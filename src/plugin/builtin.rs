@@ -1,245 +1,326 @@
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use ckb_sdk::{
     rpc::JsonBytes,
-    wallet::{DerivationPath, DerivedKeySet, Key, KeyStore, MasterPrivKey},
+    wallet::{CkbRoot, DerivationPath, DerivedKeySet, Key, KeyStore, MasterPrivKey},
 };
-use ckb_types::core::service::Request;
-use crossbeam_channel::bounded;
+use ckb_types::{core::service::Request, H160};
+use crossbeam_channel::{bounded, RecvTimeoutError};
 use plugin_protocol::{JsonrpcError, KeyStoreRequest, PluginRequest, PluginResponse};
 
+use super::agent::AgentClient;
 use super::manager::PluginHandler;
 use crate::utils::other::{get_key_store, serialize_signature};
 
 pub const ERROR_KEYSTORE_REQUIRE_PASSWORD: &str = "keystore require password";
 
-pub(crate) struct DefaultKeyStore {
-    handler: PluginHandler,
-    _thread: JoinHandle<()>,
+fn serilize_key_set(key_set: DerivedKeySet) -> PluginResponse {
+    let external = key_set
+        .external
+        .into_iter()
+        .map(|(path, hash160)| (path.to_string(), hash160))
+        .collect::<Vec<_>>();
+    let change = key_set
+        .change
+        .into_iter()
+        .map(|(path, hash160)| (path.to_string(), hash160))
+        .collect::<Vec<_>>();
+    PluginResponse::DerivedKeySet { external, change }
 }
 
-impl DefaultKeyStore {
-    pub(crate) fn start(ckb_cli_dir: &PathBuf) -> Result<DefaultKeyStore, String> {
-        fn serilize_key_set(key_set: DerivedKeySet) -> PluginResponse {
-            let external = key_set
-                .external
-                .into_iter()
-                .map(|(path, hash160)| (path.to_string(), hash160))
-                .collect::<Vec<_>>();
-            let change = key_set
-                .change
-                .into_iter()
-                .map(|(path, hash160)| (path.to_string(), hash160))
-                .collect::<Vec<_>>();
-            PluginResponse::DerivedKeySet { external, change }
+// Only required when the ckb root is not stored in key file
+fn get_ckb_root(
+    keystore: &mut KeyStore,
+    hash160: &H160,
+    account_index: u32,
+    password: Option<String>,
+) -> Result<CkbRoot, String> {
+    if let Some(ckb_root) = keystore.get_ckb_root_by_index(hash160, account_index, true) {
+        return Ok(ckb_root.clone());
+    }
+    if let Some(password) = password {
+        keystore
+            .ckb_root_with_password(hash160, account_index, password.as_bytes())
+            .map_err(|err| err.to_string())
+    } else if keystore.is_unlocked(hash160) {
+        keystore
+            .ckb_root(hash160, account_index)
+            .map_err(|err| err.to_string())
+    } else {
+        Err(String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))
+    }
+}
+
+// The account of a request without password which is locked in this keystore
+fn locked_request_account(keystore: &mut KeyStore, request: &KeyStoreRequest) -> Option<H160> {
+    let hash160 = match request {
+        KeyStoreRequest::Sign {
+            hash160,
+            password: None,
+            ..
+        }
+        | KeyStoreRequest::ExtendedPubkey {
+            hash160,
+            password: None,
+            ..
         }
+        | KeyStoreRequest::DerivedKeySet {
+            hash160,
+            password: None,
+            ..
+        }
+        | KeyStoreRequest::DerivedKeySetByIndex {
+            hash160,
+            password: None,
+            ..
+        } => hash160,
+        _ => return None,
+    };
+    if keystore.is_unlocked(hash160) {
+        None
+    } else {
+        Some(hash160.clone())
+    }
+}
 
-        fn handle_request(
-            keystore: &mut KeyStore,
-            request: KeyStoreRequest,
-        ) -> Result<PluginResponse, String> {
-            match request {
-                KeyStoreRequest::CreateAccount(password) => {
-                    let password =
-                        password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
-                    keystore
-                        .new_account(password.as_bytes())
-                        .map(PluginResponse::H160)
-                        .map_err(|err| err.to_string())
-                }
-                KeyStoreRequest::HasAccount(hash160) => Ok(PluginResponse::Boolean(
-                    keystore.has_account(&hash160, true),
-                )),
-                KeyStoreRequest::UpdatePassword {
-                    hash160,
-                    password,
-                    new_password,
-                } => keystore
-                    .update(&hash160, password.as_bytes(), new_password.as_bytes())
-                    .map(|_| PluginResponse::Ok)
-                    .map_err(|err| err.to_string()),
-                KeyStoreRequest::Import {
-                    privkey,
-                    chain_code,
-                    password,
-                } => {
-                    let password =
-                        password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
-                    let privkey = secp256k1::SecretKey::from_slice(&privkey)
-                        .map_err(|err| err.to_string())?;
-                    let mut data = [0u8; 64];
-                    data[0..32].copy_from_slice(&privkey[..]);
-                    data[32..64].copy_from_slice(&chain_code[..]);
-                    let master_privkey =
-                        MasterPrivKey::from_bytes(data).map_err(|err| err.to_string())?;
-                    let key = Key::new(master_privkey);
-                    let lock_arg = keystore
-                        .import_key(&key, password.as_bytes())
-                        .map_err(|err| err.to_string())?;
-                    Ok(PluginResponse::H160(lock_arg))
-                }
-                KeyStoreRequest::Export { hash160, password } => {
-                    let password =
-                        password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
-                    keystore
-                        .export_key(&hash160, password.as_bytes())
-                        .map(|master_privkey| {
-                            let data = master_privkey.to_bytes();
-                            let mut privkey = vec![0u8; 32];
-                            let mut chain_code = vec![0u8; 32];
-                            privkey.copy_from_slice(&data[0..32]);
-                            chain_code.copy_from_slice(&data[32..64]);
-                            PluginResponse::MasterPrivateKey {
-                                privkey: JsonBytes::from_vec(privkey),
-                                chain_code: JsonBytes::from_vec(chain_code),
-                            }
-                        })
-                        .map_err(|err| err.to_string())
-                }
-                KeyStoreRequest::DerivedKeySet {
-                    hash160,
-                    external_max_len,
-                    change_last,
-                    change_max_len,
-                    password,
-                } => {
-                    let password =
-                        password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
-                    keystore
-                        .get_ckb_root(&hash160, true)
-                        .cloned()
-                        .map_or_else(
-                            || {
-                                keystore
-                                    .ckb_root_with_password(&hash160, password.as_bytes())
-                                    .map_err(|err| err.to_string())
-                            },
-                            Ok,
-                        )
-                        .map(|ckb_root| {
-                            ckb_root.derived_key_set(external_max_len, &change_last, change_max_len)
-                        })?
-                        .map(serilize_key_set)
-                        .map_err(|err| err.to_string())
-                }
-                KeyStoreRequest::DerivedKeySetByIndex {
-                    hash160,
+pub(crate) fn handle_request(
+    keystore: &mut KeyStore,
+    request: KeyStoreRequest,
+    agent: Option<&AgentClient>,
+) -> Result<PluginResponse, String> {
+    if let Some(agent) = agent {
+        if let Some(hash160) = locked_request_account(keystore, &request) {
+            if agent.is_unlocked(&hash160) {
+                return agent.call(request);
+            }
+        }
+    }
+    match request {
+        KeyStoreRequest::CreateAccount(password) => {
+            let password = password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
+            keystore
+                .new_account(password.as_bytes())
+                .map(PluginResponse::H160)
+                .map_err(|err| err.to_string())
+        }
+        KeyStoreRequest::HasAccount(hash160) => Ok(PluginResponse::Boolean(
+            keystore.has_account(&hash160, true),
+        )),
+        KeyStoreRequest::IsWatchOnly(hash160) => Ok(PluginResponse::Boolean(
+            keystore.is_watch_only(&hash160, true),
+        )),
+        KeyStoreRequest::UpdatePassword {
+            hash160,
+            password,
+            new_password,
+        } => keystore
+            .update(&hash160, password.as_bytes(), new_password.as_bytes())
+            .map(|_| PluginResponse::Ok)
+            .map_err(|err| err.to_string()),
+        KeyStoreRequest::Import {
+            privkey,
+            chain_code,
+            password,
+        } => {
+            let password = password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
+            let privkey =
+                secp256k1::SecretKey::from_slice(&privkey).map_err(|err| err.to_string())?;
+            let mut data = [0u8; 64];
+            data[0..32].copy_from_slice(&privkey[..]);
+            data[32..64].copy_from_slice(&chain_code[..]);
+            let master_privkey = MasterPrivKey::from_bytes(data).map_err(|err| err.to_string())?;
+            let key = Key::new(master_privkey);
+            let lock_arg = keystore
+                .import_key(&key, password.as_bytes())
+                .map_err(|err| err.to_string())?;
+            Ok(PluginResponse::H160(lock_arg))
+        }
+        KeyStoreRequest::Export { hash160, password } => {
+            let password = password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
+            keystore
+                .export_key(&hash160, password.as_bytes())
+                .map(|master_privkey| {
+                    let data = master_privkey.to_bytes();
+                    let mut privkey = vec![0u8; 32];
+                    let mut chain_code = vec![0u8; 32];
+                    privkey.copy_from_slice(&data[0..32]);
+                    chain_code.copy_from_slice(&data[32..64]);
+                    PluginResponse::MasterPrivateKey {
+                        privkey: JsonBytes::from_vec(privkey),
+                        chain_code: JsonBytes::from_vec(chain_code),
+                    }
+                })
+                .map_err(|err| err.to_string())
+        }
+        KeyStoreRequest::DerivedKeySet {
+            hash160,
+            external_max_len,
+            change_last,
+            change_max_len,
+            password,
+            account_index,
+        } => get_ckb_root(keystore, &hash160, account_index, password)
+            .map(|ckb_root| {
+                ckb_root.derived_key_set(external_max_len, &change_last, change_max_len)
+            })?
+            .map(serilize_key_set)
+            .map_err(|err| err.to_string()),
+        KeyStoreRequest::DerivedKeySetByIndex {
+            hash160,
+            external_start,
+            external_length,
+            change_start,
+            change_length,
+            password,
+            account_index,
+        } => get_ckb_root(keystore, &hash160, account_index, password)
+            .map(|ckb_root| {
+                ckb_root.derived_key_set_by_index(
                     external_start,
                     external_length,
                     change_start,
                     change_length,
-                    password,
-                } => {
-                    let password =
-                        password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
-                    keystore
-                        .get_ckb_root(&hash160, true)
-                        .cloned()
-                        .map_or_else(
-                            || {
-                                keystore
-                                    .ckb_root_with_password(&hash160, password.as_bytes())
-                                    .map_err(|err| err.to_string())
-                            },
-                            Ok,
-                        )
-                        .map(|ckb_root| {
-                            ckb_root.derived_key_set_by_index(
-                                external_start,
-                                external_length,
-                                change_start,
-                                change_length,
-                            )
-                        })
-                        .map(serilize_key_set)
-                }
-                KeyStoreRequest::ListAccount => {
-                    let mut accounts = keystore.get_accounts().iter().collect::<Vec<_>>();
-                    accounts.sort_by(|a, b| a.1.cmp(&b.1));
-                    let accounts = accounts
-                        .into_iter()
-                        .map(|(lock_arg, _)| lock_arg.clone())
-                        .collect::<Vec<_>>();
-                    Ok(PluginResponse::H160Vec(accounts))
-                }
-                KeyStoreRequest::Sign {
-                    hash160,
-                    path,
-                    message,
-                    target: _target,
-                    password,
-                    recoverable,
-                } => {
-                    let password =
-                        password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
-                    let path = DerivationPath::from_str(&path).map_err(|err| err.to_string())?;
-                    let signature = if recoverable {
-                        keystore
-                            .sign_recoverable_with_password(
-                                &hash160,
-                                path.as_ref(),
-                                &message,
-                                password.as_bytes(),
-                            )
-                            .map(|sig| serialize_signature(&sig).to_vec())
-                            .map_err(|err| err.to_string())?
-                    } else {
-                        keystore
-                            .sign_with_password(
-                                &hash160,
-                                path.as_ref(),
-                                &message,
-                                password.as_bytes(),
-                            )
-                            .map_err(|err| err.to_string())?
-                            .serialize_compact()
-                            .to_vec()
-                    };
-                    Ok(PluginResponse::Bytes(JsonBytes::from_vec(signature)))
-                }
-                KeyStoreRequest::ExtendedPubkey {
-                    hash160,
-                    path,
-                    password,
-                } => {
-                    let password =
-                        password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
-                    let path = DerivationPath::from_str(&path).map_err(|err| err.to_string())?;
-                    let data = keystore
-                        .extended_pubkey_with_password(&hash160, path.as_ref(), password.as_bytes())
-                        .map_err(|err| err.to_string())?
-                        .public_key
-                        .serialize()
-                        .to_vec();
-                    Ok(PluginResponse::Bytes(JsonBytes::from_vec(data)))
-                }
-                KeyStoreRequest::Any(_) => {
-                    // TODO: handle any request
-                    Ok(PluginResponse::JsonValue(serde_json::Value::Null))
+                )
+            })
+            .map(serilize_key_set),
+        KeyStoreRequest::ListAccount => {
+            let mut accounts = keystore.get_accounts().iter().collect::<Vec<_>>();
+            accounts.sort_by(|a, b| a.1.cmp(&b.1));
+            let accounts = accounts
+                .into_iter()
+                .map(|(lock_arg, _)| lock_arg.clone())
+                .collect::<Vec<_>>();
+            Ok(PluginResponse::H160Vec(accounts))
+        }
+        KeyStoreRequest::Sign {
+            hash160,
+            path,
+            message,
+            target: _target,
+            password,
+            recoverable,
+        } => {
+            let path = DerivationPath::from_str(&path).map_err(|err| err.to_string())?;
+            let signature = match password {
+                Some(password) if recoverable => keystore
+                    .sign_recoverable_with_password(
+                        &hash160,
+                        path.as_ref(),
+                        &message,
+                        password.as_bytes(),
+                    )
+                    .map(|sig| serialize_signature(&sig).to_vec())
+                    .map_err(|err| err.to_string())?,
+                Some(password) => keystore
+                    .sign_with_password(&hash160, path.as_ref(), &message, password.as_bytes())
+                    .map_err(|err| err.to_string())?
+                    .serialize_compact()
+                    .to_vec(),
+                // Sign with the unlocked key (see: `account unlock`)
+                None if !keystore.is_unlocked(&hash160) => {
+                    return Err(String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD));
                 }
+                None if recoverable => keystore
+                    .sign_recoverable(&hash160, path.as_ref(), &message)
+                    .map(|sig| serialize_signature(&sig).to_vec())
+                    .map_err(|err| err.to_string())?,
+                None => keystore
+                    .sign(&hash160, path.as_ref(), &message)
+                    .map_err(|err| err.to_string())?
+                    .serialize_compact()
+                    .to_vec(),
+            };
+            Ok(PluginResponse::Bytes(JsonBytes::from_vec(signature)))
+        }
+        KeyStoreRequest::ExtendedPubkey {
+            hash160,
+            path,
+            password,
+        } => {
+            let path = DerivationPath::from_str(&path).map_err(|err| err.to_string())?;
+            // The added BIP-44 account keys are stored in key file
+            let stored_root = keystore
+                .get_sub_ckb_roots(&hash160, true)
+                .into_iter()
+                .find(|ckb_root| ckb_root.path == path.to_string());
+            let public_key = if let Some(ckb_root) = stored_root {
+                ckb_root.extended_pubkey().public_key
+            } else if let Some(password) = password {
+                keystore
+                    .extended_pubkey_with_password(&hash160, path.as_ref(), password.as_bytes())
+                    .map_err(|err| err.to_string())?
+                    .public_key
+            } else if keystore.is_unlocked(&hash160) {
+                keystore
+                    .extended_pubkey(&hash160, path.as_ref())
+                    .map_err(|err| err.to_string())?
+                    .public_key
+            } else {
+                return Err(String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD));
+            };
+            let data = public_key.serialize().to_vec();
+            Ok(PluginResponse::Bytes(JsonBytes::from_vec(data)))
+        }
+        KeyStoreRequest::Unlock {
+            hash160,
+            password,
+            keep_secs,
+        } => {
+            let password = password.ok_or_else(|| String::from(ERROR_KEYSTORE_REQUIRE_PASSWORD))?;
+            let timeout = if let Some(secs) = keep_secs {
+                keystore.timed_unlock(&hash160, password.as_bytes(), Duration::from_secs(secs))
+            } else {
+                keystore.unlock(&hash160, password.as_bytes())
             }
+            .map_err(|err| err.to_string())?;
+            Ok(PluginResponse::String(timeout.to_string()))
+        }
+        KeyStoreRequest::Lock(hash160) => Ok(PluginResponse::Boolean(keystore.lock(&hash160))),
+        KeyStoreRequest::IsUnlocked(hash160) => {
+            let unlocked = keystore.is_unlocked(&hash160)
+                || agent.map(|agent| agent.is_unlocked(&hash160)) == Some(true);
+            Ok(PluginResponse::Boolean(unlocked))
         }
+        KeyStoreRequest::Any(_) => {
+            // TODO: handle any request
+            Ok(PluginResponse::JsonValue(serde_json::Value::Null))
+        }
+    }
+}
+
+pub(crate) struct DefaultKeyStore {
+    handler: PluginHandler,
+    _thread: JoinHandle<()>,
+}
 
+impl DefaultKeyStore {
+    pub(crate) fn start(ckb_cli_dir: &PathBuf) -> Result<DefaultKeyStore, String> {
         let (keystore_sender, keystore_receiver) = bounded(1);
         let mut keystore = get_key_store(ckb_cli_dir)?;
+        let ckb_cli_dir = ckb_cli_dir.clone();
 
         let keystore_thread = thread::spawn(move || loop {
-            match keystore_receiver.recv() {
+            match keystore_receiver.recv_timeout(Duration::from_secs(1)) {
                 Ok(Request {
                     responder,
                     arguments,
                 }) => {
                     let (id, plugin_request) = arguments;
                     let response = if let PluginRequest::KeyStore(request) = plugin_request {
-                        handle_request(&mut keystore, request).unwrap_or_else(|err| {
-                            PluginResponse::Error(JsonrpcError {
-                                code: 0,
-                                message: err,
-                                data: None,
-                            })
-                        })
+                        // The agent may be started after this keystore
+                        let agent = AgentClient::from_dir(&ckb_cli_dir);
+                        handle_request(&mut keystore, request, agent.as_ref()).unwrap_or_else(
+                            |err| {
+                                PluginResponse::Error(JsonrpcError {
+                                    code: 0,
+                                    message: err,
+                                    data: None,
+                                })
+                            },
+                        )
                     } else {
                         PluginResponse::Error(JsonrpcError {
                             code: 0,
@@ -255,6 +336,11 @@ impl DefaultKeyStore {
                         log::warn!("Default keystore send response err: {:?}", err);
                     }
                 }
+                Err(RecvTimeoutError::Timeout) => {
+                    for hash160 in keystore.lock_expired() {
+                        log::debug!("Account {:x} locked (expired)", hash160);
+                    }
+                }
                 Err(err) => {
                     log::warn!("Default keystore receive request error: {:?}", err);
                     break;
@@ -8,11 +8,15 @@ use std::str::FromStr;
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
+use ckb_hash::blake2b_256;
 use ckb_index::LiveCellInfo;
 use ckb_jsonrpc_types::{BlockNumber, HeaderView, Script};
 use ckb_sdk::{
-    wallet::{ChildNumber, DerivationPath, DerivedKeySet, MasterPrivKey, CKB_ROOT_PATH},
+    wallet::{
+        ckb_root_path, ChildNumber, DerivationPath, DerivedKeySet, MasterPrivKey, CKB_ROOT_PATH,
+    },
     HttpRpcClient,
 };
 use ckb_types::{bytes::Bytes, core::service::Request, H160, H256};
@@ -35,6 +39,7 @@ pub const PLUGIN_FILENAME_EXT: &str = "exe";
 pub const ACCOUNT_SOURCE_FS: &str = "Local File System";
 
 pub struct PluginManager {
+    ckb_cli_dir: PathBuf,
     plugin_dir: PathBuf,
     plugins: HashMap<String, (Plugin, PluginConfig)>,
     daemon_processes: HashMap<String, PluginProcess>,
@@ -164,6 +169,7 @@ impl PluginManager {
         let jsonrpc_id = Arc::new(AtomicU64::new(0));
 
         Ok(PluginManager {
+            ckb_cli_dir: ckb_cli_dir.clone(),
             plugin_dir,
             plugins,
             daemon_processes,
@@ -177,6 +183,9 @@ impl PluginManager {
         })
     }
 
+    pub fn ckb_cli_dir(&self) -> &PathBuf {
+        &self.ckb_cli_dir
+    }
     pub fn plugins(&self) -> &HashMap<String, (Plugin, PluginConfig)> {
         &self.plugins
     }
@@ -196,7 +205,10 @@ impl PluginManager {
                     .filter(|(plugin, _)| plugin.is_active())
                     .map(|(plugin, config)| {
                         for role in &config.roles {
-                            if let PluginRole::KeyStore { require_password } = role {
+                            if let PluginRole::KeyStore {
+                                require_password, ..
+                            } = role
+                            {
                                 return (plugin, config, *require_password);
                             }
                         }
@@ -229,6 +241,14 @@ impl PluginManager {
             .map(|(_, _, require_password)| require_password)
             .unwrap_or(true)
     }
+    // No password is required for the account unlocked by `account unlock`
+    pub fn account_require_password(&self, hash160: &H160) -> bool {
+        self.keystore_require_password()
+            && !self
+                .keystore_handler()
+                .is_unlocked(hash160.clone())
+                .unwrap_or(false)
+    }
     pub fn keystore_handler(&self) -> KeyStoreHandler {
         KeyStoreHandler::new(
             self.default_keystore_handler.clone(),
@@ -1178,9 +1198,23 @@ impl KeyStoreHandler {
         self.actived_plugin.as_ref()
     }
 
+    fn plugin_support_account_index(&self) -> bool {
+        self.actived_plugin
+            .iter()
+            .flat_map(|config| config.roles.iter())
+            .any(|role| match role {
+                PluginRole::KeyStore {
+                    support_account_index,
+                    ..
+                } => *support_account_index,
+                _ => false,
+            })
+    }
+
     fn call(&self, request: KeyStoreRequest) -> Result<PluginResponse, String> {
         let mut default_only = false;
         let mut hash160_opt = None;
+        let mut account_index = 0;
         match request {
             KeyStoreRequest::ListAccount => {
                 // Both (and) handle default part out side
@@ -1188,6 +1222,10 @@ impl KeyStoreHandler {
             KeyStoreRequest::HasAccount(_) => {
                 // Both (or) handle default part out side
             }
+            KeyStoreRequest::IsWatchOnly(_) => {
+                // Default only
+                default_only = true;
+            }
             KeyStoreRequest::CreateAccount(_) => {
                 // Both (neet target), currently default only
                 default_only = true;
@@ -1212,13 +1250,29 @@ impl KeyStoreHandler {
                 // Both
                 hash160_opt = Some(hash160.clone());
             }
-            KeyStoreRequest::DerivedKeySet { ref hash160, .. } => {
+            KeyStoreRequest::DerivedKeySet {
+                ref hash160,
+                account_index: index,
+                ..
+            } => {
                 // Both
                 hash160_opt = Some(hash160.clone());
+                account_index = index;
             }
-            KeyStoreRequest::DerivedKeySetByIndex { ref hash160, .. } => {
+            KeyStoreRequest::DerivedKeySetByIndex {
+                ref hash160,
+                account_index: index,
+                ..
+            } => {
                 // Both
                 hash160_opt = Some(hash160.clone());
+                account_index = index;
+            }
+            KeyStoreRequest::Unlock { .. }
+            | KeyStoreRequest::Lock(_)
+            | KeyStoreRequest::IsUnlocked(_) => {
+                // Default only
+                default_only = true;
             }
             KeyStoreRequest::Any(_) => {
                 // Plugin only
@@ -1241,6 +1295,16 @@ impl KeyStoreHandler {
             return result;
         }
 
+        // The old keystore plugins ignore the appended `account_index` parameter
+        if account_index != 0 && !self.plugin_support_account_index() {
+            return Err(format!(
+                "The keystore plugin {} does not support account index, only account index 0 is allowed",
+                self.actived_plugin
+                    .as_ref()
+                    .map(|config| config.name.as_str())
+                    .unwrap_or("")
+            ));
+        }
         let request = ServiceRequest::Request {
             is_from_plugin: false,
             plugin_name: String::from("default_keystore"),
@@ -1262,6 +1326,37 @@ impl KeyStoreHandler {
         }
     }
 
+    // Account 0 keeps the root key path above for compatibility, other BIP-44 accounts use the
+    // account level key (m/44'/309'/<account_index>') as root key.
+    pub fn root_key_path_by_index(
+        &self,
+        h160: H160,
+        account_index: u32,
+    ) -> Result<DerivationPath, String> {
+        if account_index == 0 {
+            self.root_key_path(h160)
+        } else {
+            DerivationPath::from_str(&ckb_root_path(account_index)).map_err(|err| err.to_string())
+        }
+    }
+
+    // The root key path and lock arg of a BIP-44 account
+    pub fn account_root(
+        &self,
+        h160: H160,
+        account_index: u32,
+        password: Option<String>,
+    ) -> Result<(DerivationPath, H160), String> {
+        let path = self.root_key_path_by_index(h160.clone(), account_index)?;
+        if account_index == 0 {
+            return Ok((path, h160));
+        }
+        let pubkey = self.extended_pubkey(h160, &path, password)?;
+        let lock_arg = H160::from_slice(&blake2b_256(&pubkey.serialize()[..])[0..20])
+            .expect("Generate hash(H160) from pubkey failed");
+        Ok((path, lock_arg))
+    }
+
     pub fn has_account_in_default(&self, hash160: H160) -> Result<bool, String> {
         let request = PluginRequest::KeyStore(KeyStoreRequest::HasAccount(hash160));
         if let Some((_, PluginResponse::Boolean(has))) =
@@ -1273,6 +1368,59 @@ impl KeyStoreHandler {
         }
     }
 
+    pub fn is_watch_only(&self, hash160: H160) -> Result<bool, String> {
+        if !self.has_account_in_default(hash160.clone())? {
+            return Ok(false);
+        }
+        if let PluginResponse::Boolean(watch_only) =
+            self.call(KeyStoreRequest::IsWatchOnly(hash160))?
+        {
+            Ok(watch_only)
+        } else {
+            Err("Mismatch keystore response".to_string())
+        }
+    }
+
+    // Unlocked in default keystore or the keystore agent
+    pub fn is_unlocked(&self, hash160: H160) -> Result<bool, String> {
+        if !self.has_account_in_default(hash160.clone())? {
+            return Ok(false);
+        }
+        if let PluginResponse::Boolean(unlocked) =
+            self.call(KeyStoreRequest::IsUnlocked(hash160))?
+        {
+            Ok(unlocked)
+        } else {
+            Err("Mismatch keystore response".to_string())
+        }
+    }
+
+    pub fn unlock(
+        &self,
+        hash160: H160,
+        password: String,
+        keep: Option<Duration>,
+    ) -> Result<String, String> {
+        let request = KeyStoreRequest::Unlock {
+            hash160,
+            password: Some(password),
+            keep_secs: keep.map(|keep| keep.as_secs()),
+        };
+        if let PluginResponse::String(timeout) = self.call(request)? {
+            Ok(timeout)
+        } else {
+            Err("Mismatch keystore response".to_string())
+        }
+    }
+
+    pub fn lock(&self, hash160: H160) -> Result<bool, String> {
+        if let PluginResponse::Boolean(locked) = self.call(KeyStoreRequest::Lock(hash160))? {
+            Ok(locked)
+        } else {
+            Err("Mismatch keystore response".to_string())
+        }
+    }
+
     pub fn list_account(&self) -> Result<Vec<(H160, String)>, String> {
         let request = KeyStoreRequest::ListAccount;
         let plugin_request = PluginRequest::KeyStore(request.clone());
@@ -1384,6 +1532,7 @@ impl KeyStoreHandler {
     pub fn derived_key_set(
         &self,
         hash160: H160,
+        account_index: u32,
         external_max_len: u32,
         change_last: H160,
         change_max_len: u32,
@@ -1395,6 +1544,7 @@ impl KeyStoreHandler {
             change_last: change_last.clone(),
             change_max_len,
             password,
+            account_index,
         };
         let resp = match self.call(request) {
             Ok(resp) => resp,
@@ -1407,6 +1557,7 @@ impl KeyStoreHandler {
                     change_last,
                     change_max_len,
                     password: Some(password),
+                    account_index,
                 };
                 self.call(request)?
             }
@@ -1425,6 +1576,7 @@ impl KeyStoreHandler {
     pub fn derived_key_set_by_index(
         &self,
         hash160: H160,
+        account_index: u32,
         external_start: u32,
         external_length: u32,
         change_start: u32,
@@ -1438,6 +1590,7 @@ impl KeyStoreHandler {
             change_start,
             change_length,
             password,
+            account_index,
         };
         let resp = match self.call(request) {
             Ok(resp) => resp,
@@ -1451,6 +1604,7 @@ impl KeyStoreHandler {
                     change_start,
                     change_length,
                     password: Some(password),
+                    account_index,
                 };
                 self.call(request)?
             }
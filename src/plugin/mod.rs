@@ -1,5 +1,7 @@
+mod agent;
 mod builtin;
 mod manager;
 
+pub use agent::{agent_socket_path, run_agent, AgentClient};
 pub use manager::{IndexerHandler, KeyStoreHandler, PluginManager};
 pub use plugin_protocol::SignTarget;
@@ -1,21 +1,26 @@
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use ckb_sdk::{
-    wallet::{DerivationPath, Key, KeyStore, MasterPrivKey},
+    wallet::{
+        combine_shares, split_secret, zeroize_slice, AccountMetadata, CkbRoot, DerivationPath,
+        KdfType, Key, KeyChain, KeyStore, KeyStoreError, MasterPrivKey, Share, MAX_SHARE_COUNT,
+    },
     Address, AddressPayload, NetworkType,
 };
 use ckb_types::{packed::Script, prelude::*, H160, H256};
 use clap::{App, Arg, ArgMatches};
+use faster_hex::hex_string;
 
 use super::{CliSubCommand, Output};
-use crate::plugin::PluginManager;
+use crate::plugin::{agent_socket_path, run_agent, AgentClient, PluginManager};
 use crate::utils::{
-    arg::lock_arg,
+    arg::{account_index, lock_arg},
     arg_parser::{
-        ArgParser, ExtendedPrivkeyPathParser, FilePathParser, FixedHashParser, FromStrParser,
-        PrivkeyPathParser, PrivkeyWrapper,
+        AddressParser, ArgParser, DurationParser, ExtendedPrivkeyPathParser, FilePathParser,
+        FixedHashParser, FromStrParser, PrivkeyPathParser, PrivkeyWrapper, PubkeyHexParser,
     },
     other::read_password,
 };
@@ -23,16 +28,19 @@ use crate::utils::{
 pub struct AccountSubCommand<'a> {
     plugin_mgr: &'a mut PluginManager,
     key_store: &'a mut KeyStore,
+    interactive: bool,
 }
 
 impl<'a> AccountSubCommand<'a> {
     pub fn new(
         plugin_mgr: &'a mut PluginManager,
         key_store: &'a mut KeyStore,
+        interactive: bool,
     ) -> AccountSubCommand<'a> {
         AccountSubCommand {
             plugin_mgr,
             key_store,
+            interactive,
         }
     }
 
@@ -44,6 +52,23 @@ impl<'a> AccountSubCommand<'a> {
             .long("extended-privkey-path")
             .takes_value(true)
             .about("Extended private key path (include master private key and chain code)");
+        let arg_mnemonic_passphrase = Arg::with_name("mnemonic-passphrase")
+            .long("mnemonic-passphrase")
+            .requires("mnemonic")
+            .about("Ask for a BIP-39 passphrase (it is not stored, the same passphrase is required when import the mnemonic)");
+        let arg_kdf = Arg::with_name("kdf")
+            .long("kdf")
+            .takes_value(true)
+            .possible_values(&["scrypt", "argon2id"])
+            .about("Key derivation function to encrypt the key file [default: scrypt]");
+        let arg_kdf_params = Arg::with_name("kdf-params")
+            .long("kdf-params")
+            .takes_value(true)
+            .requires("kdf")
+            .about("Comma separated KDF parameters, scrypt: n=262144,p=1, argon2id: m=65536,t=3,p=4 (m in KiB, the omitted ones use these default values)");
+        let arg_agent = Arg::with_name("agent")
+            .long("agent")
+            .about("Send to the running keystore agent (see: `account agent`) instead of the keystore of current process");
         App::new(name)
             .about("Manage accounts")
             .subcommands(vec![
@@ -58,24 +83,84 @@ impl<'a> AccountSubCommand<'a> {
                             .long("only-testnet-address")
                             .about("Only show CKB testnet address")
                     )
+                    .arg(
+                        Arg::with_name("tag")
+                            .long("tag")
+                            .takes_value(true)
+                            .about("Only show accounts with this tag")
+                    )
                     .about("List all accounts"),
-                App::new("new").about("Create a new account and print related information."),
+                App::new("set-metadata")
+                    .about("Set label, notes and tags of an account, they are stored as plain text in metadata.json of the keys directory (not encrypted)")
+                    .arg(lock_arg().required(true))
+                    .arg(
+                        Arg::with_name("label")
+                            .long("label")
+                            .takes_value(true)
+                            .validator(validate_label)
+                            .about("Unique label of the account, it can be used as the value of --from-account (empty string to remove)")
+                    )
+                    .arg(
+                        Arg::with_name("notes")
+                            .long("notes")
+                            .takes_value(true)
+                            .about("Notes of the account (empty string to remove)")
+                    )
+                    .arg(
+                        Arg::with_name("tags")
+                            .long("tags")
+                            .takes_value(true)
+                            .about("Comma separated tags of the account (empty string to remove)")
+                    )
+                    .arg(
+                        Arg::with_name("clear")
+                            .long("clear")
+                            .conflicts_with_all(&["label", "notes", "tags"])
+                            .about("Remove all metadata of the account")
+                    ),
+                App::new("new")
+                    .about("Create a new account and print related information.")
+                    .arg(
+                        Arg::with_name("mnemonic")
+                            .long("mnemonic")
+                            .about("Generate the account from BIP-39 mnemonic words, the words are printed for backup")
+                    )
+                    .arg(
+                        Arg::with_name("mnemonic-words")
+                            .long("mnemonic-words")
+                            .takes_value(true)
+                            .possible_values(&["12", "15", "18", "21", "24"])
+                            .requires("mnemonic")
+                            .about("Number of mnemonic words [default: 12]")
+                    )
+                    .arg(arg_mnemonic_passphrase.clone())
+                    .arg(arg_kdf.clone())
+                    .arg(arg_kdf_params.clone()),
                 App::new("import")
                     .about("Import an unencrypted private key from <privkey-path> and create a new account.")
                     .arg(
                         arg_privkey_path
                             .clone()
-                            .required_unless("extended-privkey-path")
+                            .required_unless_one(&["extended-privkey-path", "mnemonic"])
                             .validator(|input| PrivkeyPathParser.validate(input))
                             .about("The privkey is assumed to contain an unencrypted private key in hexadecimal format. (only read first line)")
                     )
                     .arg(arg_extended_privkey_path
                          .clone()
-                         .required_unless("privkey-path")
+                         .required_unless_one(&["privkey-path", "mnemonic"])
                          .validator(|input| ExtendedPrivkeyPathParser.validate(input))
-                    ),
+                    )
+                    .arg(
+                        Arg::with_name("mnemonic")
+                            .long("mnemonic")
+                            .conflicts_with_all(&["privkey-path", "extended-privkey-path"])
+                            .about("Import from BIP-39 mnemonic words (read from prompt)")
+                    )
+                    .arg(arg_mnemonic_passphrase)
+                    .arg(arg_kdf.clone())
+                    .arg(arg_kdf_params.clone()),
                 App::new("import-keystore")
-                    .about("Import key from encrypted keystore json file and create a new account.")
+                    .about("Import key from encrypted keystore json file (created by ckb-cli, Neuron or Ethereum V3 keystore) and create a new account.")
                     .arg(
                         Arg::with_name("path")
                             .long("path")
@@ -84,21 +169,102 @@ impl<'a> AccountSubCommand<'a> {
                             .validator(|input| FilePathParser::new(true).validate(input))
                             .about("The keystore file path (json format)")
                     ),
+                App::new("import-xpub")
+                    .about("Import the extended public key of path m/44'/309'/0' (see: `account export-xpub`) as a watch-only account. The lock arg of the watch-only account is blake160 of this account-level public key, it differs from the lock arg of the master key the extended public key exported from")
+                    .arg(
+                        Arg::with_name("pubkey")
+                            .long("pubkey")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| PubkeyHexParser.validate(input))
+                            .about("The public key (hex string, compressed format)")
+                    )
+                    .arg(
+                        Arg::with_name("chain-code")
+                            .long("chain-code")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .about("The chain code (hex string, 32 bytes)")
+                    ),
+                App::new("export-xpub")
+                    .about("Export the extended public key (public key + chain code) of path m/44'/309'/<account-index>' for creating a watch-only account (identified by blake160 of the exported public key, not by the master key's lock arg)")
+                    .arg(lock_arg().required(true))
+                    .arg(account_index()),
+                App::new("add-account-index")
+                    .about("Add a BIP-44 account (m/44'/309'/<account-index>') of the master key, its extended public key is stored for listing")
+                    .arg(lock_arg().required(true))
+                    .arg(account_index().required(true)),
                 App::new("update")
                     .about("Update password of an account")
                     .arg(lock_arg().required(true)),
                 App::new("upgrade")
                     .about("Upgrade an account to latest json format")
                     .arg(lock_arg().required(true)),
+                App::new("rekey")
+                    .about("Re-encrypt the key file of an account with new KDF parameters (the password is not changed)")
+                    .arg(lock_arg().required(true))
+                    .arg(arg_kdf.required(true))
+                    .arg(arg_kdf_params),
+                App::new("unlock")
+                    .about("Unlock an account, the unlocked account signs without password until it is locked (only in interactive mode or by keystore agent)")
+                    .arg(lock_arg().required(true))
+                    .arg(
+                        Arg::with_name("duration")
+                            .long("duration")
+                            .takes_value(true)
+                            .validator(|input| DurationParser.validate(input))
+                            .about("Keep the account unlocked for this duration, {s: second, m: minute, h: hour, d: day} (example: 30m) [default: until exit]")
+                    )
+                    .arg(arg_agent.clone()),
+                App::new("lock")
+                    .about("Lock an unlocked account (the private key is wiped from memory)")
+                    .arg(lock_arg().required(true))
+                    .arg(arg_agent),
+                App::new("agent")
+                    .about("Run the keystore agent in foreground, other ckb-cli processes (include the API server) sign by the accounts unlocked in it (unix only)")
+                    .arg(
+                        Arg::with_name("socket")
+                            .long("socket")
+                            .takes_value(true)
+                            .about("The unix socket path [default: $CKB_CLI_AGENT_SOCK or <ckb-cli-dir>/agent.sock], other ckb-cli processes find the agent by $CKB_CLI_AGENT_SOCK when it is not the default path")
+                    ),
                 App::new("export")
                     .about("Export master private key and chain code as hex plain text (USE WITH YOUR OWN RISK)")
                     .arg(lock_arg().required(true))
                     .arg(
                         arg_extended_privkey_path
                             .clone()
-                            .required(true)
+                            .required_unless("mnemonic")
                             .about("Output extended private key path (PrivKey + ChainCode)")
+                    )
+                    .arg(
+                        Arg::with_name("mnemonic")
+                            .long("mnemonic")
+                            .conflicts_with("extended-privkey-path")
+                            .about("Print the mnemonic words instead (only for accounts created or imported from mnemonic)")
+                    ),
+                App::new("backup-shares")
+                    .about("Split the master private key (include chain code) into <shares> Shamir secret shares, any <threshold> of them can restore the account (USE WITH YOUR OWN RISK)")
+                    .arg(lock_arg().required(true))
+                    .arg(
+                        Arg::with_name("threshold")
+                            .long("threshold")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FromStrParser::<u8>::default().validate(input))
+                            .about("Number of shares required to restore the account")
+                    )
+                    .arg(
+                        Arg::with_name("shares")
+                            .long("shares")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FromStrParser::<u8>::default().validate(input))
+                            .about("Total number of shares (max: 16)")
                     ),
+                App::new("restore-shares")
+                    .about("Restore an account from Shamir secret shares created by `account backup-shares` (shares are read from prompt)"),
                 App::new("bip44-addresses")
                     .about("Extended receiving/change Addresses (see: BIP-44)")
                     .arg(
@@ -141,7 +307,8 @@ impl<'a> AccountSubCommand<'a> {
                             .possible_values(&["mainnet", "testnet"])
                             .about("The network type")
                     )
-                    .arg(lock_arg().required(true)),
+                    .arg(lock_arg().required(true))
+                    .arg(account_index()),
                 App::new("extended-address")
                     .about("Extended address (see: BIP-44)")
                     .arg(lock_arg().required(true))
@@ -156,6 +323,53 @@ impl<'a> AccountSubCommand<'a> {
     }
 }
 
+impl<'a> AccountSubCommand<'a> {
+    // The keystore of a non-interactive ckb-cli process exits with the command, so only the
+    // keystore agent can keep the account unlocked there
+    fn agent_client(&self, m: &ArgMatches) -> Result<Option<AgentClient>, String> {
+        if m.is_present("agent") {
+            AgentClient::from_dir(self.plugin_mgr.ckb_cli_dir())
+                .map(Some)
+                .ok_or_else(|| {
+                    "Keystore agent is not running, start it by `ckb-cli account agent`".to_string()
+                })
+        } else if self.interactive {
+            Ok(None)
+        } else {
+            Err(
+                "Without --agent, accounts can only be unlocked/locked in interactive mode"
+                    .to_string(),
+            )
+        }
+    }
+
+    // Key files created by the closure are encrypted once with the chosen KDF, the default KDF
+    // is restored afterwards
+    fn with_kdf_type<T, F>(&mut self, kdf_type: Option<KdfType>, func: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut KeyStore) -> Result<T, KeyStoreError>,
+    {
+        let default_kdf_type = self.key_store.kdf_type();
+        self.key_store
+            .set_kdf_type(kdf_type.unwrap_or(default_kdf_type));
+        let result = func(self.key_store);
+        self.key_store.set_kdf_type(default_kdf_type);
+        result.map_err(|err| err.to_string())
+    }
+
+    // Mnemonic words are only kept by the default keystore, the keystore plugins have no
+    // methods for them
+    fn require_default_keystore(&self, arg_name: &str) -> Result<(), String> {
+        match self.plugin_mgr.actived_keystore() {
+            Some((_, config, _)) => Err(format!(
+                "--{} is only supported by the default keystore, but keystore plugin {} is actived",
+                arg_name, config.name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
 impl<'a> CliSubCommand for AccountSubCommand<'a> {
     fn process(&mut self, matches: &ArgMatches, _debug: bool) -> Result<Output, String> {
         match matches.subcommand() {
@@ -169,6 +383,15 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                     .collect::<Vec<(H160, String)>>();
                 // Sort by file path name
                 accounts.sort_by(|a, b| a.1.cmp(&b.1));
+                if let Some(tag) = m.value_of("tag") {
+                    let key_store = &mut self.key_store;
+                    accounts.retain(|(lock_arg, _)| {
+                        key_store
+                            .get_metadata(lock_arg, false)
+                            .map(|metadata| metadata.tags.iter().any(|t| t == tag))
+                            .unwrap_or(false)
+                    });
+                }
                 let only_mainnet_address = m.is_present("only-mainnet-address");
                 let only_testnet_address = m.is_present("only-testnet-address");
                 let partial_fields = only_mainnet_address || only_testnet_address;
@@ -195,32 +418,77 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                             }
                         } else {
                             let has_ckb_root = self.key_store.get_ckb_root(&lock_arg, false).is_some();
+                            let watch_only = self.key_store.is_watch_only(&lock_arg, false);
+                            let sub_accounts = self
+                                .key_store
+                                .get_sub_ckb_roots(&lock_arg, false)
+                                .iter()
+                                .map(sub_account_json)
+                                .collect::<Vec<_>>();
+                            let metadata = self
+                                .key_store
+                                .get_metadata(&lock_arg, false)
+                                .cloned()
+                                .unwrap_or_default();
                             serde_json::json!({
                                 "#": idx,
                                 "source": source,
+                                "label": metadata.label,
+                                "notes": metadata.notes,
+                                "tags": metadata.tags,
                                 "lock_arg": format!("{:#x}", lock_arg),
                                 "lock_hash": format!("{:#x}", lock_hash),
                                 "has_ckb_root": has_ckb_root,
+                                "watch_only": watch_only,
                                 "address": {
                                     "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
                                     "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
                                 },
+                                "sub_accounts": sub_accounts,
                             })
                         }
                     })
                     .collect::<Vec<_>>();
                 Ok(Output::new_output(resp))
             }
-            ("new", _) => {
+            ("new", Some(m)) => {
+                if m.is_present("mnemonic") {
+                    self.require_default_keystore("mnemonic")?;
+                }
+                let kdf_type = read_kdf_type(m)?;
+                if kdf_type.is_some() {
+                    self.require_default_keystore("kdf")?;
+                }
                 eprintln!("Your new account is locked with a password. Please give a password. Do not forget this password.");
                 let password = read_password(true, None)?;
-                let lock_arg = self
-                    .plugin_mgr
-                    .keystore_handler()
-                    .create_account(password)?;
+                let (lock_arg, mnemonic) = if m.is_present("mnemonic") {
+                    let word_count: usize = FromStrParser::<usize>::default()
+                        .from_matches_opt(m, "mnemonic-words", false)?
+                        .unwrap_or(12);
+                    let passphrase = read_mnemonic_passphrase(m, true)?;
+                    let (lock_arg, phrase) = self.with_kdf_type(kdf_type, |key_store| {
+                        key_store.new_account_with_mnemonic(
+                            password.as_bytes(),
+                            word_count,
+                            &passphrase,
+                        )
+                    })?;
+                    (lock_arg, Some(phrase))
+                } else if kdf_type.is_some() {
+                    let lock_arg = self.with_kdf_type(kdf_type, |key_store| {
+                        key_store.new_account(password.as_bytes())
+                    })?;
+                    (lock_arg, None)
+                } else {
+                    let lock_arg = self
+                        .plugin_mgr
+                        .keystore_handler()
+                        .create_account(password)?;
+                    (lock_arg, None)
+                };
                 let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
                 let lock_hash: H256 = Script::from(&address_payload).calc_script_hash().unpack();
-                let resp = serde_json::json!({
+                let mut resp = serde_json::json!({
                     "lock_arg": format!("{:#x}", lock_arg),
                     "lock_hash": format!("{:#x}", lock_hash),
                     "address": {
@@ -228,9 +496,36 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                         "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
                     },
                 });
+                if let Some(phrase) = mnemonic {
+                    eprintln!("Write down the mnemonic words and keep them safe, anyone has the words can spend your assets.");
+                    resp["mnemonic"] = serde_json::json!(phrase);
+                }
+                Ok(Output::new_output(resp))
+            }
+            ("import", Some(m)) if m.is_present("mnemonic") => {
+                self.require_default_keystore("mnemonic")?;
+                let kdf_type = read_kdf_type(m)?;
+                let phrase = read_password(false, Some("Mnemonic words"))?;
+                let passphrase = read_mnemonic_passphrase(m, false)?;
+                let password = read_password(false, None)?;
+                let lock_arg = self.with_kdf_type(kdf_type, |key_store| {
+                    key_store.import_mnemonic(phrase.trim(), &passphrase, password.as_bytes())
+                })?;
+                let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:x}", lock_arg),
+                    "address": {
+                        "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+                        "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
+                    },
+                });
                 Ok(Output::new_output(resp))
             }
             ("import", Some(m)) => {
+                let kdf_type = read_kdf_type(m)?;
+                if kdf_type.is_some() {
+                    self.require_default_keystore("kdf")?;
+                }
                 let secp_key: Option<PrivkeyWrapper> =
                     PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
                 let password = Some(read_password(false, None)?);
@@ -245,10 +540,17 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                     master_privkey
                 };
 
-                let lock_arg = self
-                    .plugin_mgr
-                    .keystore_handler()
-                    .import_key(master_privkey, password)?;
+                let lock_arg = if let Some(kdf_type) = kdf_type {
+                    // Encrypted by the builtin keystore directly with the chosen KDF
+                    let password = password.unwrap_or_default();
+                    self.with_kdf_type(Some(kdf_type), |key_store| {
+                        key_store.import_key(&Key::new(master_privkey), password.as_bytes())
+                    })?
+                } else {
+                    self.plugin_mgr
+                        .keystore_handler()
+                        .import_key(master_privkey, password)?
+                };
                 let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
                 let resp = serde_json::json!({
                     "lock_arg": format!("{:x}", lock_arg),
@@ -267,22 +569,128 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                 let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
                 let data: serde_json::Value =
                     serde_json::from_str(&content).map_err(|err| err.to_string())?;
-                let master_privkey = Key::from_json(&data, old_password.as_bytes())
-                    .map(|key| key.master_privkey().clone())
+                let (key, format) = Key::from_keystore_json(&data, old_password.as_bytes())
                     .map_err(|err| err.to_string())?;
+                let ckb_root = key.ckb_root();
 
                 let lock_arg = self
                     .plugin_mgr
                     .keystore_handler()
-                    .import_key(master_privkey, new_password)?;
+                    .import_key(key.master_privkey().clone(), new_password)?;
                 let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
-                let resp = serde_json::json!({
+                let mut resp = serde_json::json!({
+                    "format": format.to_string(),
                     "lock_arg": format!("{:x}", lock_arg),
                     "address": {
                         "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
                         "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
                     },
                 });
+                // Wallets like Neuron only use the addresses derived from CKB root path
+                if format.is_hd() {
+                    let (path, hash160) = ckb_root.derived_hash160(KeyChain::External, 0);
+                    let payload = AddressPayload::from_pubkey_hash(hash160);
+                    resp["derivation_root"] = serde_json::json!(ckb_root.path);
+                    resp["first_receiving_address"] = serde_json::json!({
+                        "path": path.to_string(),
+                        "mainnet": Address::new(NetworkType::Mainnet, payload.clone()).to_string(),
+                        "testnet": Address::new(NetworkType::Testnet, payload).to_string(),
+                    });
+                }
+                Ok(Output::new_output(resp))
+            }
+            ("import-xpub", Some(m)) => {
+                let pubkey: secp256k1::PublicKey = PubkeyHexParser.from_matches(m, "pubkey")?;
+                let chain_code: H256 =
+                    FixedHashParser::<H256>::default().from_matches(m, "chain-code")?;
+                let ckb_root = CkbRoot::new(pubkey, chain_code.0);
+                let lock_arg = self
+                    .key_store
+                    .import_watch_only(ckb_root)
+                    .map_err(|err| err.to_string())?;
+                let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "address": {
+                        "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+                        "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
+                    },
+                });
+                Ok(Output::new_output(resp))
+            }
+            ("export-xpub", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let account_index: u32 =
+                    FromStrParser::<u32>::default().from_matches(m, "account-index")?;
+                let ckb_root = match self
+                    .key_store
+                    .get_ckb_root_by_index(&lock_arg, account_index, true)
+                    .cloned()
+                {
+                    Some(ckb_root) => ckb_root,
+                    None => {
+                        let password = read_password(false, None)?;
+                        self.key_store
+                            .ckb_root_with_password(&lock_arg, account_index, password.as_bytes())
+                            .map_err(|err| err.to_string())?
+                    }
+                };
+                let extended_pubkey = ckb_root.extended_pubkey();
+                let resp = serde_json::json!({
+                    "path": ckb_root.path,
+                    "pubkey": format!("0x{}", hex_string(&extended_pubkey.public_key.serialize()[..]).unwrap()),
+                    "chain_code": format!("{:#x}", H256::from_slice(&extended_pubkey.chain_code[..]).unwrap()),
+                });
+                Ok(Output::new_output(resp))
+            }
+            ("add-account-index", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let account_index: u32 =
+                    FromStrParser::<u32>::default().from_matches(m, "account-index")?;
+                let password = read_password(false, None)?;
+                let ckb_root = self
+                    .key_store
+                    .add_account_index(&lock_arg, account_index, password.as_bytes())
+                    .map_err(|err| err.to_string())?;
+                Ok(Output::new_output(sub_account_json(&ckb_root)))
+            }
+            ("set-metadata", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let mut metadata = if m.is_present("clear") {
+                    AccountMetadata::default()
+                } else {
+                    self.key_store
+                        .get_metadata(&lock_arg, true)
+                        .cloned()
+                        .unwrap_or_default()
+                };
+                let non_empty = |value: &str| Some(value.to_string()).filter(|s| !s.is_empty());
+                if let Some(label) = m.value_of("label") {
+                    metadata.label = non_empty(label);
+                }
+                if let Some(notes) = m.value_of("notes") {
+                    metadata.notes = non_empty(notes);
+                }
+                if let Some(tags) = m.value_of("tags") {
+                    metadata.tags = tags
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(ToOwned::to_owned)
+                        .collect();
+                }
+                self.key_store
+                    .set_metadata(&lock_arg, metadata.clone())
+                    .map_err(|err| err.to_string())?;
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "label": metadata.label,
+                    "notes": metadata.notes,
+                    "tags": metadata.tags,
+                });
                 Ok(Output::new_output(resp))
             }
             ("update", Some(m)) => {
@@ -306,6 +714,73 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                     .map_err(|err| err.to_string())?;
                 Ok(Output::new_success())
             }
+            ("rekey", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let kdf_type = read_kdf_type(m)?.expect("kdf is required");
+                let password = read_password(false, None)?;
+                self.key_store
+                    .rekey(&lock_arg, password.as_bytes(), kdf_type)
+                    .map_err(|err| err.to_string())?;
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "kdf": kdf_type.kdf(),
+                });
+                Ok(Output::new_output(resp))
+            }
+            ("unlock", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let keep: Option<Duration> =
+                    DurationParser.from_matches_opt(m, "duration", false)?;
+                let agent = self.agent_client(m)?;
+                let password = read_password(false, None)?;
+                let timeout = if let Some(agent) = agent {
+                    agent.unlock(lock_arg.clone(), password, keep)?
+                } else {
+                    self.plugin_mgr
+                        .keystore_handler()
+                        .unlock(lock_arg.clone(), password, keep)?
+                };
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "status": timeout,
+                });
+                Ok(Output::new_output(resp))
+            }
+            ("lock", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let locked = if let Some(agent) = self.agent_client(m)? {
+                    agent.lock(lock_arg.clone())?
+                } else {
+                    self.plugin_mgr.keystore_handler().lock(lock_arg.clone())?
+                };
+                if !locked {
+                    return Err(format!("Account {:#x} is not unlocked", lock_arg));
+                }
+                Ok(Output::new_success())
+            }
+            ("agent", Some(m)) => {
+                let path = m
+                    .value_of("socket")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| agent_socket_path(self.plugin_mgr.ckb_cli_dir()));
+                run_agent(self.plugin_mgr.ckb_cli_dir(), path)?;
+                Ok(Output::new_success())
+            }
+            ("export", Some(m)) if m.is_present("mnemonic") => {
+                self.require_default_keystore("mnemonic")?;
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let password = read_password(false, None)?;
+                let phrase = self
+                    .key_store
+                    .export_mnemonic(&lock_arg, password.as_bytes())
+                    .map_err(|err| err.to_string())?;
+                let resp = serde_json::json!({ "mnemonic": phrase });
+                Ok(Output::new_output(resp))
+            }
             ("export", Some(m)) => {
                 let lock_arg: H160 =
                     FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
@@ -335,6 +810,80 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                 });
                 Ok(Output::new_error(resp))
             }
+            ("backup-shares", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let threshold: u8 = FromStrParser::<u8>::default().from_matches(m, "threshold")?;
+                let count: u8 = FromStrParser::<u8>::default().from_matches(m, "shares")?;
+                if threshold == 0 || threshold > count || count > MAX_SHARE_COUNT {
+                    return Err(format!(
+                        "Invalid threshold {} of {} shares, required: 1 <= threshold <= shares <= {}",
+                        threshold, count, MAX_SHARE_COUNT
+                    ));
+                }
+                let password = Some(read_password(false, None)?);
+                let master_privkey = self
+                    .plugin_mgr
+                    .keystore_handler()
+                    .export_key(lock_arg.clone(), password)?;
+                let mut bytes = master_privkey.to_bytes();
+                let shares = split_secret(&bytes, threshold, count);
+                zeroize_slice(&mut bytes);
+                let shares = shares.map_err(|err| err.to_string())?;
+                eprintln!(
+                    "Keep the shares in different places, anyone has {} of them can spend your assets.",
+                    threshold
+                );
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "threshold": threshold,
+                    "shares": shares.iter().map(Share::to_string).collect::<Vec<_>>(),
+                });
+                Ok(Output::new_output(resp))
+            }
+            ("restore-shares", Some(_)) => {
+                let first: Share = read_password(false, Some("Share 1"))?
+                    .parse()
+                    .map_err(|err: KeyStoreError| err.to_string())?;
+                let threshold = first.threshold;
+                let mut shares = vec![first];
+                for number in 2..=threshold {
+                    let share: Share =
+                        read_password(false, Some(format!("Share {}", number).as_str()))?
+                            .parse()
+                            .map_err(|err: KeyStoreError| err.to_string())?;
+                    shares.push(share);
+                }
+                let mut secret = combine_shares(&shares).map_err(|err| err.to_string())?;
+                if secret.len() != 64 {
+                    zeroize_slice(&mut secret);
+                    return Err(format!(
+                        "Invalid master private key length: {}",
+                        secret.len()
+                    ));
+                }
+                let mut bytes = [0u8; 64];
+                bytes.copy_from_slice(&secret);
+                zeroize_slice(&mut secret);
+                let master_privkey = MasterPrivKey::from_bytes(bytes);
+                zeroize_slice(&mut bytes);
+                let master_privkey = master_privkey.map_err(|err| err.to_string())?;
+
+                let password = read_password(true, None)?;
+                let lock_arg = self
+                    .key_store
+                    .import_key(&Key::new(master_privkey), password.as_bytes())
+                    .map_err(|err| err.to_string())?;
+                let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "address": {
+                        "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+                        "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
+                    },
+                });
+                Ok(Output::new_output(resp))
+            }
             ("bip44-addresses", Some(m)) => {
                 let lock_arg: H160 =
                     FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
@@ -346,6 +895,8 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                     FromStrParser::<u32>::default().from_matches(m, "from-change-index")?;
                 let change_length: u32 =
                     FromStrParser::<u32>::default().from_matches(m, "change-length")?;
+                let account_index: u32 =
+                    FromStrParser::<u32>::default().from_matches(m, "account-index")?;
                 let network = match m.value_of("network").expect("network argument") {
                     "mainnet" => NetworkType::Mainnet,
                     "testnet" => NetworkType::Testnet,
@@ -357,6 +908,7 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                     .keystore_handler()
                     .derived_key_set_by_index(
                         lock_arg,
+                        account_index,
                         from_receiving_index,
                         receiving_length,
                         from_change_index,
@@ -388,7 +940,7 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                     .from_matches_opt(m, "path", false)?
                     .unwrap_or(root_key_path);
 
-                let password = if self.plugin_mgr.keystore_require_password() {
+                let password = if self.plugin_mgr.account_require_password(&lock_arg) {
                     Some(read_password(false, None)?)
                 } else {
                     None
@@ -411,3 +963,44 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
         }
     }
 }
+
+// A label must not be confused with lock arg or address when used as --from-account
+fn validate_label(input: &str) -> Result<(), String> {
+    if input.is_empty() {
+        return Ok(());
+    }
+    AccountMetadata::check_label(input).map_err(|err| err.to_string())?;
+    if AddressParser::default().validate(input).is_ok() {
+        return Err(format!("Invalid label {:?}: it is an address", input));
+    }
+    Ok(())
+}
+
+// The BIP-39 passphrase is empty unless `--mnemonic-passphrase` is given
+fn read_mnemonic_passphrase(m: &ArgMatches, repeat: bool) -> Result<String, String> {
+    if m.is_present("mnemonic-passphrase") {
+        read_password(repeat, Some("BIP-39 passphrase"))
+    } else {
+        Ok(String::new())
+    }
+}
+
+fn read_kdf_type(m: &ArgMatches) -> Result<Option<KdfType>, String> {
+    m.value_of("kdf")
+        .map(|kdf| KdfType::parse(kdf, m.value_of("kdf-params")).map_err(|err| err.to_string()))
+        .transpose()
+}
+
+// A BIP-44 account (index > 0) is identified by the lock arg of its account level key
+fn sub_account_json(ckb_root: &CkbRoot) -> serde_json::Value {
+    let address_payload = AddressPayload::from_pubkey_hash(ckb_root.hash160());
+    serde_json::json!({
+        "account_index": ckb_root.account_index(),
+        "path": ckb_root.path,
+        "lock_arg": format!("{:#x}", ckb_root.hash160()),
+        "address": {
+            "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+            "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
+        },
+    })
+}
@@ -5,13 +5,14 @@ use std::thread;
 use std::time::Duration;
 
 use ckb_crypto::secp::SECP256K1;
+use ckb_jsonrpc_types as json_types;
 use ckb_sdk::{Address, AddressPayload, GenesisInfo, HttpRpcClient, HumanCapacity, NetworkType};
 use ckb_types::{
     bytes::Bytes,
     core::{service::Request, BlockView},
     packed::Script,
     prelude::*,
-    H256,
+    H160, H256,
 };
 use clap::{App, Arg, ArgMatches};
 use jsonrpc_core::{Error as RpcError, ErrorCode as RpcErrorCode, IoHandler, Result as RpcResult};
@@ -21,13 +22,13 @@ use jsonrpc_server_utils::cors::AccessControlAllowOrigin;
 use jsonrpc_server_utils::hosts::DomainsValidation;
 use serde::{Deserialize, Serialize};
 
-use super::{CliSubCommand, LiveCells, Output, TransferArgs, WalletSubCommand};
+use super::{CliSubCommand, LiveCells, Output, TransferArgs, TransferOutput, WalletSubCommand};
 use crate::plugin::PluginManager;
 use crate::utils::{
     arg,
     arg_parser::{AddressParser, ArgParser, FromStrParser, PrivkeyPathParser, PrivkeyWrapper},
     index::{IndexController, IndexRequest},
-    other::get_network_type,
+    other::{get_account, get_network_type},
 };
 
 pub struct ApiServerSubCommand<'a> {
@@ -65,12 +66,17 @@ impl<'a> ApiServerSubCommand<'a> {
                     .required(true)
                     .default_value("127.0.0.1:3000")
                     .validator(|input| FromStrParser::<SocketAddr>::new().validate(input))
-                    .about("Rpc server listen address (when --privkey-path is given ip MUST be 127.0.0.1)"),
+                    .about("Rpc server listen address (when --privkey-path or --from-account is given ip MUST be 127.0.0.1)"),
             )
             .arg(
                 arg::privkey_path()
                  .about("Private key file path (only read first line)")
             )
+            .arg(
+                arg::from_account()
+                    .conflicts_with("privkey-path")
+                    .about("Transfer from this account, it is signed by the keystore agent and must be unlocked there (see: `account agent`)")
+            )
     }
 }
 
@@ -81,9 +87,17 @@ impl<'a> CliSubCommand for ApiServerSubCommand<'a> {
         let privkey_path: Option<String> = matches.value_of("privkey-path").map(Into::into);
 
         let network_result = get_network_type(self.rpc_client);
-        if privkey_path.is_some() && listen_addr.ip() != IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)) {
+        let from_account: Option<H160> = get_account(
+            self.plugin_mgr.as_ref().unwrap().ckb_cli_dir(),
+            network_result.as_ref().ok().cloned(),
+            matches,
+            "from-account",
+        )?;
+        if (privkey_path.is_some() || from_account.is_some())
+            && listen_addr.ip() != IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))
+        {
             return Err(format!(
-                "When privkey-path is given, listen ip MUST be 127.0.0.1, current ip: {}",
+                "When privkey-path or from-account is given, listen ip MUST be 127.0.0.1, current ip: {}",
                 listen_addr.ip()
             ));
         }
@@ -98,11 +112,13 @@ impl<'a> CliSubCommand for ApiServerSubCommand<'a> {
             }
             Err(_) => NetworkType::Mainnet,
         };
-        let address_opt = privkey_opt.map(|privkey| {
-            let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, &privkey);
-            let payload = AddressPayload::from_pubkey(&pubkey);
-            Address::new(network, payload).to_string()
-        });
+        let address_opt = privkey_opt
+            .map(|privkey| {
+                let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, &privkey);
+                AddressPayload::from_pubkey(&pubkey)
+            })
+            .or_else(|| from_account.clone().map(AddressPayload::from_pubkey_hash))
+            .map(|payload| Address::new(network, payload).to_string());
 
         Request::call(self.index_controller.sender(), IndexRequest::Kick);
 
@@ -114,6 +130,7 @@ impl<'a> CliSubCommand for ApiServerSubCommand<'a> {
             plugin_mgr: Arc::new(Mutex::new(self.plugin_mgr.take().unwrap())),
             genesis_info: Arc::new(Mutex::new(self.genesis_info.clone())),
             privkey_path,
+            from_account,
             index_dir: self.index_dir.clone(),
             index_controller: self.index_controller.clone(),
         };
@@ -212,6 +229,7 @@ struct ApiRpcImpl {
     plugin_mgr: Arc<Mutex<PluginManager>>,
     genesis_info: Arc<Mutex<Option<GenesisInfo>>>,
     privkey_path: Option<String>,
+    from_account: Option<H160>,
     index_dir: PathBuf,
     index_controller: IndexController,
 }
@@ -253,17 +271,35 @@ impl ApiRpcImpl {
 impl ApiRpc for ApiRpcImpl {
     fn transfer(&self, args: HttpTransferArgs) -> RpcResult<H256> {
         log::info!("[call]: tranfer({:?})", args);
-        if let Some(privkey_path) = self.privkey_path.clone() {
-            self.with_wallet(|cmd| {
-                cmd.transfer(args.into_full_args(privkey_path), false)
-                    .map_err(RpcError::invalid_params)
-            })
-            .map(|tx| tx.hash().unpack())
-        } else {
-            Err(internal_err(
-                "Please give privkey-path argument to enable transfer api".to_string(),
-            ))
+        if self.privkey_path.is_none() && self.from_account.is_none() {
+            return Err(internal_err(
+                "Please give privkey-path or from-account argument to enable transfer api"
+                    .to_string(),
+            ));
+        }
+        if let Some(ref account) = self.from_account {
+            // Never prompt for password in server
+            let unlocked = self
+                .plugin_mgr
+                .lock()
+                .unwrap()
+                .keystore_handler()
+                .is_unlocked(account.clone())
+                .map_err(internal_err)?;
+            if !unlocked {
+                return Err(internal_err(format!(
+                    "Account {:#x} is locked, unlock it by `ckb-cli account unlock --agent`",
+                    account
+                )));
+            }
         }
+        self.with_wallet(|cmd| {
+            let args = args
+                .into_full_args(self.privkey_path.clone(), self.from_account.clone())
+                .map_err(RpcError::invalid_params)?;
+            cmd.transfer(args, false).map_err(RpcError::invalid_params)
+        })
+        .map(|(tx, _)| tx.hash().unpack())
     }
 
     fn get_capacity_by_address(&self, address: String) -> RpcResult<GetCapacityResponse> {
@@ -285,10 +321,15 @@ impl ApiRpc for ApiRpcImpl {
         let lock_hashes = vec![lock_hash.pack()];
         self.with_wallet(|cmd| {
             cmd.get_capacity(lock_hashes)
-                .map(|(total, immature, dao)| GetCapacityResponse {
-                    total,
-                    immature,
-                    dao,
+                .map(|breakdown| GetCapacityResponse {
+                    total: breakdown.total,
+                    immature: breakdown.immature,
+                    dao: breakdown.dao(),
+                    free: breakdown.free,
+                    time_locked: breakdown.time_locked,
+                    dao_deposited: breakdown.dao_deposited,
+                    dao_prepared: breakdown.dao_prepared,
+                    occupied: breakdown.occupied,
                 })
                 .map_err(RpcError::invalid_params)
         })
@@ -419,30 +460,103 @@ fn internal_err(message: String) -> RpcError {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct HttpTransferArgs {
-    pub capacity: u64,
-    pub tx_fee: u64,
-    pub to_address: String,
+    pub capacity: Option<u64>,
+    pub tx_fee: Option<u64>,
+    // Unit: shannons/KB (conflicts with tx_fee)
+    pub fee_rate: Option<u64>,
+    pub to_address: Option<String>,
+    // Transfer to a lock script directly (conflicts with to_address)
+    pub to_lock_script: Option<json_types::Script>,
     pub from_locked_address: Option<String>,
     pub to_data: Option<Bytes>,
+    // Transfer to multiple recipients (conflicts with capacity/to_address/to_lock_script/to_data)
+    pub outputs: Option<Vec<HttpTransferOutput>>,
+    // Allow lock scripts other than sighash and multisig
+    pub allow_unknown_lock: Option<bool>,
+    // Also spend the outputs of transactions sent but not committed yet
+    pub use_pending_change: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpTransferOutput {
+    pub capacity: u64,
+    pub to_address: Option<String>,
+    pub to_lock_script: Option<json_types::Script>,
+    pub to_data: Option<Bytes>,
+}
+
+impl From<HttpTransferOutput> for TransferOutput {
+    fn from(output: HttpTransferOutput) -> TransferOutput {
+        TransferOutput {
+            to_address: output.to_address,
+            to_lock_script: output.to_lock_script,
+            capacity: HumanCapacity::from(output.capacity).to_string(),
+            to_data: output.to_data,
+        }
+    }
 }
 
 impl HttpTransferArgs {
-    pub fn into_full_args(self, privkey_path: String) -> TransferArgs {
-        let capacity = HumanCapacity::from(self.capacity).to_string();
-        let tx_fee = HumanCapacity::from(self.tx_fee).to_string();
-        TransferArgs {
-            privkey_path: Some(privkey_path),
-            from_account: None,
+    pub fn into_full_args(
+        self,
+        privkey_path: Option<String>,
+        from_account: Option<H160>,
+    ) -> Result<TransferArgs, String> {
+        let (tx_fee, fee_rate) = match (self.tx_fee, self.fee_rate) {
+            (Some(tx_fee), None) => (Some(HumanCapacity::from(tx_fee).to_string()), None),
+            (None, Some(fee_rate)) => (None, Some(fee_rate.to_string())),
+            _ => return Err("One of tx_fee and fee_rate is required".to_string()),
+        };
+        let outputs = match (self.outputs, self.capacity) {
+            (Some(outputs), None)
+                if self.to_address.is_none()
+                    && self.to_lock_script.is_none()
+                    && self.to_data.is_none() =>
+            {
+                outputs.into_iter().map(TransferOutput::from).collect()
+            }
+            (Some(_), _) => {
+                return Err(
+                    "outputs can not be used with capacity/to_address/to_lock_script/to_data"
+                        .to_string(),
+                )
+            }
+            (None, Some(capacity))
+                if self.to_address.is_some() != self.to_lock_script.is_some() =>
+            {
+                vec![TransferOutput {
+                    to_address: self.to_address,
+                    to_lock_script: self.to_lock_script,
+                    capacity: HumanCapacity::from(capacity).to_string(),
+                    to_data: self.to_data,
+                }]
+            }
+            (None, _) => {
+                return Err(
+                    "capacity and one of to_address and to_lock_script are required".to_string(),
+                );
+            }
+        };
+        Ok(TransferArgs {
+            privkey_path,
+            from_account: from_account.map(|account| format!("{:#x}", account)),
+            account_index: None,
             from_locked_address: self.from_locked_address,
             password: None,
             derive_receiving_address_length: None,
             derive_change_address: None,
-            capacity,
+            derive_change_address_length: None,
             tx_fee,
-            to_address: self.to_address,
-            to_data: self.to_data,
+            fee_rate,
+            coin_selection: None,
+            outputs,
             is_type_id: false,
-        }
+            allow_unknown_lock: self.allow_unknown_lock.unwrap_or(false),
+            transfer_all: false,
+            min_cell_capacity: None,
+            use_pending_change: self.use_pending_change.unwrap_or(false),
+        })
     }
 }
 
@@ -451,4 +565,9 @@ pub struct GetCapacityResponse {
     pub total: u64,
     pub immature: u64,
     pub dao: u64,
+    pub free: u64,
+    pub time_locked: u64,
+    pub dao_deposited: u64,
+    pub dao_prepared: u64,
+    pub occupied: u64,
 }
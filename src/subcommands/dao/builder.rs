@@ -1,7 +1,8 @@
-use super::util::minimal_unlock_point;
-use crate::subcommands::dao::util::calculate_dao_maximum_withdraw4;
 use ckb_index::LiveCellInfo;
-use ckb_sdk::{constants::MIN_SECP_CELL_CAPACITY, GenesisInfo, HttpRpcClient, Since, SinceType};
+use ckb_sdk::{
+    calculate_dao_maximum_withdraw4, constants::MIN_SECP_CELL_CAPACITY, minimal_unlock_point,
+    GenesisInfo, HttpRpcClient, Since, SinceType,
+};
 use ckb_types::core::Capacity;
 use ckb_types::{
     bytes::Bytes,
@@ -11,11 +12,12 @@ use ckb_types::{
 };
 use std::collections::HashSet;
 
-// NOTE: We assume all inputs are from same account
+// NOTE: We assume all inputs are from same account (the root and HD derived addresses)
 #[derive(Debug)]
 pub(crate) struct DAOBuilder {
     genesis_info: GenesisInfo,
     tx_fee: u64,
+    change_lock: Script,
     live_cells: Vec<LiveCellInfo>,
 }
 
@@ -23,11 +25,13 @@ impl DAOBuilder {
     pub(crate) fn new(
         genesis_info: GenesisInfo,
         tx_fee: u64,
+        change_lock: Script,
         live_cells: Vec<LiveCellInfo>,
     ) -> Self {
         Self {
             genesis_info,
             tx_fee,
+            change_lock,
             live_cells,
         }
     }
@@ -65,6 +69,7 @@ impl DAOBuilder {
         if change_capacity >= MIN_SECP_CELL_CAPACITY {
             let change = CellOutput::new_builder()
                 .capacity(change_capacity.pack())
+                .lock(self.change_lock.clone())
                 .build();
             Ok(tx.output(change).output_data(Default::default()).build())
         } else {
@@ -132,12 +137,18 @@ impl DAOBuilder {
             .witnesses(witnesses)
             .outputs_data(outputs_data);
 
+        // The rest less than a secp cell is paid as fee, the same as deposit
         let change_capacity =
             change_cells.iter().map(|txo| txo.capacity).sum::<u64>() - self.tx_fee;
-        let change = CellOutput::new_builder()
-            .capacity(change_capacity.pack())
-            .build();
-        Ok(tx.output(change).output_data(Default::default()).build())
+        if change_capacity >= MIN_SECP_CELL_CAPACITY {
+            let change = CellOutput::new_builder()
+                .capacity(change_capacity.pack())
+                .lock(self.change_lock.clone())
+                .build();
+            Ok(tx.output(change).output_data(Default::default()).build())
+        } else {
+            Ok(tx.build())
+        }
     }
 
     pub(crate) fn withdraw(
@@ -201,6 +212,8 @@ impl DAOBuilder {
                     occupied_capacity.as_u64(),
                 )
             })
+            .collect::<Result<Vec<_>, String>>()?
+            .into_iter()
             .sum::<u64>();
         let output_capacity = total_capacity - self.tx_fee;
         let output = CellOutput::new_builder()
@@ -1,81 +1,139 @@
-use crate::subcommands::dao::util::{calculate_dao_maximum_withdraw, send_transaction};
+use crate::subcommands::dao::util::{estimate_deposit, estimate_epoch_timestamp, estimate_prepare};
 use crate::subcommands::{CliSubCommand, DAOSubCommand, Output};
 use crate::utils::{
     arg,
     arg_parser::{
-        AddressParser, ArgParser, CapacityParser, FixedHashParser, OutPointParser,
-        PrivkeyPathParser, PrivkeyWrapper,
+        AddressParser, ArgParser, CapacityParser, FilePathParser, FixedHashParser, FromStrParser,
+        OutPointParser, PrivkeyPathParser, PrivkeyWrapper,
     },
-    other::{get_address, get_network_type},
+    other::{get_account, get_address, get_network_type},
 };
+use chrono::prelude::*;
 use ckb_crypto::secp::SECP256K1;
-use ckb_sdk::{constants::SIGHASH_TYPE_HASH, Address, AddressPayload, NetworkType};
+use ckb_sdk::{
+    constants::MULTISIG_TYPE_HASH, is_epoch_reached, Address, AddressPayload, CoinSelection,
+    NetworkType, Since, SinceType,
+};
 use ckb_types::{
+    core::HeaderView,
     packed::{Byte32, Script},
     prelude::*,
     H160, H256,
 };
 use clap::{App, Arg, ArgMatches};
 use std::collections::HashSet;
+use std::path::PathBuf;
 
 impl<'a> CliSubCommand for DAOSubCommand<'a> {
     fn process(&mut self, matches: &ArgMatches, debug: bool) -> Result<Output, String> {
         let network_type = get_network_type(&mut self.rpc_client)?;
         match matches.subcommand() {
             ("deposit", Some(m)) => {
-                self.transact_args = Some(TransactArgs::from_matches(m, network_type)?);
+                self.set_transact_args(TransactArgs::from_matches(
+                    m,
+                    network_type,
+                    self.plugin_mgr.ckb_cli_dir(),
+                )?)?;
                 let capacity: u64 = CapacityParser.from_matches(m, "capacity")?;
                 let transaction = self.deposit(capacity)?;
-                send_transaction(self.rpc_client(), transaction, debug)
+                self.send_or_save(transaction, debug)
             }
             ("prepare", Some(m)) => {
-                self.transact_args = Some(TransactArgs::from_matches(m, network_type)?);
+                self.set_transact_args(TransactArgs::from_matches(
+                    m,
+                    network_type,
+                    self.plugin_mgr.ckb_cli_dir(),
+                )?)?;
                 let out_points = OutPointParser.from_matches_vec(m, "out-point")?;
                 if out_points.len() != out_points.iter().collect::<HashSet<_>>().len() {
                     return Err("Duplicated out-points".to_string());
                 }
                 let transaction = self.prepare(out_points)?;
-                send_transaction(self.rpc_client(), transaction, debug)
+                self.send_or_save(transaction, debug)
             }
             ("withdraw", Some(m)) => {
-                self.transact_args = Some(TransactArgs::from_matches(m, network_type)?);
+                self.set_transact_args(TransactArgs::from_matches(
+                    m,
+                    network_type,
+                    self.plugin_mgr.ckb_cli_dir(),
+                )?)?;
                 let out_points = OutPointParser.from_matches_vec(m, "out-point")?;
                 if out_points.len() != out_points.iter().collect::<HashSet<_>>().len() {
                     return Err("Duplicated out-points".to_string());
                 }
-                let transaction = self.withdraw(out_points)?;
-                send_transaction(self.rpc_client(), transaction, debug)
+                let only_unlocked = m.is_present("only-unlocked");
+                let transaction = self.withdraw(out_points, only_unlocked)?;
+                self.send_or_save(transaction, debug)
             }
             ("query-deposited-cells", Some(m)) => {
                 let query_args = QueryArgs::from_matches(m, network_type)?;
                 let lock_hash = query_args.lock_hash;
-                let cells = self.query_deposit_cells(lock_hash)?;
+                let cells = self.query_deposit_cells(vec![lock_hash])?;
                 let total_capacity = cells.iter().map(|live| live.capacity).sum::<u64>();
+                // Estimated as if the cells are prepared at the tip block
+                let tip_header: HeaderView = self.rpc_client().get_tip_header()?.into();
+                let estimates = cells
+                    .iter()
+                    .map(|cell| estimate_deposit(self.rpc_client(), cell, &tip_header))
+                    .collect::<Result<Vec<_>, String>>()?;
+                let total_compensation = estimates
+                    .iter()
+                    .map(|estimate| estimate.compensation)
+                    .sum::<u64>();
                 let resp = serde_json::json!({
-                    "live_cells": cells.into_iter().map(|info| {
-                        serde_json::to_value(&info).unwrap()
+                    "live_cells": cells.iter().zip(estimates.iter()).map(|(info, estimate)| {
+                        let mut value = serde_json::to_value(info).unwrap();
+                        let obj = value.as_object_mut().unwrap();
+                        obj.insert("compensation".to_owned(), serde_json::json!(estimate.compensation));
+                        obj.insert(
+                            "annualized_rate".to_owned(),
+                            serde_json::json!(estimate.annualized_rate.map(|rate| format!("{:.2}%", rate * 100.0))),
+                        );
+                        obj.insert("cycle_end_epoch".to_owned(), serde_json::json!(estimate.cycle_end.to_string()));
+                        obj.insert(
+                            "epochs_to_cycle_end".to_owned(),
+                            serde_json::json!(estimate.cycle_end.number().saturating_sub(tip_header.epoch().number())),
+                        );
+                        value
                     }).collect::<Vec<_>>(),
                     "total_capacity": total_capacity,
+                    "total_compensation": total_compensation,
+                    "tip_epoch": tip_header.epoch().to_string(),
                 });
                 Ok(Output::new_output(resp))
             }
             ("query-prepared-cells", Some(m)) => {
                 let query_args = QueryArgs::from_matches(m, network_type)?;
                 let lock_hash = query_args.lock_hash;
-                let cells = self.query_prepare_cells(lock_hash)?;
-                let maximum_withdraws: Vec<_> = cells
+                let cells = self.query_prepare_cells(vec![lock_hash])?;
+                let tip_header: HeaderView = self.rpc_client().get_tip_header()?.into();
+                let estimates = cells
+                    .iter()
+                    .map(|cell| estimate_prepare(self.rpc_client(), cell))
+                    .collect::<Result<Vec<_>, String>>()?;
+                let total_maximum_withdraw = estimates
                     .iter()
-                    .map(|cell| calculate_dao_maximum_withdraw(self.rpc_client(), cell))
-                    .collect::<Result<Vec<u64>, String>>()?;
-                let total_maximum_withdraw = maximum_withdraws.iter().sum::<u64>();
+                    .map(|estimate| estimate.maximum_withdraw)
+                    .sum::<u64>();
                 let resp = serde_json::json!({
-                    "live_cells": (0..cells.len()).map(|i| {
-                        let mut value = serde_json::to_value(&cells[i]).unwrap();
+                    "live_cells": cells.iter().zip(estimates.iter()).map(|(info, estimate)| {
+                        let mut value = serde_json::to_value(info).unwrap();
                         let obj = value.as_object_mut().unwrap();
-                        obj.insert("maximum_withdraw".to_owned(), serde_json::json!(maximum_withdraws[i]));
+                        obj.insert("maximum_withdraw".to_owned(), serde_json::json!(estimate.maximum_withdraw));
+                        obj.insert("unlock_epoch".to_owned(), serde_json::json!(estimate.unlock_point.to_string()));
+                        obj.insert(
+                            "unlocked".to_owned(),
+                            serde_json::json!(is_epoch_reached(tip_header.epoch(), estimate.unlock_point)),
+                        );
+                        let unlock_timestamp = estimate_epoch_timestamp(&tip_header, estimate.unlock_point);
+                        obj.insert(
+                            "estimated_unlock_time".to_owned(),
+                            serde_json::json!(Utc.timestamp_millis(unlock_timestamp as i64).to_rfc3339()),
+                        );
                         value
                     }).collect::<Vec<_>>(),
                     "total_maximum_withdraw": total_maximum_withdraw,
+                    "tip_epoch": tip_header.epoch().to_string(),
                 });
                 Ok(Output::new_output(resp))
             }
@@ -92,7 +150,8 @@ impl<'a> DAOSubCommand<'a> {
                 App::new("deposit")
                     .about("Deposit capacity into NervosDAO")
                     .args(&TransactArgs::args())
-                    .arg(arg::capacity().required(true)),
+                    .arg(arg::capacity().required(true))
+                    .arg(arg::coin_selection()),
                 App::new("prepare")
                     .about("Prepare specified cells from NervosDAO")
                     .args(&TransactArgs::args())
@@ -100,9 +159,14 @@ impl<'a> DAOSubCommand<'a> {
                 App::new("withdraw")
                     .about("Withdraw specified cells from NervosDAO")
                     .args(&TransactArgs::args())
-                    .arg(arg::out_point().required(true).multiple(true)),
+                    .arg(arg::out_point().required_unless("only-unlocked").multiple(true))
+                    .arg(
+                        Arg::with_name("only-unlocked")
+                            .long("only-unlocked")
+                            .about("Only withdraw the cells already unlocked at the tip and skip the others (all prepared cells when no out-point is given)")
+                    ),
                 App::new("query-deposited-cells")
-                    .about("Query NervosDAO deposited capacity by lock script hash or address")
+                    .about("Query NervosDAO deposited capacity by lock script hash or address, with the compensation estimated at the tip")
                     .args(&QueryArgs::args()),
                 App::new("query-prepared-cells")
                     .about("Query NervosDAO prepared capacity by lock script hash or address")
@@ -117,8 +181,23 @@ pub(crate) struct QueryArgs {
 
 pub(crate) struct TransactArgs {
     pub(crate) privkey: Option<PrivkeyWrapper>,
+    // Sighash address of the signer, or the multisig address given by <from-multisig-address>
     pub(crate) address: Address,
+    // The keystore account given by <from-account>
+    pub(crate) from_account: Option<H160>,
+    // The unsigned transaction is saved to this file when spending from a multisig address
+    pub(crate) tx_file: Option<PathBuf>,
     pub(crate) tx_fee: u64,
+    // Unit: shannons/KB, the tx_fee is calculated by transaction size when given
+    pub(crate) fee_rate: Option<u64>,
+    // Only used by deposit, None means collect live cells by block order
+    pub(crate) coin_selection: Option<CoinSelection>,
+    // The last HD change address of <from-account>, the derived addresses are also spent and
+    // the change goes to the next change address when given
+    pub(crate) derive_change_address: Option<Address>,
+    pub(crate) derive_receiving_address_length: u32,
+    // The BIP-44 account of <from-account> to spend from and derive addresses from
+    pub(crate) account_index: u32,
 }
 
 impl QueryArgs {
@@ -141,52 +220,140 @@ impl QueryArgs {
 }
 
 impl TransactArgs {
-    fn from_matches(m: &ArgMatches, network_type: NetworkType) -> Result<Self, String> {
+    fn from_matches(
+        m: &ArgMatches,
+        network_type: NetworkType,
+        ckb_cli_dir: &PathBuf,
+    ) -> Result<Self, String> {
         let privkey: Option<PrivkeyWrapper> =
             PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
-        let address = if let Some(privkey) = privkey.as_ref() {
+        let multisig_address_opt: Option<Address> = AddressParser::default()
+            .set_network(network_type)
+            .from_matches_opt(m, "from-multisig-address", false)?;
+        let tx_file: Option<PathBuf> =
+            FilePathParser::new(true).from_matches_opt(m, "tx-file", false)?;
+        let address = if let Some(address) = multisig_address_opt {
+            if address.payload().code_hash() != MULTISIG_TYPE_HASH.pack() {
+                return Err(format!("Not a multisig address: {}", address));
+            }
+            let args_len = address.payload().args().len();
+            if args_len != 20 && args_len != 28 {
+                return Err(format!(
+                    "Invalid multisig address args length: {}",
+                    args_len
+                ));
+            }
+            if tx_file.is_none() {
+                return Err(String::from(
+                    "<tx-file> is required by <from-multisig-address>",
+                ));
+            }
+            if let Some(since) = lock_since(&address) {
+                let since = Since::from_raw_value(since);
+                if !since.flags_is_valid()
+                    || !since.is_absolute()
+                    || since.extract_metric().map(|(ty, _)| ty)
+                        != Some(SinceType::EpochNumberWithFraction)
+                {
+                    return Err(String::from(
+                        "Only support absolute epoch since in time locked multisig address",
+                    ));
+                }
+            }
+            address
+        } else if let Some(privkey) = privkey.as_ref() {
             let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
             let payload = AddressPayload::from_pubkey(&pubkey);
             Address::new(network_type, payload)
         } else {
-            let account: H160 = FixedHashParser::<H160>::default()
-                .from_matches_opt(m, "from-account", false)
-                .or_else(|err| {
-                    let result: Result<Option<Address>, String> = AddressParser::new_sighash()
-                        .set_network(network_type)
-                        .from_matches_opt(m, "from-account", false);
-                    result
-                        .map(|address_opt| {
-                            address_opt
-                                .map(|address| H160::from_slice(&address.payload().args()).unwrap())
-                        })
-                        .map_err(|_| format!("Invalid value for '--from-account': {}", err))
-                })?
+            let account: H160 = get_account(ckb_cli_dir, Some(network_type), m, "from-account")?
                 .ok_or_else(|| {
                     // It's a bug of clap, otherwise if <privkey-path> is not given <from-account> must required.
                     // The bug only happen when put <tx-fee> before <out-point>.
                     String::from("<privkey-path> or <from-account> is required!")
                 })?;
+            from_account = Some(account.clone());
             let payload = AddressPayload::from_pubkey_hash(account);
             Address::new(network_type, payload)
         };
-        assert_eq!(address.payload().code_hash(), SIGHASH_TYPE_HASH.pack());
-        let tx_fee: u64 = CapacityParser.from_matches(m, "tx-fee")?;
+        let fee_rate: Option<u64> =
+            FromStrParser::<u64>::default().from_matches_opt(m, "fee-rate", false)?;
+        let tx_fee: u64 = if fee_rate.is_some() {
+            0
+        } else {
+            CapacityParser.from_matches(m, "tx-fee")?
+        };
+        let coin_selection: Option<CoinSelection> = m
+            .value_of("coin-selection")
+            .map(|input| input.parse())
+            .transpose()?;
+        let derive_change_address: Option<Address> = AddressParser::new_sighash()
+            .set_network(network_type)
+            .from_matches_opt(m, "derive-change-address", false)?;
+        let derive_receiving_address_length: u32 =
+            FromStrParser::<u32>::default().from_matches(m, "derive-receiving-address-length")?;
+        let account_index: u32 =
+            FromStrParser::<u32>::default().from_matches(m, "account-index")?;
+        if account_index > 0 && from_account.is_none() {
+            return Err("account-index is only for from-account".to_string());
+        }
         Ok(Self {
             privkey,
             address,
+            from_account,
+            tx_file,
             tx_fee,
+            fee_rate,
+            coin_selection,
+            derive_change_address,
+            derive_receiving_address_length,
+            account_index,
         })
     }
 
     fn args<'a>() -> Vec<Arg<'a>> {
         vec![
-            arg::privkey_path().required_unless(arg::from_account().get_name()),
-            arg::from_account().required_unless(arg::privkey_path().get_name()),
-            arg::tx_fee().required(true),
+            arg::privkey_path()
+                .required_unless_one(&[arg::from_account().get_name(), "from-multisig-address"]),
+            arg::from_account()
+                .required_unless_one(&[arg::privkey_path().get_name(), "from-multisig-address"]),
+            Arg::with_name("from-multisig-address")
+                .long("from-multisig-address")
+                .takes_value(true)
+                .conflicts_with_all(&[arg::privkey_path().get_name(), arg::from_account().get_name()])
+                .requires("tx-file")
+                .validator(|input| AddressParser::default().validate(input))
+                .about("Spend from this multisig address (short, or long with since which is time locked), the unsigned transaction is saved to <tx-file>"),
+            Arg::with_name("tx-file")
+                .long("tx-file")
+                .takes_value(true)
+                .validator(|input| FilePathParser::new(true).validate(input))
+                .about("Multisig transaction file created by `tx init` and `tx add-multisig-config`, the transaction in it is replaced by the unsigned DAO transaction. Sign it by `tx sign-inputs` and send it by `tx send`"),
+            arg::tx_fee().required_unless("fee-rate"),
+            arg::fee_rate(),
+            arg::derive_receiving_address_length(),
+            arg::derive_change_address().conflicts_with_all(&[
+                arg::privkey_path().get_name(),
+                "from-multisig-address",
+            ]),
+            arg::account_index(),
         ]
     }
 
+    pub(crate) fn is_multisig(&self) -> bool {
+        self.address.payload().code_hash() == MULTISIG_TYPE_HASH.pack()
+    }
+
+    // The since value required by a time locked multisig address
+    pub(crate) fn lock_since(&self) -> Option<u64> {
+        lock_since(&self.address)
+    }
+
+    // The transaction fee calculated by fee rate
+    pub(crate) fn calculated_tx_fee(&self) -> Option<u64> {
+        self.fee_rate.map(|_| self.tx_fee)
+    }
+
     pub(crate) fn sighash_args(&self) -> H160 {
         H160::from_slice(self.address.payload().args().as_ref()).unwrap()
     }
@@ -195,3 +362,14 @@ impl TransactArgs {
         Script::from(self.address.payload()).calc_script_hash()
     }
 }
+
+fn lock_since(address: &Address) -> Option<u64> {
+    let args = address.payload().args();
+    if args.len() == 28 {
+        let mut since_bytes = [0u8; 8];
+        since_bytes.copy_from_slice(&args[20..]);
+        Some(u64::from_le_bytes(since_bytes))
+    } else {
+        None
+    }
+}
@@ -1,28 +1,32 @@
 use self::builder::DAOBuilder;
 use self::command::TransactArgs;
-use crate::plugin::{KeyStoreHandler, PluginManager, SignTarget};
+use self::util::{estimate_prepare, send_transaction};
+use super::tx::{read_tx_file, write_tx_file};
+use super::wallet::{get_keystore_signer, DERIVE_CHANGE_ADDRESS_MAX_LEN};
+use super::Output;
+use crate::plugin::PluginManager;
 use crate::utils::index::IndexController;
 use crate::utils::other::{
-    get_max_mature_number, get_network_type, get_privkey_signer, is_mature, read_password,
-    sync_to_tip,
+    get_live_cell, get_max_mature_number, get_network_type, get_privkey_signer, is_mature,
+    read_password, sync_to_tip,
 };
+use crate::utils::pending_tx::{reserved_inputs, PendingTxStore};
 use byteorder::{ByteOrder, LittleEndian};
-use ckb_hash::new_blake2b;
 use ckb_index::{with_index_db, IndexDatabase, LiveCellInfo};
-use ckb_jsonrpc_types::{self as json_types, JsonBytes};
+use ckb_jsonrpc_types::JsonBytes;
 use ckb_sdk::{
-    constants::{MIN_SECP_CELL_CAPACITY, SIGHASH_TYPE_HASH},
-    GenesisInfo, HttpRpcClient, SignerFn,
+    calculate_tx_fee, is_epoch_reached, later_epoch_since, select_coins,
+    wallet::{DerivationPath, DerivedKeySet},
+    Address, AddressPayload, GenesisInfo, HttpRpcClient, HumanCapacity, SelectionTarget, SignerFn,
+    TxHelper,
 };
 use ckb_types::{
-    bytes::Bytes,
-    core::{ScriptHashType, TransactionView},
-    packed::{self, Byte32, CellOutput, OutPoint, Script, WitnessArgs},
+    core::{HeaderView, TransactionView},
+    packed::{Byte32, CellOutput, OutPoint, Script},
     prelude::*,
-    {h256, H160, H256},
+    {H160, H256},
 };
-use itertools::Itertools;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 mod builder;
@@ -37,9 +41,26 @@ pub struct DAOSubCommand<'a> {
     index_dir: PathBuf,
     index_controller: IndexController,
     transact_args: Option<TransactArgs>,
+    account_root: Option<AccountRoot>,
+    derived_addresses: Option<DerivedAddresses>,
     wait_for_sync: bool,
 }
 
+// The BIP-44 account of <from-account> given by <account-index>
+struct AccountRoot {
+    account: H160,
+    root_key_path: DerivationPath,
+    password: Option<String>,
+}
+
+// The HD derived addresses of <from-account> searched by <derive-change-address>
+struct DerivedAddresses {
+    lock_hashes: Vec<Byte32>,
+    path_map: HashMap<H160, DerivationPath>,
+    change_address: AddressPayload,
+    change_path: DerivationPath,
+}
+
 impl<'a> DAOSubCommand<'a> {
     pub fn new(
         rpc_client: &'a mut HttpRpcClient,
@@ -56,48 +77,275 @@ impl<'a> DAOSubCommand<'a> {
             index_dir,
             index_controller,
             transact_args: None,
+            account_root: None,
+            derived_addresses: None,
             wait_for_sync,
         }
     }
 
     pub fn deposit(&mut self, capacity: u64) -> Result<TransactionView, String> {
         self.check_db_ready()?;
-        let target_capacity = capacity + self.transact_args().tx_fee;
-        let cells = self.collect_sighash_cells(target_capacity)?;
-        let raw_transaction = self.build(cells).deposit(capacity)?;
-        self.sign(raw_transaction)
+        self.build_with_fee(|cmd| {
+            let target = SelectionTarget::with_fee(capacity, cmd.transact_args().tx_fee);
+            let cells = cmd.collect_sighash_cells(target)?;
+            cmd.build(cells).deposit(capacity)
+        })
     }
 
     pub fn prepare(&mut self, out_points: Vec<OutPoint>) -> Result<TransactionView, String> {
         self.check_db_ready()?;
-        let tx_fee = self.transact_args().tx_fee;
-        let lock_hash = self.transact_args().lock_hash();
-        let cells = {
-            let mut to_pay_fee = self.collect_sighash_cells(tx_fee)?;
-            let mut to_prepare = {
-                let deposit_cells = self.query_deposit_cells(lock_hash)?;
-                take_by_out_points(deposit_cells, &out_points)?
-            };
-            to_prepare.append(&mut to_pay_fee);
-            to_prepare
+        let to_prepare = {
+            let deposit_cells = self.transact_deposit_cells()?;
+            take_by_out_points(deposit_cells, &out_points)?
         };
-        let raw_transaction = self.build(cells).prepare(self.rpc_client())?;
-        self.sign(raw_transaction)
+        self.build_with_fee(|cmd| {
+            let tx_fee = cmd.transact_args().tx_fee;
+            let cells = {
+                let mut to_pay_fee =
+                    cmd.collect_sighash_cells(SelectionTarget::with_fee(0, tx_fee))?;
+                let mut to_prepare = to_prepare.clone();
+                to_prepare.append(&mut to_pay_fee);
+                to_prepare
+            };
+            cmd.build(cells).prepare(cmd.rpc_client())
+        })
     }
 
-    pub fn withdraw(&mut self, out_points: Vec<OutPoint>) -> Result<TransactionView, String> {
+    pub fn withdraw(
+        &mut self,
+        out_points: Vec<OutPoint>,
+        only_unlocked: bool,
+    ) -> Result<TransactionView, String> {
         self.check_db_ready()?;
-        let lock_hash = self.transact_args().lock_hash();
-        let cells = {
-            let prepare_cells = self.query_prepare_cells(lock_hash)?;
+        let prepare_cells = self.transact_prepare_cells()?;
+        let cells = if only_unlocked {
+            self.unlocked_cells(prepare_cells, &out_points)?
+        } else {
             take_by_out_points(prepare_cells, &out_points)?
         };
-        let raw_transaction = self.build(cells).withdraw(self.rpc_client())?;
-        self.sign(raw_transaction)
+        self.build_with_fee(|cmd| cmd.build(cells.clone()).withdraw(cmd.rpc_client()))
+    }
+
+    pub(crate) fn set_transact_args(
+        &mut self,
+        mut transact_args: TransactArgs,
+    ) -> Result<(), String> {
+        self.account_root = None;
+        if let Some(account) = transact_args.from_account.clone() {
+            let account_index = transact_args.account_index;
+            let password = if account_index > 0
+                && !self
+                    .plugin_mgr
+                    .keystore_handler()
+                    .is_watch_only(account.clone())?
+                && self.plugin_mgr.account_require_password(&account)
+            {
+                Some(read_password(false, None)?)
+            } else {
+                None
+            };
+            let (root_key_path, root_lock_arg) = self.plugin_mgr.keystore_handler().account_root(
+                account.clone(),
+                account_index,
+                password.clone(),
+            )?;
+            // Spend from the root address of the account
+            transact_args.address = Address::new(
+                transact_args.address.network(),
+                AddressPayload::from_pubkey_hash(root_lock_arg),
+            );
+            self.account_root = Some(AccountRoot {
+                account,
+                root_key_path,
+                password,
+            });
+        }
+        self.transact_args = Some(transact_args);
+        self.derived_addresses = self.derive_addresses()?;
+        Ok(())
+    }
+
+    // Search the receiving and change addresses up to <derive-change-address> like a HD wallet,
+    // the change goes to the change address next to it.
+    fn derive_addresses(&self) -> Result<Option<DerivedAddresses>, String> {
+        let change_last = match self.transact_args().derive_change_address.as_ref() {
+            Some(address) => H160::from_slice(address.payload().args().as_ref()).unwrap(),
+            None => return Ok(None),
+        };
+        let account_root = self
+            .account_root
+            .as_ref()
+            .ok_or_else(|| "derive-change-address is only for from-account".to_string())?;
+        let account = account_root.account.clone();
+        let account_index = self.transact_args().account_index;
+        let keystore = self.plugin_mgr.keystore_handler();
+        let key_set = keystore.derived_key_set(
+            account.clone(),
+            account_index,
+            self.transact_args().derive_receiving_address_length,
+            change_last.clone(),
+            DERIVE_CHANGE_ADDRESS_MAX_LEN,
+            account_root.password.clone(),
+        )?;
+        let change_last_index = key_set
+            .change
+            .iter()
+            .position(|(_, hash160)| hash160 == &change_last)
+            .expect("change address not exists");
+        let (change_path, change_hash160) = keystore
+            .derived_key_set_by_index(
+                account,
+                account_index,
+                0,
+                0,
+                change_last_index as u32 + 1,
+                1,
+                account_root.password.clone(),
+            )?
+            .change
+            .pop()
+            .ok_or_else(|| "No change address derived".to_string())?;
+        Ok(Some(DerivedAddresses::new(
+            key_set,
+            change_path,
+            change_hash160,
+        )))
+    }
+
+    // The lock hashes of the from address and its derived addresses
+    fn transact_lock_hashes(&self) -> Vec<Byte32> {
+        let mut lock_hashes = vec![self.transact_args().lock_hash()];
+        if let Some(derived) = self.derived_addresses.as_ref() {
+            lock_hashes.extend(derived.lock_hashes.iter().cloned());
+        }
+        lock_hashes
+    }
+
+    fn transact_deposit_cells(&mut self) -> Result<Vec<LiveCellInfo>, String> {
+        let lock_hashes = self.transact_lock_hashes();
+        self.query_deposit_cells(lock_hashes)
     }
 
-    pub fn query_deposit_cells(&mut self, lock_hash: Byte32) -> Result<Vec<LiveCellInfo>, String> {
-        let dao_cells = self.collect_dao_cells(lock_hash)?;
+    fn transact_prepare_cells(&mut self) -> Result<Vec<LiveCellInfo>, String> {
+        let lock_hashes = self.transact_lock_hashes();
+        self.query_prepare_cells(lock_hashes)
+    }
+
+    // Pick the prepared cells whose since is satisfiable at the tip, empty `out_points` means
+    // all the prepared cells
+    fn unlocked_cells(
+        &mut self,
+        prepare_cells: Vec<LiveCellInfo>,
+        out_points: &[OutPoint],
+    ) -> Result<Vec<LiveCellInfo>, String> {
+        let cells = if out_points.is_empty() {
+            prepare_cells
+        } else {
+            take_by_out_points(prepare_cells, out_points)?
+        };
+        let tip_header: HeaderView = self.rpc_client().get_tip_header()?.into();
+        let mut unlocked = Vec::with_capacity(cells.len());
+        for cell in cells {
+            let unlock_point = estimate_prepare(self.rpc_client(), &cell)?.unlock_point;
+            if is_epoch_reached(tip_header.epoch(), unlock_point) {
+                unlocked.push(cell);
+            } else {
+                eprintln!(
+                    "Skip cell {:#x}-{}, it is unlocked at epoch {}",
+                    cell.tx_hash, cell.index.output_index, unlock_point
+                );
+            }
+        }
+        if unlocked.is_empty() {
+            return Err(format!(
+                "No prepared cell is unlocked at the tip epoch {}",
+                tip_header.epoch()
+            ));
+        }
+        Ok(unlocked)
+    }
+
+    // Build and sign the transaction. When fee rate is given, the transaction is rebuilt with
+    // a larger fee until the fee covers the size of the signed transaction.
+    fn build_with_fee<F>(&mut self, mut build_tx: F) -> Result<TransactionView, String>
+    where
+        F: FnMut(&mut Self) -> Result<TransactionView, String>,
+    {
+        loop {
+            let raw_transaction = build_tx(self)?;
+            if let Some(fee_rate) = self.transact_args().fee_rate {
+                let tx_size = self.estimate_tx_size(raw_transaction.clone())?;
+                let min_tx_fee = calculate_tx_fee(fee_rate, tx_size);
+                if self.transact_args().tx_fee < min_tx_fee {
+                    self.transact_args.as_mut().expect("exist").tx_fee = min_tx_fee;
+                    continue;
+                }
+            }
+            return if self.transact_args().is_multisig() {
+                // Signed later by `tx sign-inputs`
+                self.install_lock(raw_transaction)
+            } else {
+                self.sign(raw_transaction)
+            };
+        }
+    }
+
+    // Send the signed transaction, or save the unsigned multisig transaction to <tx-file>
+    pub(crate) fn send_or_save(
+        &mut self,
+        transaction: TransactionView,
+        debug: bool,
+    ) -> Result<Output, String> {
+        if let Some(tx_file) = self.transact_args().tx_file.clone() {
+            let helper = self.multisig_helper(transaction.clone())?;
+            let network_type = get_network_type(self.rpc_client)?;
+            write_tx_file(&tx_file, helper, network_type)?;
+            let tx_hash: H256 = transaction.hash().unpack();
+            let mut resp = serde_json::json!({
+                "transaction_hash": tx_hash,
+                "tx_file": tx_file.to_string_lossy(),
+            });
+            if let Some(tx_fee) = self.transact_args().calculated_tx_fee() {
+                resp["tx_fee"] = serde_json::json!(format!("{:#}", HumanCapacity::from(tx_fee)));
+            }
+            Ok(Output::new_output(resp))
+        } else {
+            let tx_fee_opt = self.transact_args().calculated_tx_fee();
+            let pending_tx_store = self.pending_tx_store();
+            send_transaction(
+                self.rpc_client(),
+                &pending_tx_store,
+                transaction,
+                tx_fee_opt,
+                debug,
+            )
+        }
+    }
+
+    // The multisig configs are taken from <tx-file>, other content of the file is dropped
+    fn multisig_helper(&self, transaction: TransactionView) -> Result<TxHelper, String> {
+        let tx_file = self.transact_args().tx_file.as_ref().expect("checked");
+        let address = &self.transact_args().address;
+        let hash160 = H160::from_slice(&address.payload().args()[0..20]).unwrap();
+        let multisig_configs = read_tx_file(tx_file)?.multisig_configs().clone();
+        if !multisig_configs.contains_key(&hash160) {
+            return Err(format!(
+                "No multisig config of {} found in {:?}, add it by `tx add-multisig-config`",
+                address, tx_file
+            ));
+        }
+        let mut helper = TxHelper::new(transaction);
+        for (_, config) in multisig_configs {
+            helper.add_multisig_config(config);
+        }
+        Ok(helper)
+    }
+
+    pub fn query_deposit_cells(
+        &mut self,
+        lock_hashes: Vec<Byte32>,
+    ) -> Result<Vec<LiveCellInfo>, String> {
+        let dao_cells = self.collect_dao_cells(lock_hashes)?;
         assert!(dao_cells.iter().all(|cell| cell.data_bytes == 8));
         let mut ret = Vec::with_capacity(dao_cells.len());
         for cell in dao_cells {
@@ -108,8 +356,11 @@ impl<'a> DAOSubCommand<'a> {
         Ok(ret)
     }
 
-    pub fn query_prepare_cells(&mut self, lock_hash: Byte32) -> Result<Vec<LiveCellInfo>, String> {
-        let dao_cells = self.collect_dao_cells(lock_hash)?;
+    pub fn query_prepare_cells(
+        &mut self,
+        lock_hashes: Vec<Byte32>,
+    ) -> Result<Vec<LiveCellInfo>, String> {
+        let dao_cells = self.collect_dao_cells(lock_hashes)?;
         assert!(dao_cells.iter().all(|cell| cell.data_bytes == 8));
         let mut ret = Vec::with_capacity(dao_cells.len());
         for cell in dao_cells {
@@ -120,53 +371,68 @@ impl<'a> DAOSubCommand<'a> {
         Ok(ret)
     }
 
-    fn collect_dao_cells(&mut self, lock_hash: Byte32) -> Result<Vec<LiveCellInfo>, String> {
+    // Scan the DAO cells once and keep the ones locked by any of `lock_hashes`
+    fn collect_dao_cells(&mut self, lock_hashes: Vec<Byte32>) -> Result<Vec<LiveCellInfo>, String> {
         let dao_type_hash = self.dao_type_hash().clone();
+        let lock_hashes = lock_hashes
+            .into_iter()
+            .map(|lock_hash| lock_hash.unpack())
+            .collect::<HashSet<H256>>();
         self.with_db(|db, _| {
-            let cells_by_lock = db
-                .get_live_cells_by_lock(lock_hash, Some(0), |_, _| (false, true))
-                .into_iter()
-                .collect::<HashSet<_>>();
-            let cells_by_code = db
-                .get_live_cells_by_code(dao_type_hash.clone(), Some(0), |_, _| (false, true))
-                .into_iter()
-                .collect::<HashSet<_>>();
-            cells_by_lock
-                .intersection(&cells_by_code)
-                .sorted_by_key(|live| (live.number, live.index.tx_index, live.index.output_index))
-                .cloned()
-                .collect::<Vec<_>>()
+            let mut cells = db.get_live_cells_by_code(dao_type_hash, Some(0), |_, info| {
+                (false, lock_hashes.contains(&info.lock_hash))
+            });
+            cells.sort_by_key(|live| (live.number, live.index.tx_index, live.index.output_index));
+            cells
         })
     }
 
-    fn collect_sighash_cells(&mut self, target_capacity: u64) -> Result<Vec<LiveCellInfo>, String> {
+    fn collect_sighash_cells(
+        &mut self,
+        target: SelectionTarget,
+    ) -> Result<Vec<LiveCellInfo>, String> {
         let from_address = self.transact_args().address.clone();
+        let coin_selection = self.transact_args().coin_selection;
         let mut enough = false;
         let mut take_capacity = 0;
         let max_mature_number = get_max_mature_number(self.rpc_client())?;
-        let terminator = |_, cell: &LiveCellInfo| {
+        let indexed_number = self.with_db(|db, _| db.last_number())?;
+        let pending_txs = self
+            .pending_tx_store()
+            .refresh(self.rpc_client, indexed_number)?;
+        let reserved = reserved_inputs(&pending_txs);
+        let lock_hashes = self.transact_lock_hashes();
+        let mut terminator = |_, cell: &LiveCellInfo| {
+            if enough && coin_selection.is_none() {
+                return (true, false);
+            }
             if !(cell.type_hashes.is_none() && cell.data_bytes == 0)
                 && is_mature(cell, max_mature_number)
             {
                 return (false, false);
             }
+            if reserved.contains(&cell.out_point()) {
+                return (false, false);
+            }
 
             take_capacity += cell.capacity;
-            if take_capacity == target_capacity
-                || take_capacity >= target_capacity + MIN_SECP_CELL_CAPACITY
-            {
-                enough = true;
+            if coin_selection.is_some() {
+                // Take all candidates, the inputs are selected by the strategy later
+                enough = take_capacity >= target.capacity;
+                return (false, true);
             }
+            enough = target.is_satisfied(take_capacity);
             (enough, true)
         };
 
         let cells: Vec<LiveCellInfo> = {
             self.with_db(|db, _| {
-                db.get_live_cells_by_lock(
-                    Script::from(from_address.payload()).calc_script_hash(),
-                    None,
-                    terminator,
-                )
+                lock_hashes
+                    .into_iter()
+                    .flat_map(|lock_hash| {
+                        db.get_live_cells_by_lock(lock_hash, None, &mut terminator)
+                    })
+                    .collect()
             })?
         };
 
@@ -176,126 +442,133 @@ impl<'a> DAOSubCommand<'a> {
                 from_address, take_capacity,
             ));
         }
-        Ok(cells)
+        if let Some(strategy) = coin_selection {
+            select_coins(strategy.selector().as_ref(), cells, &target).ok_or_else(|| {
+                format!(
+                    "No suitable live cells found by coin selection strategy: {}",
+                    strategy
+                )
+            })
+        } else {
+            Ok(cells)
+        }
     }
 
     fn build(&self, cells: Vec<LiveCellInfo>) -> DAOBuilder {
         let tx_fee = self.transact_args().tx_fee;
-        DAOBuilder::new(self.genesis_info.clone(), tx_fee, cells)
+        let change_lock = change_lock(
+            self.derived_addresses.as_ref(),
+            self.transact_args().address.payload(),
+        );
+        DAOBuilder::new(self.genesis_info.clone(), tx_fee, change_lock, cells)
     }
 
+    // The inputs may be locked by different derived addresses, each of them is signed by the
+    // key of its derivation path
     fn sign(&mut self, transaction: TransactionView) -> Result<TransactionView, String> {
-        // 1. Install sighash lock script
-        let transaction = self.install_sighash_lock(transaction);
-
-        // 2. Install signed sighash witnesses
-        let transaction = self.install_sighash_witness(transaction)?;
+        let mut helper = TxHelper::new(self.install_lock(transaction)?);
+        let signer = self.signer()?;
+        let rpc_client = self.rpc_client();
+        let mut get_live_cell_fn = |out_point, with_data| {
+            get_live_cell(rpc_client, out_point, with_data).map(|(output, _)| output)
+        };
+        for (lock_arg, signature) in helper.sign_inputs(signer, &mut get_live_cell_fn, false)? {
+            helper.add_signature(lock_arg, signature)?;
+        }
+        helper.build_tx(&mut get_live_cell_fn, false)
+    }
 
-        Ok(transaction)
+    fn signer(&self) -> Result<SignerFn, String> {
+        if let Some(ref privkey) = self.transact_args().privkey {
+            return Ok(get_privkey_signer(privkey.clone()));
+        }
+        let account_root = self
+            .account_root
+            .as_ref()
+            .ok_or_else(|| "<privkey-path> or <from-account> is required!".to_string())?;
+        let account = account_root.account.clone();
+        let password = if account_root.password.is_some() {
+            account_root.password.clone()
+        } else if self.plugin_mgr.account_require_password(&account) {
+            Some(read_password(false, None)?)
+        } else {
+            None
+        };
+        let keystore = self.plugin_mgr.keystore_handler();
+        let (change_path, path_map) = signing_paths(
+            account_root,
+            self.transact_args().sighash_args(),
+            self.derived_addresses.as_ref(),
+        );
+        let new_client = HttpRpcClient::new(self.rpc_client.url().to_owned());
+        Ok(get_keystore_signer(
+            keystore,
+            new_client,
+            change_path,
+            path_map,
+            account,
+            password,
+        ))
     }
 
-    fn install_sighash_lock(&self, transaction: TransactionView) -> TransactionView {
-        let sighash_args = self.transact_args().sighash_args();
+    // Install the sighash or multisig lock script of the from address to the outputs without
+    // lock, the change and prepared outputs already have their locks
+    fn install_lock(&self, transaction: TransactionView) -> Result<TransactionView, String> {
         let genesis_info = &self.genesis_info;
-        let sighash_dep = genesis_info.sighash_dep();
-        let sighash_type_hash = genesis_info.sighash_type_hash();
-        let lock_script = Script::new_builder()
-            .hash_type(ScriptHashType::Type.into())
-            .code_hash(sighash_type_hash.clone())
-            .args(Bytes::from(sighash_args.as_bytes().to_vec()).pack())
-            .build();
+        let lock_script = Script::from(self.transact_args().address.payload());
+        let lock_dep = if self.transact_args().is_multisig() {
+            genesis_info.multisig_dep()
+        } else {
+            genesis_info.sighash_dep()
+        };
         let outputs = transaction
             .outputs()
             .into_iter()
-            .map(|output: CellOutput| output.as_builder().lock(lock_script.clone()).build())
+            .map(|output: CellOutput| {
+                if output.lock() == Script::default() {
+                    output.as_builder().lock(lock_script.clone()).build()
+                } else {
+                    output
+                }
+            })
             .collect::<Vec<_>>();
-        transaction
-            .as_advanced_builder()
-            .set_outputs(outputs)
-            .cell_dep(sighash_dep)
-            .build()
-    }
-
-    fn install_sighash_witness(
-        &self,
-        transaction: TransactionView,
-    ) -> Result<TransactionView, String> {
-        for output in transaction.outputs() {
-            assert_eq!(output.lock().hash_type(), ScriptHashType::Type.into());
-            assert_eq!(output.lock().args().len(), 20);
-            assert_eq!(output.lock().code_hash(), SIGHASH_TYPE_HASH.pack());
-        }
-        for witness in transaction.witnesses() {
-            if let Ok(w) = WitnessArgs::from_slice(witness.as_slice()) {
-                assert!(w.lock().is_none());
-            }
-        }
-
-        let mut witnesses = transaction
-            .witnesses()
+        // All inputs are from the time locked address, the since must satisfy both the lock
+        // and the DAO withdraw
+        let inputs = transaction
+            .inputs()
             .into_iter()
-            .map(|w| w.unpack())
-            .collect::<Vec<Bytes>>();
-        let init_witness = {
-            let init_witness = if witnesses[0].is_empty() {
-                WitnessArgs::default()
-            } else {
-                WitnessArgs::from_slice(&witnesses[0]).map_err(|err| err.to_string())?
-            };
-            init_witness
-                .as_builder()
-                .lock(Some(Bytes::from(&[0u8; 65][..])).pack())
-                .build()
-        };
-        let digest = {
-            let mut blake2b = new_blake2b();
-            blake2b.update(&transaction.hash().raw_data());
-            blake2b.update(&(init_witness.as_bytes().len() as u64).to_le_bytes());
-            blake2b.update(&init_witness.as_bytes());
-            for other_witness in witnesses.iter().skip(1) {
-                blake2b.update(&(other_witness.len() as u64).to_le_bytes());
-                blake2b.update(&other_witness);
-            }
-            let mut message = [0u8; 32];
-            blake2b.finalize(&mut message);
-            H256::from(message)
-        };
-        let signature = {
-            let account = self.transact_args().sighash_args();
-            let mut signer = {
-                if let Some(ref privkey) = self.transact_args().privkey {
-                    get_privkey_signer(privkey.clone())
-                } else {
-                    let password = if self.plugin_mgr.keystore_require_password() {
-                        Some(read_password(false, None)?)
-                    } else {
-                        None
-                    };
-                    let new_client = HttpRpcClient::new(self.rpc_client.url().to_owned());
-                    get_keystore_signer(
-                        self.plugin_mgr.keystore_handler(),
-                        new_client,
-                        account.clone(),
-                        password,
-                    )
+            .map(|input| match self.transact_args().lock_since() {
+                Some(lock_since) => {
+                    let since = later_epoch_since(input.since().unpack(), lock_since)?;
+                    Ok(input.as_builder().since(since.pack()).build())
                 }
-            };
-            let accounts = vec![account].into_iter().collect::<HashSet<H160>>();
-            signer(&accounts, &digest, &transaction.data().into())?.expect("signer missed")
-        };
-
-        witnesses[0] = init_witness
-            .as_builder()
-            .lock(Some(Bytes::from(signature[..].to_vec())).pack())
-            .build()
-            .as_bytes();
-
+                None => Ok(input),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
         Ok(transaction
             .as_advanced_builder()
-            .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect::<Vec<_>>())
+            .set_inputs(inputs)
+            .set_outputs(outputs)
+            .cell_dep(lock_dep)
             .build())
     }
 
+    fn estimate_tx_size(&mut self, transaction: TransactionView) -> Result<usize, String> {
+        let transaction = self.install_lock(transaction)?;
+        let helper = if self.transact_args().is_multisig() {
+            self.multisig_helper(transaction)?
+        } else {
+            TxHelper::new(transaction)
+        };
+        let rpc_client = self.rpc_client();
+        helper.estimate_tx_size(
+            |out_point, with_data| {
+                get_live_cell(rpc_client, out_point, with_data).map(|(output, _)| output)
+            },
+            false,
+        )
+    }
+
     fn check_db_ready(&mut self) -> Result<(), String> {
         self.with_db(|_, _| ())
     }
@@ -333,69 +606,56 @@ impl<'a> DAOSubCommand<'a> {
     pub(crate) fn rpc_client(&mut self) -> &mut HttpRpcClient {
         &mut self.rpc_client
     }
+
+    pub(crate) fn pending_tx_store(&self) -> PendingTxStore {
+        PendingTxStore::new(self.plugin_mgr.ckb_cli_dir())
+    }
 }
 
-// TODO remove the duplicated function later
-fn get_keystore_signer(
-    keystore: KeyStoreHandler,
-    mut client: HttpRpcClient,
-    account: H160,
-    password: Option<String>,
-) -> SignerFn {
-    Box::new(
-        move |lock_args: &HashSet<H160>, message: &H256, tx: &json_types::Transaction| {
-            if lock_args.contains(&account) {
-                if message == &h256!("0x0") {
-                    Ok(Some([0u8; 65]))
-                } else {
-                    let path = keystore.root_key_path(account.clone())?;
-                    let sign_target = if keystore.has_account_in_default(account.clone())? {
-                        SignTarget::AnyData(Default::default())
-                    } else {
-                        let inputs = tx
-                            .inputs
-                            .iter()
-                            .map(|input| {
-                                let tx_hash = &input.previous_output.tx_hash;
-                                client
-                                    .get_transaction(tx_hash.clone())?
-                                    .map(|tx_with_status| tx_with_status.transaction.inner)
-                                    .map(packed::Transaction::from)
-                                    .map(json_types::Transaction::from)
-                                    .ok_or_else(|| format!("transaction not exists: {:x}", tx_hash))
-                            })
-                            .collect::<Result<Vec<_>, String>>()?;
-                        SignTarget::Transaction {
-                            tx: tx.clone(),
-                            inputs,
-                            change_path: path.to_string(),
-                        }
-                    };
-                    let data = keystore.sign(
-                        account.clone(),
-                        &path,
-                        message.clone(),
-                        sign_target,
-                        password.clone(),
-                        true,
-                    )?;
-                    if data.len() != 65 {
-                        Err(format!(
-                            "Invalid signature data lenght: {}, data: {:?}",
-                            data.len(),
-                            data
-                        ))
-                    } else {
-                        let mut data_bytes = [0u8; 65];
-                        data_bytes.copy_from_slice(&data[..]);
-                        Ok(Some(data_bytes))
-                    }
-                }
-            } else {
-                Ok(None)
-            }
-        },
-    )
+impl DerivedAddresses {
+    // Every derived address is signed by the key of its own path, the change address is the
+    // one next to <derive-change-address>
+    fn new(key_set: DerivedKeySet, change_path: DerivationPath, change_hash160: H160) -> Self {
+        let mut lock_hashes = Vec::new();
+        let mut path_map: HashMap<H160, DerivationPath> = Default::default();
+        for (path, hash160) in key_set.external.into_iter().chain(key_set.change) {
+            let payload = AddressPayload::from_pubkey_hash(hash160.clone());
+            lock_hashes.push(Script::from(&payload).calc_script_hash());
+            path_map.insert(hash160, path);
+        }
+        path_map.insert(change_hash160.clone(), change_path.clone());
+        DerivedAddresses {
+            lock_hashes,
+            path_map,
+            change_address: AddressPayload::from_pubkey_hash(change_hash160),
+            change_path,
+        }
+    }
+}
+
+// The root address of the account is signed by its root key path, the derived addresses by
+// their own paths
+fn signing_paths(
+    account_root: &AccountRoot,
+    root_lock_arg: H160,
+    derived: Option<&DerivedAddresses>,
+) -> (DerivationPath, HashMap<H160, DerivationPath>) {
+    let (change_path, mut path_map) = match derived {
+        Some(derived) => (derived.change_path.clone(), derived.path_map.clone()),
+        None => (account_root.root_key_path.clone(), HashMap::default()),
+    };
+    if root_lock_arg != account_root.account {
+        path_map.insert(root_lock_arg, account_root.root_key_path.clone());
+    }
+    (change_path, path_map)
+}
+
+// The change goes to the derived change address when given, otherwise back to the from address
+fn change_lock(derived: Option<&DerivedAddresses>, from_payload: &AddressPayload) -> Script {
+    match derived {
+        Some(derived) => Script::from(&derived.change_address),
+        None => Script::from(from_payload),
+    }
 }
 
 fn take_by_out_points(
@@ -439,3 +699,94 @@ fn get_cell_data(
         .ok_or_else(|| format!("cell is not found: {:?}", dao_cell.out_point()))?;
     Ok(cell_info.data.unwrap().content)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn path(s: &str) -> DerivationPath {
+        DerivationPath::from_str(s).unwrap()
+    }
+
+    fn lock_hash(hash160: &H160) -> Byte32 {
+        Script::from(&AddressPayload::from_pubkey_hash(hash160.clone())).calc_script_hash()
+    }
+
+    #[test]
+    fn test_derived_addresses() {
+        let external = (1..=2u8)
+            .map(|i| (path(&format!("m/44'/309'/1'/0/{}", i - 1)), H160([i; 20])))
+            .collect::<Vec<_>>();
+        let change = vec![(path("m/44'/309'/1'/1/0"), H160([3; 20]))];
+        let key_set = DerivedKeySet {
+            external: external.clone(),
+            change: change.clone(),
+        };
+        let change_path = path("m/44'/309'/1'/1/1");
+        let change_hash160 = H160([4; 20]);
+        let derived = DerivedAddresses::new(key_set, change_path.clone(), change_hash160.clone());
+
+        // Each input is signed by the path of its own address
+        for (path, hash160) in external.iter().chain(change.iter()) {
+            assert_eq!(derived.path_map.get(hash160), Some(path));
+        }
+        assert_eq!(derived.path_map.get(&change_hash160), Some(&change_path));
+        assert_eq!(derived.path_map.len(), 4);
+        assert_eq!(
+            derived.lock_hashes,
+            vec![
+                lock_hash(&H160([1; 20])),
+                lock_hash(&H160([2; 20])),
+                lock_hash(&H160([3; 20])),
+            ]
+        );
+
+        let from_payload = AddressPayload::from_pubkey_hash(H160([9; 20]));
+        assert_eq!(
+            change_lock(Some(&derived), &from_payload),
+            Script::from(&AddressPayload::from_pubkey_hash(change_hash160))
+        );
+        assert_eq!(
+            change_lock(None, &from_payload),
+            Script::from(&from_payload)
+        );
+    }
+
+    #[test]
+    fn test_signing_paths() {
+        let account = H160([1; 20]);
+        let account_root = AccountRoot {
+            account: account.clone(),
+            root_key_path: path("m/44'/309'/0'"),
+            password: None,
+        };
+        // Account 0 is signed by the account itself
+        let (change_path, path_map) = signing_paths(&account_root, account.clone(), None);
+        assert_eq!(change_path, path("m/44'/309'/0'"));
+        assert!(path_map.is_empty());
+
+        // Other accounts are signed by their root key path
+        let account_root = AccountRoot {
+            account,
+            root_key_path: path("m/44'/309'/1'"),
+            password: Some("123".to_string()),
+        };
+        let root_lock_arg = H160([2; 20]);
+        let (change_path, path_map) = signing_paths(&account_root, root_lock_arg.clone(), None);
+        assert_eq!(change_path, path("m/44'/309'/1'"));
+        assert_eq!(path_map.get(&root_lock_arg), Some(&path("m/44'/309'/1'")));
+        assert_eq!(path_map.len(), 1);
+
+        let key_set = DerivedKeySet {
+            external: vec![(path("m/44'/309'/1'/0/0"), H160([3; 20]))],
+            change: Vec::new(),
+        };
+        let derived = DerivedAddresses::new(key_set, path("m/44'/309'/1'/1/0"), H160([4; 20]));
+        let (change_path, path_map) =
+            signing_paths(&account_root, root_lock_arg.clone(), Some(&derived));
+        assert_eq!(change_path, path("m/44'/309'/1'/1/0"));
+        assert_eq!(path_map.get(&root_lock_arg), Some(&path("m/44'/309'/1'")));
+        assert_eq!(path_map.len(), 3);
+    }
+}
@@ -1,23 +1,37 @@
 use crate::subcommands::Output;
 use crate::utils::{
     other::check_lack_of_capacity,
+    pending_tx::PendingTxStore,
     printer::{OutputFormat, Printable},
 };
 use ckb_dao_utils::extract_dao_data;
 use ckb_index::LiveCellInfo;
-use ckb_sdk::HttpRpcClient;
+use ckb_sdk::{
+    calculate_dao_maximum_withdraw4, calculate_fee_rate, minimal_unlock_point, HttpRpcClient,
+    HumanCapacity,
+};
 use ckb_types::core::{Capacity, TransactionView};
-use ckb_types::packed::CellOutput;
 use ckb_types::{
-    core::{EpochNumber, EpochNumberWithFraction, HeaderView},
+    core::{EpochNumberWithFraction, HeaderView},
     packed,
     prelude::*,
 };
 
-pub(crate) fn calculate_dao_maximum_withdraw(
+// Nominal epoch duration of CKB, used to estimate the time of a future epoch
+const EPOCH_DURATION_MILLIS: u64 = 4 * 60 * 60 * 1000;
+
+// The estimation of a prepared cell
+pub(crate) struct PrepareEstimate {
+    // Unit: shannons
+    pub(crate) maximum_withdraw: u64,
+    // The cell can be withdrawn since this epoch
+    pub(crate) unlock_point: EpochNumberWithFraction,
+}
+
+pub(crate) fn estimate_prepare(
     rpc_client: &mut HttpRpcClient,
     prepare_cell: &LiveCellInfo,
-) -> Result<u64, String> {
+) -> Result<PrepareEstimate, String> {
     // Get the deposit_header and prepare_header corresponding to the `prepare_cell`
     let prepare_tx_status = rpc_client
         .get_transaction(prepare_cell.tx_hash.clone())?
@@ -69,32 +83,109 @@ pub(crate) fn calculate_dao_maximum_withdraw(
     let occupied_capacity = output
         .occupied_capacity(Capacity::bytes(output_data.len()).unwrap())
         .unwrap();
-    Ok(calculate_dao_maximum_withdraw4(
+    Ok(PrepareEstimate {
+        maximum_withdraw: calculate_dao_maximum_withdraw4(
+            &deposit_header,
+            &prepare_header,
+            &output,
+            occupied_capacity.as_u64(),
+        )?,
+        unlock_point: minimal_unlock_point(&deposit_header, &prepare_header),
+    })
+}
+
+// Estimate the timestamp (in milliseconds) of `target` epoch by the nominal epoch duration
+pub(crate) fn estimate_epoch_timestamp(
+    tip_header: &HeaderView,
+    target: EpochNumberWithFraction,
+) -> u64 {
+    let to_epochs = |epoch: EpochNumberWithFraction| {
+        epoch.number() as f64 + epoch.index() as f64 / epoch.length().max(1) as f64
+    };
+    let remaining = to_epochs(target) - to_epochs(tip_header.epoch());
+    if remaining <= 0.0 {
+        tip_header.timestamp()
+    } else {
+        tip_header.timestamp() + (remaining * EPOCH_DURATION_MILLIS as f64) as u64
+    }
+}
+
+// The estimation of a deposited cell if it is prepared at the tip
+pub(crate) struct DepositEstimate {
+    // Unit: shannons
+    pub(crate) compensation: u64,
+    pub(crate) annualized_rate: Option<f64>,
+    // Prepare before this point to withdraw at it, otherwise the cell is locked for another
+    // 180 epochs
+    pub(crate) cycle_end: EpochNumberWithFraction,
+}
+
+pub(crate) fn estimate_deposit(
+    rpc_client: &mut HttpRpcClient,
+    deposit_cell: &LiveCellInfo,
+    tip_header: &HeaderView,
+) -> Result<DepositEstimate, String> {
+    let deposit_tx_status = rpc_client
+        .get_transaction(deposit_cell.tx_hash.clone())?
+        .ok_or_else(|| "invalid deposit out_point, the tx is not found".to_string())?;
+    let deposit_block_hash = deposit_tx_status
+        .tx_status
+        .block_hash
+        .ok_or("invalid deposit out_point, the tx is not committed")?;
+    let deposit_tx = {
+        let tx: packed::Transaction = deposit_tx_status.transaction.inner.into();
+        tx.into_view()
+    };
+    let (output, output_data) = deposit_tx
+        .output_with_data(deposit_cell.index.output_index as usize)
+        .ok_or_else(|| "invalid deposit out_point, the cell is not found".to_string())?;
+    let deposit_header: HeaderView = rpc_client
+        .get_header(deposit_block_hash)?
+        .ok_or_else(|| "failed to get deposit_header".to_string())?
+        .into();
+
+    let occupied_capacity = output
+        .occupied_capacity(Capacity::bytes(output_data.len()).unwrap())
+        .unwrap();
+    let maximum_withdraw = calculate_dao_maximum_withdraw4(
         &deposit_header,
-        &prepare_header,
+        tip_header,
         &output,
         occupied_capacity.as_u64(),
-    ))
+    )?;
+    let output_capacity: Capacity = output.capacity().unpack();
+    Ok(DepositEstimate {
+        compensation: maximum_withdraw.saturating_sub(output_capacity.as_u64()),
+        annualized_rate: calculate_dao_annualized_rate(&deposit_header, tip_header),
+        cycle_end: minimal_unlock_point(&deposit_header, tip_header),
+    })
 }
 
-pub(crate) fn calculate_dao_maximum_withdraw4(
+// Simple (not compounded) annualized rate by the accumulate rate growth between two headers,
+// None when no time passed.
+pub(crate) fn calculate_dao_annualized_rate(
     deposit_header: &HeaderView,
     prepare_header: &HeaderView,
-    output: &CellOutput,
-    occupied_capacity: u64,
-) -> u64 {
+) -> Option<f64> {
+    const MILLISECONDS_IN_YEAR: u64 = 365 * 24 * 60 * 60 * 1000;
     let (deposit_ar, _, _, _) = extract_dao_data(deposit_header.dao()).unwrap();
     let (prepare_ar, _, _, _) = extract_dao_data(prepare_header.dao()).unwrap();
-    let output_capacity: Capacity = output.capacity().unpack();
-    let counted_capacity = output_capacity.as_u64() - occupied_capacity;
-    let withdraw_counted_capacity =
-        u128::from(counted_capacity) * u128::from(prepare_ar) / u128::from(deposit_ar);
-    occupied_capacity + withdraw_counted_capacity as u64
+    let elapsed = prepare_header
+        .timestamp()
+        .saturating_sub(deposit_header.timestamp());
+    if elapsed == 0 {
+        return None;
+    }
+    let growth = prepare_ar as f64 / deposit_ar as f64 - 1.0;
+    Some(growth * MILLISECONDS_IN_YEAR as f64 / elapsed as f64)
 }
 
+// Report the transaction fee and the effective fee rate when `tx_fee_opt` is given
 pub(crate) fn send_transaction(
     rpc_client: &mut HttpRpcClient,
+    pending_tx_store: &PendingTxStore,
     transaction: TransactionView,
+    tx_fee_opt: Option<u64>,
     debug: bool,
 ) -> Result<Output, String> {
     check_lack_of_capacity(&transaction)?;
@@ -107,117 +198,48 @@ pub(crate) fn send_transaction(
     }
 
     let resp = rpc_client.send_transaction(transaction.data())?;
-    Ok(Output::new_output(resp))
-}
-
-pub(crate) fn minimal_unlock_point(
-    deposit_header: &HeaderView,
-    prepare_header: &HeaderView,
-) -> EpochNumberWithFraction {
-    const LOCK_PERIOD_EPOCHES: EpochNumber = 180;
-
-    // https://github.com/nervosnetwork/ckb-system-scripts/blob/master/c/dao.c#L182-L223
-    let deposit_point = deposit_header.epoch();
-    let prepare_point = prepare_header.epoch();
-    let prepare_fraction = prepare_point.index() * deposit_point.length();
-    let deposit_fraction = deposit_point.index() * prepare_point.length();
-    let passed_epoch_cnt = if prepare_fraction > deposit_fraction {
-        prepare_point.number() - deposit_point.number() + 1
+    pending_tx_store.add(&transaction);
+    if let Some(tx_fee) = tx_fee_opt {
+        let tx_size = transaction.data().serialized_size_in_block();
+        Ok(Output::new_output(serde_json::json!({
+            "transaction_hash": resp,
+            "tx_fee": format!("{:#}", HumanCapacity::from(tx_fee)),
+            "tx_size": tx_size,
+            "fee_rate": calculate_fee_rate(tx_fee, tx_size),
+        })))
     } else {
-        prepare_point.number() - deposit_point.number()
-    };
-    let rest_epoch_cnt =
-        (passed_epoch_cnt + (LOCK_PERIOD_EPOCHES - 1)) / LOCK_PERIOD_EPOCHES * LOCK_PERIOD_EPOCHES;
-    EpochNumberWithFraction::new(
-        deposit_point.number() + rest_epoch_cnt,
-        deposit_point.index(),
-        deposit_point.length(),
-    )
+        Ok(Output::new_output(resp))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use ckb_dao_utils::pack_dao_data;
-    use ckb_types::{
-        bytes::Bytes,
-        core::{capacity_bytes, HeaderBuilder},
-    };
+    use ckb_types::core::HeaderBuilder;
 
     #[test]
-    fn test_minimal_unlock_point() {
-        let cases = vec![
-            ((5, 5, 1000), (184, 4, 1000), (5 + 180, 5, 1000)),
-            ((5, 5, 1000), (184, 5, 1000), (5 + 180, 5, 1000)),
-            ((5, 5, 1000), (184, 6, 1000), (5 + 180, 5, 1000)),
-            ((5, 5, 1000), (185, 4, 1000), (5 + 180, 5, 1000)),
-            ((5, 5, 1000), (185, 5, 1000), (5 + 180, 5, 1000)),
-            ((5, 5, 1000), (185, 6, 1000), (5 + 180 * 2, 5, 1000)), // 6/1000 > 5/1000
-            ((5, 5, 1000), (186, 4, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (186, 5, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (186, 6, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (364, 4, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (364, 5, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (364, 6, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (365, 4, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (365, 5, 1000), (5 + 180 * 2, 5, 1000)),
-            ((5, 5, 1000), (365, 6, 1000), (5 + 180 * 3, 5, 1000)),
-            ((5, 5, 1000), (366, 4, 1000), (5 + 180 * 3, 5, 1000)),
-            ((5, 5, 1000), (366, 5, 1000), (5 + 180 * 3, 5, 1000)),
-            ((5, 5, 1000), (366, 6, 1000), (5 + 180 * 3, 5, 1000)),
-        ];
-        for (deposit_point, prepare_point, expected) in cases {
-            let deposit_point =
-                EpochNumberWithFraction::new(deposit_point.0, deposit_point.1, deposit_point.2);
-            let prepare_point =
-                EpochNumberWithFraction::new(prepare_point.0, prepare_point.1, prepare_point.2);
-            let expected = EpochNumberWithFraction::new(expected.0, expected.1, expected.2);
-            let deposit_header = HeaderBuilder::default()
-                .epoch(deposit_point.full_value().pack())
-                .build();
-            let prepare_header = HeaderBuilder::default()
-                .epoch(prepare_point.full_value().pack())
-                .build();
-            let actual = minimal_unlock_point(&deposit_header, &prepare_header);
-            assert_eq!(
-                expected, actual,
-                "minimal_unlock_point deposit_point: {}, prepare_point: {}, expected: {}, actual: {}",
-                deposit_point, prepare_point, expected, actual,
-            );
-        }
-    }
-
-    #[test]
-    fn check_withdraw_calculation() {
-        let data = Bytes::from(vec![1; 10]);
-        let output = CellOutput::new_builder()
-            .capacity(capacity_bytes!(1000000).pack())
-            .build();
-        let deposit_header = HeaderBuilder::default()
-            .number(100.pack())
-            .dao(pack_dao_data(
-                10_000_000_000_123_456,
-                Default::default(),
-                Default::default(),
-                Default::default(),
-            ))
-            .build();
-        let prepare_header = HeaderBuilder::default()
-            .number(200.pack())
-            .dao(pack_dao_data(
-                10_000_000_001_123_456,
-                Default::default(),
-                Default::default(),
-                Default::default(),
-            ))
-            .build();
-
-        let result = calculate_dao_maximum_withdraw4(
-            &deposit_header,
-            &prepare_header,
-            &output,
-            Capacity::bytes(data.len()).unwrap().as_u64(),
+    fn test_annualized_rate() {
+        let header = |timestamp: u64, ar: u64| {
+            HeaderBuilder::default()
+                .timestamp(timestamp.pack())
+                .dao(pack_dao_data(
+                    ar,
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                ))
+                .build()
+        };
+        let day = 24 * 60 * 60 * 1000;
+        let deposit_header = header(1000, 10_000_000_000_000_000);
+        // 0.01% in a day
+        let prepare_header = header(1000 + day, 10_001_000_000_000_000);
+        let rate = calculate_dao_annualized_rate(&deposit_header, &prepare_header).unwrap();
+        assert!((rate - 0.0365).abs() < 1e-9);
+        assert_eq!(
+            calculate_dao_annualized_rate(&deposit_header, &deposit_header),
+            None
         );
-        assert_eq!(result, 100_000_000_009_999);
     }
 }
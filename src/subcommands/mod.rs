@@ -23,7 +23,7 @@ pub use plugin::PluginSubCommand;
 pub use rpc::RpcSubCommand;
 pub use tx::TxSubCommand;
 pub use util::UtilSubCommand;
-pub use wallet::{start_index_thread, LiveCells, TransferArgs, WalletSubCommand};
+pub use wallet::{start_index_thread, LiveCells, TransferArgs, TransferOutput, WalletSubCommand};
 
 use clap::ArgMatches;
 use serde::Serialize;
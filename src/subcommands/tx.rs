@@ -8,8 +8,9 @@ use std::str::FromStr;
 use ckb_jsonrpc_types as json_types;
 use ckb_jsonrpc_types::JsonBytes;
 use ckb_sdk::{
+    calculate_fee_rate,
     constants::{MULTISIG_TYPE_HASH, SECP_SIGNATURE_SIZE},
-    Address, AddressPayload, CodeHashIndex, GenesisInfo, HttpRpcClient, HumanCapacity,
+    is_dao_cell, Address, AddressPayload, CodeHashIndex, GenesisInfo, HttpRpcClient, HumanCapacity,
     MultisigConfig, NetworkType, SignerFn, TxHelper,
 };
 use ckb_types::{
@@ -33,9 +34,10 @@ use crate::utils::{
         HexParser, PrivkeyPathParser, PrivkeyWrapper,
     },
     other::{
-        check_capacity, get_genesis_info, get_live_cell, get_live_cell_with_cache,
-        get_network_type, get_privkey_signer, get_to_data, read_password,
+        check_capacity, get_account, get_genesis_info, get_live_cell, get_live_cell_with_cache,
+        get_network_type, get_privkey_signer, get_to_data, get_tx_with_header, read_password,
     },
+    pending_tx::{check_tx_status, PendingTxStore},
 };
 
 pub struct TxSubCommand<'a> {
@@ -116,7 +118,7 @@ impl<'a> TxSubCommand<'a> {
                     )
                     .arg(arg_tx_file.clone()),
                 App::new("add-input")
-                    .about("Add cell input (with secp/multisig lock)")
+                    .about("Add cell input (with secp/multisig lock, NervosDAO cells are supported)")
                     .arg(
                         Arg::with_name("tx-hash")
                             .long("tx-hash")
@@ -224,7 +226,26 @@ impl<'a> TxSubCommand<'a> {
                             .validator(|input| CapacityParser.validate(input))
                             .about("Max transaction fee (unit: CKB)"),
                     )
+                    .arg(
+                        Arg::with_name("max-fee-rate")
+                            .long("max-fee-rate")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<u64>::default().validate(input))
+                            .about("Max transaction fee rate of the signed transaction (unit: shannons/KB)"),
+                    )
                     .arg(arg_skip_check),
+                App::new("status")
+                    .about("Show the status (pending/proposed/committed) and confirmations of a transaction")
+                    .arg(
+                        Arg::with_name("tx-hash")
+                            .long("tx-hash")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .about("Transaction hash"),
+                    )
+                    .arg(arg::rebroadcast())
+                    .arg(arg::wait_confirmations()),
                 App::new("build-multisig-address")
                     .about(
                         "Build multisig address with multisig config and since(optional) argument",
@@ -246,17 +267,12 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                 let tx_file_opt: Option<PathBuf> =
                     FilePathParser::new(false).from_matches_opt(m, "tx-file", false)?;
                 let helper = TxHelper::default();
-                let repr = ReprTxHelper::new(helper, network);
 
                 if let Some(tx_file) = tx_file_opt {
-                    let mut file = fs::File::create(&tx_file).map_err(|err| err.to_string())?;
-                    let content =
-                        serde_json::to_string_pretty(&repr).map_err(|err| err.to_string())?;
-                    file.write_all(content.as_bytes())
-                        .map_err(|err| err.to_string())?;
+                    write_tx_file(&tx_file, helper, network)?;
                     Ok(Output::new_success())
                 } else {
-                    Ok(Output::new_output(repr))
+                    Ok(Output::new_output(ReprTxHelper::new(helper, network)))
                 }
             }
             ("clear-field", Some(m)) => {
@@ -287,18 +303,33 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                     .tx_hash(tx_hash.pack())
                     .index(index.pack())
                     .build();
-                let get_live_cell = |out_point, with_data| {
-                    get_live_cell(self.rpc_client, out_point, with_data).map(|(output, _)| output)
-                };
-                modify_tx_file(&tx_file, network, |helper| {
-                    helper.add_input(
-                        out_point,
-                        since_absolute_epoch_opt,
-                        get_live_cell,
-                        &genesis_info,
-                        skip_check,
-                    )
-                })?;
+                let (output, _) = get_live_cell(self.rpc_client, out_point.clone(), false)?;
+                if is_dao_cell(&output) {
+                    let get_tx_with_header = |tx_hash| get_tx_with_header(self.rpc_client, tx_hash);
+                    modify_tx_file(&tx_file, network, |helper| {
+                        helper.add_dao_input(
+                            out_point,
+                            since_absolute_epoch_opt,
+                            get_tx_with_header,
+                            &genesis_info,
+                            skip_check,
+                        )
+                    })?;
+                } else {
+                    let get_live_cell = |out_point, with_data| {
+                        get_live_cell(self.rpc_client, out_point, with_data)
+                            .map(|(output, _)| output)
+                    };
+                    modify_tx_file(&tx_file, network, |helper| {
+                        helper.add_input(
+                            out_point,
+                            since_absolute_epoch_opt,
+                            get_live_cell,
+                            &genesis_info,
+                            skip_check,
+                        )
+                    })?;
+                }
 
                 Ok(Output::new_success())
             }
@@ -432,36 +463,54 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                         type_script_empty,
                     );
                 }
+                let dao_compensation = {
+                    let mut client = HttpRpcClient::new(self.rpc_client.url().to_owned());
+                    helper.dao_compensation(|tx_hash| get_tx_with_header(&mut client, tx_hash))?
+                };
+                input_total += dao_compensation;
                 let tx_fee_string = if input_total >= output_total {
                     format!("{:#}", HumanCapacity(input_total - output_total))
                 } else {
                     format!("-{:#}", HumanCapacity(output_total - input_total))
                 };
-
-                let resp = serde_json::json!({
+                // The size after all inputs are signed
+                let tx_size = helper.estimate_tx_size(
+                    |out_point, with_data| {
+                        get_live_cell(out_point, with_data).map(|(output, _)| output)
+                    },
+                    true,
+                )?;
+
+                let mut resp = serde_json::json!({
                     "input_total": format!("{:#}", HumanCapacity(input_total)),
+                    "dao_compensation": format!("{:#}", HumanCapacity(dao_compensation)),
                     "output_total": format!("{:#}", HumanCapacity(output_total)),
                     "tx_fee": tx_fee_string,
+                    "tx_size": tx_size,
                 });
+                if input_total >= output_total {
+                    resp["fee_rate"] =
+                        serde_json::json!(calculate_fee_rate(input_total - output_total, tx_size));
+                }
                 Ok(Output::new_output(resp))
             }
             ("sign-inputs", Some(m)) => {
                 let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
                 let privkey_opt: Option<PrivkeyWrapper> =
                     PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
-                let account_opt: Option<H160> = FixedHashParser::<H160>::default()
-                    .from_matches_opt(m, "from-account", false)?;
+                let account_opt: Option<H160> =
+                    get_account(self.plugin_mgr.ckb_cli_dir(), None, m, "from-account")?;
                 let skip_check: bool = m.is_present("skip-check");
 
                 let signer = if let Some(privkey) = privkey_opt {
                     get_privkey_signer(privkey)
                 } else {
-                    let password = if self.plugin_mgr.keystore_require_password() {
+                    let account = account_opt.unwrap();
+                    let password = if self.plugin_mgr.account_require_password(&account) {
                         Some(read_password(false, None)?)
                     } else {
                         None
                     };
-                    let account = account_opt.unwrap();
                     let keystore = self.plugin_mgr.keystore_handler();
                     let new_client = HttpRpcClient::new(self.rpc_client.url().to_owned());
                     get_keystore_signer(keystore, new_client, account, password)
@@ -502,6 +551,8 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
             ("send", Some(m)) => {
                 let tx_file: PathBuf = FilePathParser::new(false).from_matches(m, "tx-file")?;
                 let max_tx_fee: u64 = CapacityParser.from_matches(m, "max-tx-fee")?;
+                let max_fee_rate_opt: Option<u64> =
+                    FromStrParser::<u64>::default().from_matches_opt(m, "max-fee-rate", false)?;
                 let skip_check: bool = m.is_present("skip-check");
 
                 let mut live_cell_cache: HashMap<(OutPoint, bool), (CellOutput, Bytes)> =
@@ -521,9 +572,20 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                     serde_json::from_reader(&file).map_err(|err| err.to_string())?;
                 let helper = TxHelper::try_from(repr)?;
 
+                let mut tx_fee_opt = None;
                 if !skip_check {
-                    let (input_total, output_total) = helper.check_tx(&mut get_live_cell)?;
-                    let tx_fee = input_total - output_total;
+                    let mut client = HttpRpcClient::new(self.rpc_client.url().to_owned());
+                    let (input_total, output_total) = helper
+                        .check_tx(&mut get_live_cell, |tx_hash| {
+                            get_tx_with_header(&mut client, tx_hash)
+                        })?;
+                    let tx_fee = input_total.checked_sub(output_total).ok_or_else(|| {
+                        format!(
+                            "Output capacity {:#} is larger than input capacity {:#}",
+                            HumanCapacity(output_total),
+                            HumanCapacity(input_total),
+                        )
+                    })?;
                     if tx_fee > max_tx_fee {
                         return Err(format!(
                             "Too much transaction fee: {:#}, max: {:#}",
@@ -531,8 +593,18 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                             HumanCapacity(max_tx_fee),
                         ));
                     }
+                    tx_fee_opt = Some(tx_fee);
                 }
                 let tx = helper.build_tx(&mut get_live_cell, skip_check)?;
+                if let (Some(tx_fee), Some(max_fee_rate)) = (tx_fee_opt, max_fee_rate_opt) {
+                    let fee_rate = calculate_fee_rate(tx_fee, tx.data().serialized_size_in_block());
+                    if fee_rate > max_fee_rate {
+                        return Err(format!(
+                            "Too much transaction fee rate: {} shannons/KB, max: {} shannons/KB",
+                            fee_rate, max_fee_rate,
+                        ));
+                    }
+                }
                 let rpc_tx = json_types::Transaction::from(tx.data());
                 if debug {
                     eprintln!(
@@ -544,6 +616,21 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                     .rpc_client
                     .send_transaction(tx.data())
                     .map_err(|err| format!("Send transaction error: {}", err))?;
+                PendingTxStore::new(self.plugin_mgr.ckb_cli_dir()).add(&tx);
+                Ok(Output::new_output(resp))
+            }
+            ("status", Some(m)) => {
+                let tx_hash: H256 =
+                    FixedHashParser::<H256>::default().from_matches(m, "tx-hash")?;
+                let wait_confirmations: Option<u64> = FromStrParser::<u64>::default()
+                    .from_matches_opt(m, "wait-confirmations", false)?;
+                let info = check_tx_status(
+                    self.rpc_client,
+                    &tx_hash,
+                    m.is_present("rebroadcast"),
+                    wait_confirmations,
+                )?;
+                let resp = serde_json::to_value(&info).map_err(|err| err.to_string())?;
                 Ok(Output::new_output(resp))
             }
             ("build-multisig-address", Some(m)) => {
@@ -675,18 +762,30 @@ fn modify_tx_file<T, F: FnOnce(&mut TxHelper) -> Result<T, String>>(
     network: NetworkType,
     func: F,
 ) -> Result<T, String> {
+    let mut helper = read_tx_file(path)?;
+    let result = func(&mut helper)?;
+    write_tx_file(path, helper, network)?;
+    Ok(result)
+}
+
+// Load the transaction in the format of `tx --tx-file`
+pub(crate) fn read_tx_file(path: &PathBuf) -> Result<TxHelper, String> {
     let file = fs::File::open(path).map_err(|err| err.to_string())?;
     let repr: ReprTxHelper = serde_json::from_reader(&file).map_err(|err| err.to_string())?;
-    let mut helper = TxHelper::try_from(repr)?;
-
-    let result = func(&mut helper)?;
+    TxHelper::try_from(repr)
+}
 
+// Save the transaction in the format of `tx --tx-file`
+pub(crate) fn write_tx_file(
+    path: &PathBuf,
+    helper: TxHelper,
+    network: NetworkType,
+) -> Result<(), String> {
     let repr = ReprTxHelper::new(helper, network);
     let mut file = fs::File::create(path).map_err(|err| err.to_string())?;
     let content = serde_json::to_string_pretty(&repr).map_err(|err| err.to_string())?;
     file.write_all(content.as_bytes())
-        .map_err(|err| err.to_string())?;
-    Ok(result)
+        .map_err(|err| err.to_string())
 }
 
 #[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -33,7 +33,7 @@ use crate::utils::{
         AddressParser, AddressPayloadOption, ArgParser, FilePathParser, FixedHashParser,
         FromStrParser, HexParser, PrivkeyPathParser, PrivkeyWrapper, PubkeyHexParser,
     },
-    other::{get_address, get_network_type, read_password, serialize_signature},
+    other::{get_account, get_address, get_network_type, read_password, serialize_signature},
 };
 use crate::{build_cli, get_version};
 
@@ -356,26 +356,16 @@ message = "0x"
                 let recoverable = m.is_present("recoverable");
                 let from_privkey_opt: Option<PrivkeyWrapper> =
                     PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
-                let from_account_opt: Option<H160> = FixedHashParser::<H160>::default()
-                    .from_matches_opt(m, "from-account", false)
-                    .or_else(|err| {
-                        let result: Result<Option<Address>, String> =
-                            AddressParser::new_sighash().from_matches_opt(m, "from-account", false);
-                        result
-                            .map(|address_opt| {
-                                address_opt.map(|address| {
-                                    H160::from_slice(&address.payload().args()).unwrap()
-                                })
-                            })
-                            .map_err(|_| err)
-                    })?;
+                let from_account_opt: Option<H160> =
+                    get_account(self.plugin_mgr.ckb_cli_dir(), None, m, "from-account")?;
                 let no_magic_bytes = m.is_present("no-magic-bytes");
-                let password =
-                    if self.plugin_mgr.keystore_require_password() && from_account_opt.is_some() {
-                        Some(read_password(false, None)?)
-                    } else {
-                        None
-                    };
+                let password = if from_account_opt.as_ref().map_or(false, |account| {
+                    self.plugin_mgr.account_require_password(account)
+                }) {
+                    Some(read_password(false, None)?)
+                } else {
+                    None
+                };
                 let extended_address_opt: Option<Address> =
                     AddressParser::new_sighash().from_matches_opt(m, "extended-address", false)?;
                 let root_path = if let Some(ref account) = from_account_opt {
@@ -431,25 +421,15 @@ message = "0x"
                 let recoverable = m.is_present("recoverable");
                 let from_privkey_opt: Option<PrivkeyWrapper> =
                     PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
-                let from_account_opt: Option<H160> = FixedHashParser::<H160>::default()
-                    .from_matches_opt(m, "from-account", false)
-                    .or_else(|err| {
-                        let result: Result<Option<Address>, String> =
-                            AddressParser::new_sighash().from_matches_opt(m, "from-account", false);
-                        result
-                            .map(|address_opt| {
-                                address_opt.map(|address| {
-                                    H160::from_slice(&address.payload().args()).unwrap()
-                                })
-                            })
-                            .map_err(|_| err)
-                    })?;
-                let password =
-                    if self.plugin_mgr.keystore_require_password() && from_account_opt.is_some() {
-                        Some(read_password(false, None)?)
-                    } else {
-                        None
-                    };
+                let from_account_opt: Option<H160> =
+                    get_account(self.plugin_mgr.ckb_cli_dir(), None, m, "from-account")?;
+                let password = if from_account_opt.as_ref().map_or(false, |account| {
+                    self.plugin_mgr.account_require_password(account)
+                }) {
+                    Some(read_password(false, None)?)
+                } else {
+                    None
+                };
                 let extended_address_opt: Option<Address> =
                     AddressParser::new_sighash().from_matches_opt(m, "extended-address", false)?;
 
@@ -492,27 +472,17 @@ message = "0x"
                     PubkeyHexParser.from_matches_opt(m, "pubkey", false)?;
                 let from_privkey_opt: Option<PrivkeyWrapper> =
                     PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
-                let from_account_opt: Option<H160> = FixedHashParser::<H160>::default()
-                    .from_matches_opt(m, "from-account", false)
-                    .or_else(|err| {
-                        let result: Result<Option<Address>, String> =
-                            AddressParser::new_sighash().from_matches_opt(m, "from-account", false);
-                        result
-                            .map(|address_opt| {
-                                address_opt.map(|address| {
-                                    H160::from_slice(&address.payload().args()).unwrap()
-                                })
-                            })
-                            .map_err(|_| err)
-                    })?;
+                let from_account_opt: Option<H160> =
+                    get_account(self.plugin_mgr.ckb_cli_dir(), None, m, "from-account")?;
                 let extended_address_opt: Option<Address> =
                     AddressParser::new_sighash().from_matches_opt(m, "extended-address", false)?;
-                let password =
-                    if self.plugin_mgr.keystore_require_password() && from_account_opt.is_some() {
-                        Some(read_password(false, None)?)
-                    } else {
-                        None
-                    };
+                let password = if from_account_opt.as_ref().map_or(false, |account| {
+                    self.plugin_mgr.account_require_password(account)
+                }) {
+                    Some(read_password(false, None)?)
+                } else {
+                    None
+                };
                 let root_path = if let Some(ref account) = from_account_opt {
                     self.plugin_mgr.root_key_path(account.clone())?
                 } else {
@@ -767,7 +737,7 @@ fn search_path(
         .map_err(|err| format!("parse extended address lock args error: {}", err))?;
     let key_set = plugin_mgr
         .keystore_handler()
-        .derived_key_set_by_index(hash160, 0, 2000, 0, 2000, password)?;
+        .derived_key_set_by_index(hash160, 0, 0, 2000, 0, 2000, password)?;
     for (path, hash) in key_set
         .external
         .into_iter()
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use ckb_types::{H160, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::pending_tx::{write_file_atomically, StoreLock};
+
+const DERIVED_INDICES_FILENAME: &str = "derived-indices.json";
+const DERIVED_INDICES_LOCK_FILENAME: &str = "derived-indices.json.lock";
+const DERIVED_INDICES_TMP_FILENAME: &str = "derived-indices.json.tmp";
+pub const DEFAULT_GAP_LIMIT: &str = "20";
+
+// The HD wallet address indices discovered by gap limit scanning
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DerivedIndices {
+    // Next unused receiving address index, all used receiving addresses are in [0, external_next)
+    pub external_next: u32,
+    // Next unused change address index, all used change addresses are in [0, change_next)
+    pub change_next: u32,
+}
+
+// Persisted as: {genesis-hash} => {account lock arg} => DerivedIndices
+type DerivedIndicesMap = BTreeMap<H256, BTreeMap<H160, DerivedIndices>>;
+
+pub struct DerivedIndicesStore {
+    path: PathBuf,
+    lock_path: PathBuf,
+    tmp_path: PathBuf,
+    genesis_hash: H256,
+}
+
+impl DerivedIndicesStore {
+    pub fn new(index_dir: &PathBuf, genesis_hash: H256) -> DerivedIndicesStore {
+        DerivedIndicesStore {
+            path: index_dir.join(DERIVED_INDICES_FILENAME),
+            lock_path: index_dir.join(DERIVED_INDICES_LOCK_FILENAME),
+            tmp_path: index_dir.join(DERIVED_INDICES_TMP_FILENAME),
+            genesis_hash,
+        }
+    }
+
+    pub fn get(&self, account: &H160) -> Result<Option<DerivedIndices>, String> {
+        Ok(self
+            .load()?
+            .get(&self.genesis_hash)
+            .and_then(|accounts| accounts.get(account))
+            .cloned())
+    }
+
+    // Reload under the lock, the indices of other accounts set by other processes are kept
+    pub fn set(&self, account: H160, indices: DerivedIndices) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let _lock = StoreLock::acquire(&self.lock_path)?;
+        let mut map = self.load()?;
+        map.entry(self.genesis_hash.clone())
+            .or_default()
+            .insert(account, indices);
+        let content = serde_json::to_string_pretty(&map).map_err(|err| err.to_string())?;
+        write_file_atomically(&self.path, &self.tmp_path, content.as_bytes())
+    }
+
+    fn load(&self) -> Result<DerivedIndicesMap, String> {
+        if !self.path.exists() {
+            return Ok(DerivedIndicesMap::default());
+        }
+        let file = fs::File::open(&self.path).map_err(|err| err.to_string())?;
+        serde_json::from_reader(&file)
+            .map_err(|err| format!("Invalid file {}: {}", self.path.to_string_lossy(), err))
+    }
+}
+
+// Scan state of one key chain (receiving or change)
+#[derive(Default)]
+pub struct ChainScanner {
+    // Next unused index
+    pub next: u32,
+    // Number of addresses already scanned
+    pub scanned: u32,
+}
+
+impl ChainScanner {
+    pub fn new(next: u32) -> ChainScanner {
+        ChainScanner {
+            next,
+            scanned: next,
+        }
+    }
+
+    pub fn is_done(&self, gap_limit: u32) -> bool {
+        self.scanned - self.next >= gap_limit
+    }
+
+    // How many addresses to derive in next round
+    pub fn next_length(&self, gap_limit: u32) -> u32 {
+        if self.is_done(gap_limit) {
+            0
+        } else {
+            gap_limit - (self.scanned - self.next)
+        }
+    }
+
+    pub fn update(&mut self, used: &[bool]) {
+        for (offset, is_used) in used.iter().enumerate() {
+            if *is_used {
+                self.next = self.scanned + offset as u32 + 1;
+            }
+        }
+        self.scanned += used.len() as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_scanner() {
+        let gap_limit = 3;
+        let mut scanner = ChainScanner::default();
+        assert_eq!(scanner.next_length(gap_limit), 3);
+        scanner.update(&[true, false, true]);
+        assert_eq!(scanner.next, 3);
+        assert!(!scanner.is_done(gap_limit));
+        assert_eq!(scanner.next_length(gap_limit), 3);
+        scanner.update(&[false, true, false]);
+        assert_eq!(scanner.next, 5);
+        assert_eq!(scanner.next_length(gap_limit), 2);
+        scanner.update(&[false, false]);
+        assert!(scanner.is_done(gap_limit));
+        assert_eq!(scanner.next_length(gap_limit), 0);
+
+        let scanner = ChainScanner::new(8);
+        assert_eq!(scanner.next_length(gap_limit), 3);
+    }
+
+    #[test]
+    fn test_derived_indices_store() {
+        let index_dir =
+            std::env::temp_dir().join(format!("ckb-cli-derived-indices-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&index_dir);
+        let store = DerivedIndicesStore::new(&index_dir, H256::default());
+        let account = H160::default();
+        assert_eq!(store.get(&account).unwrap(), None);
+
+        let indices = DerivedIndices {
+            external_next: 3,
+            change_next: 1,
+        };
+        store.set(account.clone(), indices).unwrap();
+        store.set(H160([1; 20]), DerivedIndices::default()).unwrap();
+        assert_eq!(store.get(&account).unwrap(), Some(indices));
+        assert_eq!(
+            store.get(&H160([1; 20])).unwrap(),
+            Some(DerivedIndices::default())
+        );
+        assert!(!index_dir.join(DERIVED_INDICES_TMP_FILENAME).exists());
+        assert!(!index_dir.join(DERIVED_INDICES_LOCK_FILENAME).exists());
+        let _ = fs::remove_dir_all(&index_dir);
+    }
+}
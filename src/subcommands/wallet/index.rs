@@ -22,6 +22,7 @@ pub fn start_index_thread(
     url: &str,
     index_dir: PathBuf,
     state: Arc<RwLock<IndexThreadState>>,
+    enable_explorer: bool,
 ) -> IndexController {
     let (sender, receiver) = crossbeam_channel::bounded::<Request<IndexRequest, IndexResponse>>(1);
     let shutdown = Arc::new(AtomicBool::new(false));
@@ -52,6 +53,7 @@ pub fn start_index_thread(
                     &index_dir,
                     &state,
                     &shutdown_clone,
+                    enable_explorer,
                 ) {
                     Ok(true) => {
                         state.write().stop();
@@ -81,6 +83,7 @@ fn process(
     index_dir: &PathBuf,
     state: &Arc<RwLock<IndexThreadState>>,
     shutdown: &Arc<AtomicBool>,
+    enable_explorer: bool,
 ) -> Result<bool, String> {
     if let Some(exit) = try_recv(&receiver, rpc_client) {
         return Ok(exit);
@@ -107,9 +110,14 @@ fn process(
 
         if tip_header.number() >= next_number {
             let exit_opt = with_index_db(index_dir, genesis_hash.clone(), |backend, cf| {
-                let mut db =
-                    IndexDatabase::from_db(backend, cf, network_type, genesis_info.clone(), false)
-                        .unwrap();
+                let mut db = IndexDatabase::from_db(
+                    backend,
+                    cf,
+                    network_type,
+                    genesis_info.clone(),
+                    enable_explorer,
+                )
+                .unwrap();
                 if db.last_number().is_none() {
                     db.apply_next_block(genesis_block.clone())
                         .expect("Apply genesis block failed");
@@ -1,13 +1,19 @@
+mod derived;
 mod index;
 
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
 use std::path::PathBuf;
 
 use ckb_hash::new_blake2b;
 use ckb_jsonrpc_types as json_types;
 use ckb_types::{
     bytes::Bytes,
-    core::{BlockView, Capacity, ScriptHashType, TransactionView},
+    core::{
+        BlockView, Capacity, EpochNumberWithFraction, HeaderView, ScriptHashType, TransactionView,
+    },
     h256,
     packed::{self, Byte32, CellOutput, OutPoint, Script, ScriptOpt},
     prelude::*,
@@ -16,35 +22,41 @@ use ckb_types::{
 use clap::{App, AppSettings, Arg, ArgMatches};
 use serde::{Deserialize, Serialize};
 
-use super::{CliSubCommand, Output};
+use super::{tx::write_tx_file, CliSubCommand, Output};
 use crate::plugin::{KeyStoreHandler, PluginManager, SignTarget};
 use crate::utils::{
     arg,
     arg_parser::{
-        AddressParser, ArgParser, CapacityParser, FixedHashParser, FromStrParser,
-        PrivkeyPathParser, PrivkeyWrapper,
+        AddressParser, ArgParser, CapacityParser, FilePathParser, FixedHashParser, FromStrParser,
+        HexParser, PrivkeyPathParser, PrivkeyWrapper,
     },
     index::IndexController,
     other::{
-        check_capacity, get_address, get_arg_value, get_live_cell_with_cache,
-        get_max_mature_number, get_network_type, get_privkey_signer, get_to_data, is_mature,
-        read_password, sync_to_tip,
+        check_capacity, check_output_capacity, get_account, get_address, get_arg_value,
+        get_live_cell_with_cache, get_max_mature_number, get_network_type, get_privkey_signer,
+        get_to_data, is_mature, parse_account, read_confirm, read_password, sync_to_tip,
     },
+    pending_tx::{check_tx_status, reserved_inputs, PendingTx, PendingTxStore},
+    printer::is_a_tty,
 };
 use ckb_chain_spec::consensus::TYPE_ID_CODE_HASH;
 use ckb_index::{with_index_db, IndexDatabase, LiveCellInfo};
 use ckb_sdk::{
-    constants::{
-        DAO_TYPE_HASH, MIN_SECP_CELL_CAPACITY, MULTISIG_TYPE_HASH, ONE_CKB, SIGHASH_TYPE_HASH,
-    },
+    calculate_fee_rate, calculate_tx_fee, check_lock_script,
+    constants::{DAO_TYPE_HASH, MIN_SECP_CELL_CAPACITY, MULTISIG_TYPE_HASH, ONE_CKB},
+    select_coins,
     wallet::DerivationPath,
-    Address, AddressPayload, GenesisInfo, HttpRpcClient, HumanCapacity, MultisigConfig, SignerFn,
-    Since, SinceType, TxHelper, SECP256K1,
+    Address, AddressPayload, CoinSelection, GenesisInfo, HttpRpcClient, HumanCapacity,
+    MultisigConfig, NetworkType, SelectionTarget, SignerFn, Since, SinceType, TxHelper, SECP256K1,
 };
 pub use index::start_index_thread;
 
+use derived::{ChainScanner, DerivedIndices, DerivedIndicesStore, DEFAULT_GAP_LIMIT};
+
 // Max derived change address to search
-const DERIVE_CHANGE_ADDRESS_MAX_LEN: u32 = 10000;
+pub(crate) const DERIVE_CHANGE_ADDRESS_MAX_LEN: u32 = 10000;
+// Default fee rate of consolidate transactions (unit: shannons/KB)
+const DEFAULT_FEE_RATE: u64 = 1000;
 
 pub struct WalletSubCommand<'a> {
     rpc_client: &'a mut HttpRpcClient,
@@ -86,6 +98,17 @@ impl<'a> WalletSubCommand<'a> {
         Ok(self.genesis_info.clone().unwrap())
     }
 
+    fn pending_tx_store(&self) -> PendingTxStore {
+        PendingTxStore::new(self.plugin_mgr.ckb_cli_dir())
+    }
+
+    // The transactions sent but not committed and indexed yet
+    fn pending_txs(&mut self) -> Result<Vec<PendingTx>, String> {
+        let indexed_number = self.with_db(|db| db.last_number())?;
+        self.pending_tx_store()
+            .refresh(self.rpc_client, indexed_number)
+    }
+
     fn with_db<F, T>(&mut self, func: F) -> Result<T, String>
     where
         F: FnOnce(IndexDatabase) -> T,
@@ -120,12 +143,54 @@ impl<'a> WalletSubCommand<'a> {
                             .required_unless(arg::privkey_path().get_name())
                             .conflicts_with(arg::privkey_path().get_name()),
                     )
+                    .arg(arg::account_index())
                     .arg(arg::from_locked_address())
-                    .arg(arg::to_address().required(true))
+                    .arg(arg::to_address().required_unless_one(&["outputs-file", "to-lock-script"]))
+                    .arg(
+                        Arg::with_name("to-lock-script")
+                            .long("to-lock-script")
+                            .takes_value(true)
+                            .validator(|input| {
+                                serde_json::from_str::<json_types::Script>(input)
+                                    .map(|_| ())
+                                    .map_err(|err| err.to_string())
+                            })
+                            .conflicts_with("to-address")
+                            .about("Target lock script in JSON format: {\"code_hash\": \"0x..\", \"hash_type\": \"type\", \"args\": \"0x..\"}"),
+                    )
+                    .arg(
+                        Arg::with_name("allow-unknown-lock")
+                            .long("allow-unknown-lock")
+                            .about("Allow target lock script other than sighash and multisig (the capacity may be lost if the lock script is wrong)"),
+                    )
                     .arg(arg::to_data())
                     .arg(arg::to_data_path())
-                    .arg(arg::capacity().required(true))
-                    .arg(arg::tx_fee().required(true))
+                    .arg(arg::capacity().required_unless_one(&["outputs-file", "all"]))
+                    .arg(
+                        Arg::with_name("all")
+                            .long("all")
+                            .conflicts_with_all(&["capacity", "outputs-file", "coin-selection"])
+                            .about("Transfer all mature live cells (without type script and data) to target address, the transaction fee is deducted from the output"),
+                    )
+                    .arg(arg::min_cell_capacity().requires("all"))
+                    .arg(
+                        Arg::with_name("outputs-file")
+                            .long("outputs-file")
+                            .takes_value(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .conflicts_with_all(&[
+                                "to-address",
+                                "to-lock-script",
+                                "to-data",
+                                "to-data-path",
+                                "capacity",
+                                "type-id",
+                            ])
+                            .about("Transfer to multiple recipients in one transaction, the file is a JSON array of {\"address\"(or \"lock_script\"), \"capacity\", \"data\"(optional)} or CSV lines of `address,capacity[,data]` (capacity unit: CKB)"),
+                    )
+                    .arg(arg::tx_fee().required_unless("fee-rate"))
+                    .arg(arg::fee_rate())
+                    .arg(arg::coin_selection())
                     .arg(arg::derive_receiving_address_length())
                     .arg(
                         arg::derive_change_address().conflicts_with(arg::privkey_path().get_name()),
@@ -134,16 +199,87 @@ impl<'a> WalletSubCommand<'a> {
                         Arg::with_name("type-id")
                             .long("type-id")
                             .about("Add type id type script to target output cell"),
+                    )
+                    .arg(
+                        Arg::with_name("use-pending-change")
+                            .long("use-pending-change")
+                            .about("Also spend the outputs (e.g. the change) of transactions sent by ckb-cli but not committed yet"),
+                    )
+                    .arg(
+                        Arg::with_name("dry-run")
+                            .long("dry-run")
+                            .about("Only show the inputs, outputs, change and fee of the transaction, do not sign and send it"),
+                    )
+                    .arg(
+                        Arg::with_name("tx-file")
+                            .long("tx-file")
+                            .takes_value(true)
+                            .requires("dry-run")
+                            .validator(|input| FilePathParser::new(false).validate(input))
+                            .about("Save the unsigned transaction to this file, it can be signed later by `tx sign-inputs --tx-file`"),
+                    )
+                    .arg(
+                        Arg::with_name("no-confirm")
+                            .long("no-confirm")
+                            .about("Do not ask for confirmation before signing (the confirmation is only asked when stdout is a terminal)"),
+                    ),
+                App::new("sweep")
+                    .about("Transfer all mature live cells (without type script and data) of an account and its HD derived addresses to target address")
+                    .arg(arg::from_account().required(true))
+                    .arg(arg::account_index())
+                    .arg(arg::to_address().required(true))
+                    .arg(arg::tx_fee().required_unless("fee-rate"))
+                    .arg(arg::fee_rate())
+                    .arg(arg::min_cell_capacity())
+                    .arg(arg::derive_receiving_address_length())
+                    .arg(arg::derive_change_address_length()),
+                App::new("consolidate")
+                    .about("Merge mature live cells (without type script and data) of an address into fewer cells")
+                    .arg(arg::privkey_path().required_unless(arg::from_account().get_name()))
+                    .arg(
+                        arg::from_account()
+                            .required_unless(arg::privkey_path().get_name())
+                            .conflicts_with(arg::privkey_path().get_name()),
+                    )
+                    .arg(arg::account_index())
+                    .arg(arg::to_address().about("Target address of the merged cells (default: the from address)"))
+                    .arg(
+                        Arg::with_name("cells-per-tx")
+                            .long("cells-per-tx")
+                            .takes_value(true)
+                            .default_value("500")
+                            .validator(|input| FromStrParser::<usize>::default().validate(input))
+                            .about("Max number of input cells in one transaction"),
+                    )
+                    .arg(arg::tx_fee().about("The transaction fee capacity of each transaction (unit: CKB, format: 0.0001)"))
+                    .arg(arg::fee_rate().about("Calculate the transaction fee by the serialized size of the signed transaction (unit: shannons/KB, default: 1000)"))
+                    .arg(
+                        Arg::with_name("dry-run")
+                            .long("dry-run")
+                            .about("Only print how many transactions and how much fee would be spent"),
+                    ),
+                App::new("scan-derived-addresses")
+                    .about("Discover used HD derived receiving/change addresses of an account by gap limit, later transfers from the account will include them")
+                    .arg(arg::from_account().required(true))
+                    .arg(arg::account_index())
+                    .arg(
+                        Arg::with_name("gap-limit")
+                            .long("gap-limit")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<u32>::default().validate(input))
+                            .default_value(DEFAULT_GAP_LIMIT)
+                            .about("Stop scanning a key chain after this many consecutive unused addresses"),
                     ),
                 App::new("get-capacity")
-                    .about("Get capacity by lock script hash or address or lock arg or pubkey")
+                    .about("Get capacity by lock script hash or address or lock arg or pubkey, with the breakdown of free/immature/time-locked/NervosDAO/occupied capacity (immature excludes time-locked and NervosDAO cells)")
                     .arg(arg::lock_hash())
                     .arg(arg::address())
                     .arg(arg::pubkey())
                     .arg(arg::lock_arg())
                     .arg(arg::derive_receiving_address_length())
                     .arg(arg::derive_change_address_length())
-                    .arg(arg::derived().conflicts_with(arg::lock_hash().get_name())),
+                    .arg(arg::derived().conflicts_with(arg::lock_hash().get_name()))
+                    .arg(arg::account_index()),
                 App::new("get-live-cells")
                     .about("Get live cells by lock/type/code  hash")
                     .arg(arg::lock_hash())
@@ -158,6 +294,31 @@ impl<'a> WalletSubCommand<'a> {
                             .long("fast-mode")
                             .about("Only visit current range (by --from and --to) of live cells"),
                     ),
+                App::new("get-transactions")
+                    .about("Get transaction history by lock script hash or address (index database must be built with --enable-explorer)")
+                    .arg(arg::lock_hash().required_unless(arg::address().get_name()))
+                    .arg(arg::address().required_unless(arg::lock_hash().get_name()))
+                    .arg(arg::from_block_number())
+                    .arg(arg::to_block_number())
+                    .arg(
+                        Arg::with_name("limit")
+                            .long("limit")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<usize>::default().validate(input))
+                            .default_value("15")
+                            .about("Get transactions <= limit"),
+                    )
+                    .arg(
+                        Arg::with_name("cursor")
+                            .long("cursor")
+                            .takes_value(true)
+                            .validator(|input| parse_tx_cursor(input).map(|_| ()))
+                            .about("Get transactions after this cursor (<block-number>:<tx-index>), it is the `next_cursor` of last page"),
+                    ),
+                App::new("pending")
+                    .about("Show the transactions sent by ckb-cli which are not committed (or not indexed) yet")
+                    .arg(arg::rebroadcast())
+                    .arg(arg::wait_confirmations()),
                 // Move to index subcommand
                 App::new("db-metrics")
                     .about("Show index database metrics")
@@ -172,19 +333,39 @@ impl<'a> WalletSubCommand<'a> {
         &mut self,
         args: TransferArgs,
         skip_check: bool,
-    ) -> Result<TransactionView, String> {
+    ) -> Result<(TransactionView, u64), String> {
+        self.transfer_with_confirm(args, skip_check, |_, _| Ok(true))
+            .map(|result| result.expect("transfer is always confirmed"))
+    }
+
+    // The transaction is only signed and sent when `confirm` returns true
+    pub fn transfer_with_confirm<F>(
+        &mut self,
+        args: TransferArgs,
+        skip_check: bool,
+        confirm: F,
+    ) -> Result<Option<(TransactionView, u64)>, String>
+    where
+        F: FnOnce(&TransferPreview, &TxHelper) -> Result<bool, String>,
+    {
         let TransferArgs {
             privkey_path,
             from_account,
+            account_index,
             from_locked_address,
             password,
             derive_receiving_address_length,
             derive_change_address,
-            capacity,
+            derive_change_address_length,
             tx_fee,
-            to_address,
-            to_data,
+            fee_rate,
+            coin_selection,
+            outputs,
             is_type_id,
+            allow_unknown_lock,
+            transfer_all,
+            min_cell_capacity,
+            use_pending_change,
         } = args;
 
         let network_type = get_network_type(self.rpc_client)?;
@@ -192,19 +373,15 @@ impl<'a> WalletSubCommand<'a> {
             .map(|input| PrivkeyPathParser.parse(&input))
             .transpose()?;
         let from_account: Option<H160> = from_account
-            .map(|input| {
-                FixedHashParser::<H160>::default()
-                    .parse(&input)
-                    .or_else(|err| {
-                        let result: Result<Address, String> = AddressParser::new_sighash()
-                            .set_network(network_type)
-                            .parse(&input);
-                        result
-                            .map(|address| H160::from_slice(&address.payload().args()).unwrap())
-                            .map_err(|_| err)
-                    })
-            })
+            .map(|input| parse_account(self.plugin_mgr.ckb_cli_dir(), Some(network_type), &input))
             .transpose()?;
+        let account_index: u32 = account_index
+            .map(|input| FromStrParser::<u32>::default().parse(&input))
+            .transpose()?
+            .unwrap_or(0);
+        if account_index > 0 && from_account.is_none() {
+            return Err("account-index is only for from-account".to_string());
+        }
         let from_locked_address: Option<Address> = from_locked_address
             .map(|input| {
                 AddressParser::default()
@@ -213,12 +390,28 @@ impl<'a> WalletSubCommand<'a> {
                     .parse(&input)
             })
             .transpose()?;
-        let to_capacity: u64 = CapacityParser.parse(&capacity)?.into();
-        let tx_fee: u64 = CapacityParser.parse(&tx_fee)?.into();
+        let tx_fee_opt: Option<u64> = tx_fee
+            .map(|input| CapacityParser.parse(&input).map(Into::into))
+            .transpose()?;
+        let fee_rate_opt: Option<u64> = fee_rate
+            .map(|input| FromStrParser::<u64>::default().parse(&input))
+            .transpose()?;
+        if tx_fee_opt.is_none() && fee_rate_opt.is_none() {
+            return Err("tx-fee or fee-rate is required".to_string());
+        }
+        let coin_selection: Option<CoinSelection> =
+            coin_selection.map(|input| input.parse()).transpose()?;
         let receiving_address_length: u32 = derive_receiving_address_length
             .map(|input| FromStrParser::<u32>::default().parse(&input))
             .transpose()?
             .unwrap_or(1000);
+        let change_address_length: Option<u32> = derive_change_address_length
+            .map(|input| FromStrParser::<u32>::default().parse(&input))
+            .transpose()?;
+        let min_cell_capacity: u64 = min_cell_capacity
+            .map(|input| CapacityParser.parse(&input).map(u64::from))
+            .transpose()?
+            .unwrap_or(0);
         let last_change_address_opt: Option<Address> = derive_change_address
             .map(|input| {
                 AddressParser::default()
@@ -226,24 +419,63 @@ impl<'a> WalletSubCommand<'a> {
                     .parse(&input)
             })
             .transpose()?;
-        let to_address: Address = AddressParser::default()
-            .set_network(network_type)
-            .parse(&to_address)?;
-        let to_data = to_data.unwrap_or_default();
+        if outputs.is_empty() {
+            return Err("No output to transfer".to_string());
+        }
+        if transfer_all && outputs.len() != 1 {
+            return Err("Only one output is allowed when transfer all".to_string());
+        }
+        let to_outputs = outputs
+            .into_iter()
+            .map(|output| {
+                let to_lock = match (output.to_address, output.to_lock_script) {
+                    (Some(to_address), None) => {
+                        let to_address: Address = AddressParser::default()
+                            .set_network(network_type)
+                            .parse(&to_address)?;
+                        Script::from(to_address.payload())
+                    }
+                    (None, Some(to_lock_script)) => Script::from(to_lock_script),
+                    _ => {
+                        return Err("One of to-address and to-lock-script is required".to_string());
+                    }
+                };
+                let to_capacity: u64 = CapacityParser.parse(&output.capacity)?.into();
+                let to_data = output.to_data.unwrap_or_default();
+                Ok((to_lock, to_capacity, to_data))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
 
-        let (from_address_payload, password) = if let Some(from_privkey) = from_privkey.as_ref() {
+        let mut watch_only = false;
+        let (from_address_payload, root_key_path, password) = if let Some(from_privkey) =
+            from_privkey.as_ref()
+        {
             let from_pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, from_privkey);
-            (AddressPayload::from_pubkey(&from_pubkey), None)
+            (AddressPayload::from_pubkey(&from_pubkey), None, None)
         } else {
+            watch_only = self
+                .plugin_mgr
+                .keystore_handler()
+                .is_watch_only(from_account.clone().unwrap())?;
             let password = if let Some(password) = password {
                 Some(password)
-            } else if self.plugin_mgr.keystore_require_password() {
+            } else if !watch_only
+                && self
+                    .plugin_mgr
+                    .account_require_password(from_account.as_ref().unwrap())
+            {
                 Some(read_password(false, None)?)
             } else {
                 None
             };
+            let (root_key_path, root_lock_arg) = self.plugin_mgr.keystore_handler().account_root(
+                from_account.clone().unwrap(),
+                account_index,
+                password.clone(),
+            )?;
             (
-                AddressPayload::from_pubkey_hash(from_account.unwrap()),
+                AddressPayload::from_pubkey_hash(root_lock_arg),
+                Some(root_key_path),
                 password,
             )
         };
@@ -270,45 +502,85 @@ impl<'a> WalletSubCommand<'a> {
             }
         }
 
-        let to_address_hash_type = to_address.payload().hash_type();
-        let to_address_code_hash: H256 = to_address.payload().code_hash().unpack();
-        let to_address_args_len = to_address.payload().args().len();
-        if !(to_address_hash_type == ScriptHashType::Type
-            && to_address_code_hash == SIGHASH_TYPE_HASH
-            && to_address_args_len == 20)
-            && !(to_address_hash_type == ScriptHashType::Type
-                && to_address_code_hash == MULTISIG_TYPE_HASH
-                && (to_address_args_len == 20 || to_address_args_len == 28))
-        {
-            return Err(format!("Invalid to-address: {}", to_address));
+        let mut to_capacity: u64 = 0;
+        for (idx, (to_lock, capacity, to_data)) in to_outputs.iter().enumerate() {
+            check_lock_script(to_lock, allow_unknown_lock).map_err(|err| {
+                if allow_unknown_lock {
+                    err
+                } else {
+                    format!("{} (use --allow-unknown-lock to skip this check)", err)
+                }
+            })?;
+            // The type id args is not known yet, only its size matters
+            let type_script = if is_type_id && idx == 0 {
+                Some(build_type_id_script(&[0u8; 32]))
+            } else {
+                None
+            };
+            let to_output = CellOutput::new_builder()
+                .capacity(Capacity::shannons(*capacity).pack())
+                .lock(to_lock.clone())
+                .type_(ScriptOpt::new_builder().set(type_script).build())
+                .build();
+            // The output capacity is decided by live cells when transfer all
+            if !transfer_all {
+                check_output_capacity(&to_output, to_data.len())?;
+            }
+            to_capacity = to_capacity
+                .checked_add(*capacity)
+                .ok_or_else(|| "Total output capacity overflow".to_string())?;
         }
-        check_capacity(to_capacity, to_data.len())?;
 
         let genesis_info = self.genesis_info()?;
 
-        // For check index database is ready
-        self.with_db(|_| ())?;
+        // Also check index database is ready
+        let pending_txs = self.pending_txs()?;
+        let reserved = reserved_inputs(&pending_txs);
+        let pending_cells = if use_pending_change {
+            pending_txs
+                .iter()
+                .flat_map(PendingTx::output_live_cell_infos)
+                .filter(|info| !reserved.contains(&info.out_point()))
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
         let index_dir = self.index_dir.clone();
         let genesis_hash = genesis_info.header().hash();
         let genesis_info_clone = genesis_info.clone();
 
         // The lock hashes for search live cells
         let mut lock_hashes = vec![Script::from(&from_address_payload).calc_script_hash()];
-        let mut helper = TxHelper::default();
+        let mut base_helper = TxHelper::default();
 
         let from_lock_arg = H160::from_slice(from_address.payload().args().as_ref()).unwrap();
+        // The account in keystore, differs from from_lock_arg when account index is not 0
+        let account = from_account.unwrap_or_else(|| from_lock_arg.clone());
+        let root_key_path = match root_key_path {
+            Some(path) => path,
+            None => self.plugin_mgr.root_key_path(from_lock_arg.clone())?,
+        };
+        let stored_indices_opt = if from_privkey.is_none() {
+            DerivedIndicesStore::new(&self.index_dir, genesis_hash.unpack()).get(&from_lock_arg)?
+        } else {
+            None
+        };
         let mut path_map: HashMap<H160, DerivationPath> = Default::default();
+        if account != from_lock_arg {
+            path_map.insert(from_lock_arg.clone(), root_key_path.clone());
+        }
         let (change_address_payload, change_path) =
             if let Some(last_change_address) = last_change_address_opt {
                 // Behave like HD wallet
                 let change_last =
                     H160::from_slice(last_change_address.payload().args().as_ref()).unwrap();
                 let key_set = self.plugin_mgr.keystore_handler().derived_key_set(
-                    from_lock_arg.clone(),
+                    account.clone(),
+                    account_index,
                     receiving_address_length,
                     change_last.clone(),
                     DERIVE_CHANGE_ADDRESS_MAX_LEN,
-                    None,
+                    password.clone(),
                 )?;
                 let mut change_path_opt = None;
                 for (path, hash160) in key_set.external.iter().chain(key_set.change.iter()) {
@@ -323,11 +595,68 @@ impl<'a> WalletSubCommand<'a> {
                     last_change_address.payload().clone(),
                     change_path_opt.expect("change path not exists"),
                 )
-            } else {
+            } else if let Some(change_address_length) = change_address_length {
+                // Search all derived addresses, no change output is needed
+                let key_set = self
+                    .plugin_mgr
+                    .keystore_handler()
+                    .derived_key_set_by_index(
+                        account.clone(),
+                        account_index,
+                        0,
+                        receiving_address_length,
+                        0,
+                        change_address_length,
+                        password.clone(),
+                    )?;
+                for (path, hash160) in key_set.external.iter().chain(key_set.change.iter()) {
+                    path_map.insert(hash160.clone(), path.clone());
+                    let payload = AddressPayload::from_pubkey_hash(hash160.clone());
+                    lock_hashes.push(Script::from(&payload).calc_script_hash());
+                }
+                (from_address.payload().clone(), root_key_path.clone())
+            } else if let Some(indices) = stored_indices_opt {
+                // Use the addresses discovered by `wallet scan-derived-addresses`, skip the change
+                // addresses used since last scan.
+                let indices = self.scan_derived_indices(
+                    account.clone(),
+                    account_index,
+                    indices,
+                    1,
+                    password.clone(),
+                )?;
+                if indices != stored_indices_opt.unwrap() {
+                    DerivedIndicesStore::new(&self.index_dir, genesis_hash.unpack())
+                        .set(from_lock_arg.clone(), indices)?;
+                }
+                let key_set = self
+                    .plugin_mgr
+                    .keystore_handler()
+                    .derived_key_set_by_index(
+                        account.clone(),
+                        account_index,
+                        0,
+                        indices.external_next,
+                        0,
+                        indices.change_next + 1,
+                        password.clone(),
+                    )?;
+                for (path, hash160) in key_set.external.iter().chain(key_set.change.iter()) {
+                    path_map.insert(hash160.clone(), path.clone());
+                    let payload = AddressPayload::from_pubkey_hash(hash160.clone());
+                    lock_hashes.push(Script::from(&payload).calc_script_hash());
+                }
+                let (change_path, change_hash160) = key_set
+                    .change
+                    .last()
+                    .cloned()
+                    .ok_or_else(|| "No change address derived".to_string())?;
                 (
-                    from_address.payload().clone(),
-                    self.plugin_mgr.root_key_path(from_lock_arg.clone())?,
+                    AddressPayload::from_pubkey_hash(change_hash160),
+                    change_path,
                 )
+            } else {
+                (from_address.payload().clone(), root_key_path.clone())
             };
 
         if let Some(from_locked_address) = from_locked_address.as_ref() {
@@ -342,11 +671,11 @@ impl<'a> WalletSubCommand<'a> {
                 let threshold = 1;
                 let cfg = MultisigConfig::new_with(sighash_addresses, require_first_n, threshold)?;
                 if cfg.hash160().as_bytes() == &from_locked_address.payload().args()[0..20] {
-                    helper.add_multisig_config(cfg);
+                    base_helper.add_multisig_config(cfg);
                     break;
                 }
             }
-            if helper.multisig_configs().is_empty() {
+            if base_helper.multisig_configs().is_empty() {
                 return Err(String::from(
                     "from-locked-address is not created from the key or derived keys",
                 ));
@@ -354,115 +683,203 @@ impl<'a> WalletSubCommand<'a> {
         }
 
         let max_mature_number = get_max_mature_number(self.rpc_client)?;
-        let mut from_capacity = 0;
-        let mut infos: Vec<LiveCellInfo> = Default::default();
-
-        fn enough_capacity(from_capacity: u64, to_capacity: u64, tx_fee: u64) -> bool {
-            let rest_capacity = from_capacity - to_capacity - tx_fee;
-            from_capacity >= to_capacity + tx_fee
-                && (rest_capacity >= MIN_SECP_CELL_CAPACITY || tx_fee + rest_capacity < ONE_CKB)
-        }
-        let mut terminator = |_, info: &LiveCellInfo| {
-            if enough_capacity(from_capacity, to_capacity, tx_fee) {
-                (true, false)
-            } else if info.type_hashes.is_none()
-                && info.data_bytes == 0
-                && is_mature(info, max_mature_number)
-            {
-                from_capacity += info.capacity;
-                infos.push(info.clone());
-                (enough_capacity(from_capacity, to_capacity, tx_fee), false)
-            } else {
-                (false, false)
-            }
-        };
         if self.wait_for_sync {
             sync_to_tip(&self.index_controller)?;
         }
-        if let Err(err) = with_index_db(&index_dir, genesis_hash.unpack(), |backend, cf| {
-            IndexDatabase::from_db(backend, cf, network_type, genesis_info_clone, false)
-                .map(|db| {
-                    for lock_hash in lock_hashes {
-                        db.get_live_cells_by_lock(lock_hash, None, &mut terminator);
-                    }
-                })
-                .map_err(Into::into)
-        }) {
-            return Err(format!(
-                "Index database may not ready, sync process: {}, error: {}",
-                self.index_controller.state().read().to_string(),
-                err.to_string(),
-            ));
-        }
+        let index_controller = self.index_controller.clone();
 
-        if tx_fee > ONE_CKB {
-            return Err("Transaction fee can not be more than 1.0 CKB".to_string());
-        }
-        if to_capacity + tx_fee > from_capacity {
-            return Err(format!(
-                "Capacity(mature) not enough: {} => {}",
-                from_address, from_capacity,
-            ));
-        }
-        let rest_capacity = from_capacity - to_capacity - tx_fee;
-        if rest_capacity < MIN_SECP_CELL_CAPACITY && rest_capacity + tx_fee > ONE_CKB {
-            let final_fee = HumanCapacity(tx_fee + rest_capacity);
-            return Err(format!("Transaction fee ({}) can not be more than 1.0 CKB, please change to-capacity value to adjust (not enough live cells to adjust)", final_fee));
+        fn enough_capacity(from_capacity: u64, to_capacity: u64, tx_fee: u64) -> bool {
+            from_capacity >= to_capacity + tx_fee && {
+                let rest_capacity = from_capacity - to_capacity - tx_fee;
+                rest_capacity >= MIN_SECP_CELL_CAPACITY || tx_fee + rest_capacity < ONE_CKB
+            }
         }
+        let collect_cells = |tx_fee: u64| -> Result<(Vec<LiveCellInfo>, u64), String> {
+            let mut from_capacity = 0;
+            let mut infos: Vec<LiveCellInfo> = Default::default();
+            // Collect all candidates when transfer all or select by strategy
+            let collect_all = transfer_all || coin_selection.is_some();
+            let mut terminator = |_, info: &LiveCellInfo| {
+                if !collect_all && enough_capacity(from_capacity, to_capacity, tx_fee) {
+                    (true, false)
+                } else if reserved.contains(&info.out_point()) {
+                    (false, false)
+                } else if info.type_hashes.is_none()
+                    && info.data_bytes == 0
+                    && info.capacity >= min_cell_capacity
+                    && is_mature(info, max_mature_number)
+                {
+                    from_capacity += info.capacity;
+                    infos.push(info.clone());
+                    let stop = !collect_all && enough_capacity(from_capacity, to_capacity, tx_fee);
+                    (stop, false)
+                } else {
+                    (false, false)
+                }
+            };
+            with_index_db(&index_dir, genesis_hash.unpack(), |backend, cf| {
+                IndexDatabase::from_db(backend, cf, network_type, genesis_info_clone.clone(), false)
+                    .map(|db| {
+                        for lock_hash in &lock_hashes {
+                            db.get_live_cells_by_lock(lock_hash.clone(), None, &mut terminator);
+                        }
+                    })
+                    .map_err(Into::into)
+            })
+            .map_err(|err| {
+                format!(
+                    "Index database may not ready, sync process: {}, error: {}",
+                    index_controller.state().read().to_string(),
+                    err.to_string(),
+                )
+            })?;
+            // The pending outputs are used after all committed live cells
+            for info in &pending_cells {
+                if lock_hashes.contains(&info.lock_hash.pack()) && terminator(0, info).0 {
+                    break;
+                }
+            }
+            match coin_selection {
+                // Not enough capacity, return all candidates and report the error later
+                Some(strategy) if enough_capacity(from_capacity, to_capacity, tx_fee) => {
+                    let target = SelectionTarget::with_fee(to_capacity, tx_fee);
+                    let infos = select_coins(strategy.selector().as_ref(), infos, &target)
+                        .ok_or_else(|| {
+                            format!(
+                                "No suitable live cells found by coin selection strategy: {}",
+                                strategy
+                            )
+                        })?;
+                    let from_capacity = infos.iter().map(|info| info.capacity).sum();
+                    Ok((infos, from_capacity))
+                }
+                _ => Ok((infos, from_capacity)),
+            }
+        };
 
         let rpc_url = self.rpc_client.url().to_string();
         let keystore = self.plugin_mgr.keystore_handler();
         let mut live_cell_cache: HashMap<(OutPoint, bool), (CellOutput, Bytes)> =
             Default::default();
+        // The pending outputs are not live cells of the chain yet
+        if use_pending_change {
+            for (out_point, output, data) in pending_txs.iter().flat_map(PendingTx::output_cells) {
+                live_cell_cache.insert((out_point.clone(), true), (output.clone(), data));
+                live_cell_cache.insert((out_point, false), (output, Bytes::default()));
+            }
+        }
         let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
             get_live_cell_with_cache(&mut live_cell_cache, self.rpc_client, out_point, with_data)
                 .map(|(output, _)| output)
         };
-        for info in &infos {
-            helper.add_input(
-                info.out_point(),
-                None,
-                &mut get_live_cell_fn,
-                &genesis_info,
-                skip_check,
-            )?;
-        }
 
-        // Add outputs
-        let type_script = if is_type_id {
-            let mut blake2b = new_blake2b();
-            let first_cell_input = helper
-                .transaction()
-                .inputs()
-                .into_iter()
-                .next()
-                .expect("inputs empty");
-            blake2b.update(first_cell_input.as_slice());
-            blake2b.update(&0u64.to_le_bytes());
-            let mut ret = [0; 32];
-            blake2b.finalize(&mut ret);
-            Some(
-                Script::new_builder()
-                    .code_hash(TYPE_ID_CODE_HASH.pack())
-                    .hash_type(ScriptHashType::Type.into())
-                    .args(Bytes::from(ret[..].to_vec()).pack())
-                    .build(),
-            )
-        } else {
-            None
+        // When fee rate is given the fee depends on the transaction size, start from zero
+        // and collect more live cells until the inputs can pay the size based fee.
+        let mut tx_fee = tx_fee_opt.unwrap_or(0);
+        let (mut helper, tx_fee) = loop {
+            let (infos, from_capacity) = collect_cells(tx_fee)?;
+            if tx_fee > ONE_CKB {
+                return Err("Transaction fee can not be more than 1.0 CKB".to_string());
+            }
+            if to_capacity + tx_fee > from_capacity {
+                return Err(format!(
+                    "Capacity(mature) not enough: {} => {}",
+                    from_address, from_capacity,
+                ));
+            }
+            let rest_capacity = from_capacity - to_capacity - tx_fee;
+            if !transfer_all
+                && rest_capacity < MIN_SECP_CELL_CAPACITY
+                && rest_capacity + tx_fee > ONE_CKB
+            {
+                let final_fee = HumanCapacity(tx_fee + rest_capacity);
+                return Err(format!("Transaction fee ({}) can not be more than 1.0 CKB, please change to-capacity value to adjust (not enough live cells to adjust)", final_fee));
+            }
+
+            let mut helper = base_helper.clone();
+            for info in &infos {
+                helper.add_input(
+                    info.out_point(),
+                    None,
+                    &mut get_live_cell_fn,
+                    &genesis_info,
+                    skip_check,
+                )?;
+            }
+
+            // Add outputs
+            let mut type_script = if is_type_id {
+                let mut blake2b = new_blake2b();
+                let first_cell_input = helper
+                    .transaction()
+                    .inputs()
+                    .into_iter()
+                    .next()
+                    .expect("inputs empty");
+                blake2b.update(first_cell_input.as_slice());
+                blake2b.update(&0u64.to_le_bytes());
+                let mut ret = [0; 32];
+                blake2b.finalize(&mut ret);
+                Some(build_type_id_script(&ret))
+            } else {
+                None
+            };
+            for (to_lock, capacity, to_data) in &to_outputs {
+                // Transfer all the rest capacity to the only output
+                let capacity = if transfer_all {
+                    rest_capacity
+                } else {
+                    *capacity
+                };
+                // The type id script only goes to the first output
+                let to_output = CellOutput::new_builder()
+                    .capacity(Capacity::shannons(capacity).pack())
+                    .lock(to_lock.clone())
+                    .type_(ScriptOpt::new_builder().set(type_script.take()).build())
+                    .build();
+                if transfer_all {
+                    check_output_capacity(&to_output, to_data.len())?;
+                }
+                helper.add_output(to_output, to_data.clone());
+            }
+            let actual_tx_fee = if transfer_all {
+                tx_fee
+            } else if rest_capacity >= MIN_SECP_CELL_CAPACITY {
+                let change_output = CellOutput::new_builder()
+                    .capacity(Capacity::shannons(rest_capacity).pack())
+                    .lock((&change_address_payload).into())
+                    .build();
+                helper.add_output(change_output, Bytes::default());
+                tx_fee
+            } else {
+                tx_fee + rest_capacity
+            };
+
+            if let Some(fee_rate) = fee_rate_opt {
+                let tx_size = helper.estimate_tx_size(&mut get_live_cell_fn, skip_check)?;
+                let min_tx_fee = calculate_tx_fee(fee_rate, tx_size);
+                if actual_tx_fee < min_tx_fee {
+                    tx_fee = min_tx_fee;
+                    continue;
+                }
+            }
+            break (helper, actual_tx_fee);
         };
-        let to_output = CellOutput::new_builder()
-            .capacity(Capacity::shannons(to_capacity).pack())
-            .lock(to_address.payload().into())
-            .type_(ScriptOpt::new_builder().set(type_script).build())
-            .build();
-        helper.add_output(to_output, to_data);
-        if rest_capacity >= MIN_SECP_CELL_CAPACITY {
-            let change_output = CellOutput::new_builder()
-                .capacity(Capacity::shannons(rest_capacity).pack())
-                .lock((&change_address_payload).into())
-                .build();
-            helper.add_output(change_output, Bytes::default());
+
+        let tx_size = helper.estimate_tx_size(&mut get_live_cell_fn, skip_check)?;
+        let preview = TransferPreview::new(
+            &helper,
+            network_type,
+            to_outputs.len(),
+            tx_fee,
+            tx_size,
+            &mut get_live_cell_fn,
+        )?;
+        if !confirm(&preview, &helper)? {
+            return Ok(None);
+        }
+        if watch_only {
+            return Err(watch_only_error(&from_lock_arg));
         }
 
         let signer = if let Some(from_privkey) = from_privkey {
@@ -474,7 +891,7 @@ impl<'a> WalletSubCommand<'a> {
                 new_client,
                 change_path,
                 path_map,
-                from_lock_arg,
+                account,
                 password,
             )
         };
@@ -489,35 +906,297 @@ impl<'a> WalletSubCommand<'a> {
             .send_transaction(tx.data())
             .map_err(|err| format!("Send transaction error: {}", err))?;
         assert_eq!(tx.hash(), tx_hash.pack());
-        Ok(tx)
+        self.pending_tx_store().add(&tx);
+        Ok(Some((tx, tx_fee)))
     }
 
-    pub fn get_capacity(&mut self, lock_hashes: Vec<Byte32>) -> Result<(u64, u64, u64), String> {
+    pub fn consolidate(&mut self, args: ConsolidateArgs) -> Result<Vec<ConsolidateTx>, String> {
+        let ConsolidateArgs {
+            from_privkey,
+            from_account,
+            account_index,
+            to_address,
+            cells_per_tx,
+            tx_fee,
+            fee_rate,
+            dry_run,
+        } = args;
+        if cells_per_tx == 0 {
+            return Err("cells-per-tx can not be zero".to_string());
+        }
+
+        let network_type = get_network_type(self.rpc_client)?;
+        // The password is read before deriving the account level key, which requires the
+        // password when the account index is not added by `account add-account-index`
+        let (from_address_payload, keystore_info) =
+            if let Some(from_privkey) = from_privkey.as_ref() {
+                let from_pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, from_privkey);
+                (AddressPayload::from_pubkey(&from_pubkey), None)
+            } else {
+                let account = from_account.clone().expect("from-account is required");
+                let password = if dry_run {
+                    None
+                } else if self
+                    .plugin_mgr
+                    .keystore_handler()
+                    .is_watch_only(account.clone())?
+                {
+                    return Err(watch_only_error(&account));
+                } else if self.plugin_mgr.account_require_password(&account) {
+                    Some(read_password(false, None)?)
+                } else {
+                    None
+                };
+                let (root_key_path, root_lock_arg) = self
+                    .plugin_mgr
+                    .keystore_handler()
+                    .account_root(account, account_index, password.clone())?;
+                let keystore_info = if dry_run {
+                    None
+                } else {
+                    Some((root_key_path, password))
+                };
+                (
+                    AddressPayload::from_pubkey_hash(root_lock_arg),
+                    keystore_info,
+                )
+            };
+        let from_address = Address::new(network_type, from_address_payload.clone());
+        let to_address = to_address.unwrap_or_else(|| from_address.clone());
+        let from_lock = Script::from(&from_address_payload);
+        let to_lock = Script::from(to_address.payload());
+        check_lock_script(&to_lock, false)?;
+        let same_lock = from_lock.calc_script_hash() == to_lock.calc_script_hash();
+
+        let genesis_info = self.genesis_info()?;
         let max_mature_number = get_max_mature_number(self.rpc_client)?;
-        self.with_db(|db| {
-            let mut total_capacity = 0;
-            let mut dao_capacity = 0;
-            let mut immature_capacity = 0;
-            let mut terminator = |_idx: usize, info: &LiveCellInfo| {
-                if !is_mature(info, max_mature_number) {
-                    immature_capacity += info.capacity;
-                }
-                if info
-                    .type_hashes
-                    .as_ref()
-                    .filter(|(code_hash, _)| code_hash == &DAO_TYPE_HASH)
-                    .is_some()
+        let reserved = reserved_inputs(&self.pending_txs()?);
+        let infos = self.with_db(|db| {
+            let terminator = |_, info: &LiveCellInfo| {
+                let push_info = info.type_hashes.is_none()
+                    && info.data_bytes == 0
+                    && is_mature(info, max_mature_number)
+                    && !reserved.contains(&info.out_point());
+                (false, push_info)
+            };
+            db.get_live_cells_by_lock(from_lock.calc_script_hash(), None, terminator)
+        })?;
+
+        let from_lock_arg = H160::from_slice(from_address.payload().args().as_ref()).unwrap();
+        let account = from_account.unwrap_or_else(|| from_lock_arg.clone());
+        let rpc_url = self.rpc_client.url().to_string();
+        let keystore = self.plugin_mgr.keystore_handler();
+
+        // All inputs are plain cells locked by the from address, no need to query them by RPC
+        let live_cells: HashMap<OutPoint, CellOutput> = infos
+            .iter()
+            .map(|info| {
+                let output = CellOutput::new_builder()
+                    .capacity(Capacity::shannons(info.capacity).pack())
+                    .lock(from_lock.clone())
+                    .build();
+                (info.out_point(), output)
+            })
+            .collect();
+        let mut get_live_cell_fn = |out_point: OutPoint, _with_data: bool| {
+            live_cells
+                .get(&out_point)
+                .cloned()
+                .ok_or_else(|| format!("Live cell not found: {}", out_point))
+        };
+        let build_output = |capacity: u64| {
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(capacity).pack())
+                .lock(to_lock.clone())
+                .build()
+        };
+
+        let mut consolidate_txs = Vec::new();
+        for cells in infos.chunks(cells_per_tx) {
+            // Merge one cell into itself only costs fee
+            if same_lock && cells.len() < 2 {
+                continue;
+            }
+            let mut helper = TxHelper::default();
+            for info in cells {
+                helper.add_input(
+                    info.out_point(),
+                    None,
+                    &mut get_live_cell_fn,
+                    &genesis_info,
+                    false,
+                )?;
+            }
+            let input_capacity: u64 = cells.iter().map(|info| info.capacity).sum();
+            // The output capacity not affect the transaction size
+            helper.add_output(build_output(input_capacity), Bytes::default());
+            let tx_size = helper.estimate_tx_size(&mut get_live_cell_fn, false)?;
+            let tx_fee = tx_fee.unwrap_or_else(|| calculate_tx_fee(fee_rate, tx_size));
+            if tx_fee > ONE_CKB {
+                return Err("Transaction fee can not be more than 1.0 CKB".to_string());
+            }
+            let capacity = input_capacity
+                .checked_sub(tx_fee)
+                .filter(|capacity| check_capacity(*capacity, 0).is_ok())
+                .ok_or_else(|| {
+                    format!(
+                        "Capacity of {} cells ({}) is not enough to pay the transaction fee ({})",
+                        cells.len(),
+                        HumanCapacity::from(input_capacity),
+                        HumanCapacity::from(tx_fee),
+                    )
+                })?;
+            helper.clear_outputs();
+            helper.add_output(build_output(capacity), Bytes::default());
+
+            let tx_hash = if dry_run {
+                None
+            } else {
+                let signer = if let Some((root_key_path, password)) = keystore_info.as_ref() {
+                    // The account level key of a BIP-44 account is not the root key
+                    let mut path_map = HashMap::default();
+                    if account != from_lock_arg {
+                        path_map.insert(from_lock_arg.clone(), root_key_path.clone());
+                    }
+                    get_keystore_signer(
+                        keystore.clone(),
+                        HttpRpcClient::new(rpc_url.clone()),
+                        root_key_path.clone(),
+                        path_map,
+                        account.clone(),
+                        password.clone(),
+                    )
+                } else {
+                    get_privkey_signer(from_privkey.clone().expect("from-privkey is required"))
+                };
+                for (lock_arg, signature) in
+                    helper.sign_inputs(signer, &mut get_live_cell_fn, false)?
                 {
-                    dao_capacity += info.capacity;
+                    helper.add_signature(lock_arg, signature)?;
                 }
-                total_capacity += info.capacity;
-                (false, false)
+                let tx = helper.build_tx(&mut get_live_cell_fn, false)?;
+                let tx_hash = self
+                    .rpc_client
+                    .send_transaction(tx.data())
+                    .map_err(|err| format!("Send transaction error: {}", err))?;
+                assert_eq!(tx.hash(), tx_hash.pack());
+                self.pending_tx_store().add(&tx);
+                Some(tx_hash)
             };
+            consolidate_txs.push(ConsolidateTx {
+                tx_hash,
+                inputs: cells.len(),
+                capacity,
+                tx_fee,
+                tx_size,
+            });
+        }
+        Ok(consolidate_txs)
+    }
+
+    // Scan receiving and change addresses from `start` until `gap_limit` consecutive addresses
+    // are not used (an address is used when its lock script appears in index database).
+    pub fn scan_derived_indices(
+        &mut self,
+        account: H160,
+        account_index: u32,
+        start: DerivedIndices,
+        gap_limit: u32,
+        password: Option<String>,
+    ) -> Result<DerivedIndices, String> {
+        if gap_limit == 0 {
+            return Err("gap limit must be greater than 0".to_string());
+        }
+        let mut external = ChainScanner::new(start.external_next);
+        let mut change = ChainScanner::new(start.change_next);
+        while !(external.is_done(gap_limit) && change.is_done(gap_limit)) {
+            let key_set = self
+                .plugin_mgr
+                .keystore_handler()
+                .derived_key_set_by_index(
+                    account.clone(),
+                    account_index,
+                    external.scanned,
+                    external.next_length(gap_limit),
+                    change.scanned,
+                    change.next_length(gap_limit),
+                    password.clone(),
+                )?;
+            let (external_used, change_used) = self.with_db(|db| {
+                let is_used = |(_, hash160): &(DerivationPath, H160)| {
+                    let payload = AddressPayload::from_pubkey_hash(hash160.clone());
+                    db.get_lock_script_by_hash(Script::from(&payload).calc_script_hash())
+                        .is_some()
+                };
+                (
+                    key_set.external.iter().map(is_used).collect::<Vec<_>>(),
+                    key_set.change.iter().map(is_used).collect::<Vec<_>>(),
+                )
+            })?;
+            external.update(&external_used);
+            change.update(&change_used);
+        }
+        Ok(DerivedIndices {
+            external_next: external.next,
+            change_next: change.next,
+        })
+    }
+
+    pub fn get_capacity(&mut self, lock_hashes: Vec<Byte32>) -> Result<CapacityBreakdown, String> {
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let tip_header: HeaderView = self.rpc_client.get_tip_header()?.into();
+        let (mut breakdown, dao_cells) = self.with_db(|db| {
+            let mut breakdown = CapacityBreakdown::default();
+            let mut dao_cells = Vec::new();
             for lock_hash in lock_hashes {
+                let time_locked = db
+                    .get_address(lock_hash.clone())
+                    .map(|payload| is_time_locked(&payload, &tip_header))
+                    .unwrap_or(false);
+                let mut terminator = |_idx: usize, info: &LiveCellInfo| {
+                    breakdown.total += info.capacity;
+                    let is_dao = info
+                        .type_hashes
+                        .as_ref()
+                        .filter(|(code_hash, _)| code_hash == &DAO_TYPE_HASH)
+                        .is_some();
+                    // NervosDAO cells are classified before the lock, even it is time locked
+                    if is_dao && info.data_bytes == 8 {
+                        dao_cells.push(info.clone());
+                    } else if is_dao {
+                        breakdown.occupied += info.capacity;
+                    } else if time_locked {
+                        breakdown.time_locked += info.capacity;
+                    } else if !is_mature(info, max_mature_number) {
+                        breakdown.immature += info.capacity;
+                    } else if info.type_hashes.is_some() || info.data_bytes > 0 {
+                        breakdown.occupied += info.capacity;
+                    } else {
+                        breakdown.free += info.capacity;
+                    }
+                    (false, false)
+                };
                 let _ = db.get_live_cells_by_lock(lock_hash, None, &mut terminator);
             }
-            (total_capacity, immature_capacity, dao_capacity)
-        })
+            (breakdown, dao_cells)
+        })?;
+
+        // Deposited cell's data is 0, prepared cell's data is the deposit block number
+        for cell in dao_cells {
+            let data = self
+                .rpc_client
+                .get_live_cell(cell.out_point(), true)?
+                .cell
+                .and_then(|cell_info| cell_info.data)
+                .ok_or_else(|| format!("cell is not found: {:?}", cell.out_point()))?
+                .content;
+            if data.as_bytes().iter().all(|byte| *byte == 0) {
+                breakdown.dao_deposited += cell.capacity;
+            } else {
+                breakdown.dao_prepared += cell.capacity;
+            }
+        }
+        Ok(breakdown)
     }
 
     pub fn get_live_cells<F>(
@@ -588,14 +1267,38 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
     fn process(&mut self, matches: &ArgMatches, debug: bool) -> Result<Output, String> {
         match matches.subcommand() {
             ("transfer", Some(m)) => {
-                let to_data = get_to_data(m)?;
+                let outputs = if let Some(path) = m.value_of("outputs-file") {
+                    read_transfer_outputs(path, get_network_type(self.rpc_client)?)?
+                } else {
+                    let to_lock_script = m
+                        .value_of("to-lock-script")
+                        .map(|input| {
+                            serde_json::from_str::<json_types::Script>(input)
+                                .map_err(|err| format!("Invalid to-lock-script: {}", err))
+                        })
+                        .transpose()?;
+                    // The capacity is decided by live cells when transfer all
+                    let capacity = if m.is_present("all") {
+                        "0".to_string()
+                    } else {
+                        get_arg_value(m, "capacity")?
+                    };
+                    vec![TransferOutput {
+                        to_address: m.value_of("to-address").map(|s| s.to_string()),
+                        to_lock_script,
+                        capacity,
+                        to_data: Some(get_to_data(m)?),
+                    }]
+                };
                 let args = TransferArgs {
                     privkey_path: m.value_of("privkey-path").map(|s| s.to_string()),
                     from_account: m.value_of("from-account").map(|s| s.to_string()),
+                    account_index: m.value_of("account-index").map(|s| s.to_string()),
                     from_locked_address: m.value_of("from-locked-address").map(|s| s.to_string()),
                     password: None,
-                    capacity: get_arg_value(m, "capacity")?,
-                    tx_fee: get_arg_value(m, "tx-fee")?,
+                    tx_fee: m.value_of("tx-fee").map(|s| s.to_string()),
+                    fee_rate: m.value_of("fee-rate").map(|s| s.to_string()),
+                    coin_selection: m.value_of("coin-selection").map(|s| s.to_string()),
                     derive_receiving_address_length: Some(get_arg_value(
                         m,
                         "derive-receiving-address-length",
@@ -603,19 +1306,240 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                     derive_change_address: m
                         .value_of("derive-change-address")
                         .map(|s| s.to_string()),
-                    to_address: get_arg_value(m, "to-address")?,
-                    to_data: Some(to_data),
+                    derive_change_address_length: None,
+                    outputs,
                     is_type_id: m.is_present("type-id"),
+                    allow_unknown_lock: m.is_present("allow-unknown-lock"),
+                    transfer_all: m.is_present("all"),
+                    min_cell_capacity: m.value_of("min-cell-capacity").map(|s| s.to_string()),
+                    use_pending_change: m.is_present("use-pending-change"),
+                };
+                let dry_run = m.is_present("dry-run");
+                let tx_file_opt: Option<PathBuf> =
+                    FilePathParser::new(false).from_matches_opt(m, "tx-file", false)?;
+                let ask_confirm = !dry_run && !m.is_present("no-confirm") && is_a_tty(false);
+                let network_type = get_network_type(self.rpc_client)?;
+                let mut dry_run_preview = None;
+                let result = self.transfer_with_confirm(args, false, |preview, helper| {
+                    if dry_run {
+                        if let Some(tx_file) = tx_file_opt.as_ref() {
+                            write_tx_file(tx_file, helper.clone(), network_type)?;
+                        }
+                        dry_run_preview = Some(preview.clone());
+                        Ok(false)
+                    } else if ask_confirm {
+                        println!("{}", preview);
+                        read_confirm("Sign and send this transaction?")
+                    } else {
+                        Ok(true)
+                    }
+                })?;
+                let (tx, tx_fee) = match result {
+                    Some(result) => result,
+                    None => {
+                        return if let Some(preview) = dry_run_preview {
+                            let mut resp =
+                                serde_json::to_value(&preview).map_err(|err| err.to_string())?;
+                            if let Some(tx_file) = tx_file_opt {
+                                resp["tx_file"] = serde_json::json!(tx_file.to_string_lossy());
+                            }
+                            Ok(Output::new_output(resp))
+                        } else {
+                            Err("Transaction canceled".to_string())
+                        };
+                    }
                 };
-                let tx = self.transfer(args, false)?;
                 if debug {
                     let rpc_tx_view = json_types::TransactionView::from(tx);
                     Ok(Output::new_output(rpc_tx_view))
+                } else if m.is_present("fee-rate") {
+                    let tx_hash: H256 = tx.hash().unpack();
+                    let tx_size = tx.data().serialized_size_in_block();
+                    Ok(Output::new_output(serde_json::json!({
+                        "transaction_hash": tx_hash,
+                        "tx_fee": format!("{:#}", HumanCapacity::from(tx_fee)),
+                        "tx_size": tx_size,
+                        "fee_rate": calculate_fee_rate(tx_fee, tx_size),
+                    })))
                 } else {
                     let tx_hash: H256 = tx.hash().unpack();
                     Ok(Output::new_output(tx_hash))
                 }
             }
+            ("sweep", Some(m)) => {
+                let args = TransferArgs {
+                    privkey_path: None,
+                    from_account: m.value_of("from-account").map(|s| s.to_string()),
+                    account_index: m.value_of("account-index").map(|s| s.to_string()),
+                    from_locked_address: None,
+                    password: None,
+                    derive_receiving_address_length: Some(get_arg_value(
+                        m,
+                        "derive-receiving-address-length",
+                    )?),
+                    derive_change_address: None,
+                    derive_change_address_length: Some(get_arg_value(
+                        m,
+                        "derive-change-address-length",
+                    )?),
+                    tx_fee: m.value_of("tx-fee").map(|s| s.to_string()),
+                    fee_rate: m.value_of("fee-rate").map(|s| s.to_string()),
+                    coin_selection: None,
+                    outputs: vec![TransferOutput {
+                        to_address: Some(get_arg_value(m, "to-address")?),
+                        to_lock_script: None,
+                        capacity: "0".to_string(),
+                        to_data: None,
+                    }],
+                    is_type_id: false,
+                    allow_unknown_lock: false,
+                    transfer_all: true,
+                    min_cell_capacity: m.value_of("min-cell-capacity").map(|s| s.to_string()),
+                    use_pending_change: false,
+                };
+                let (tx, tx_fee) = self.transfer(args, false)?;
+                if debug {
+                    let rpc_tx_view = json_types::TransactionView::from(tx);
+                    Ok(Output::new_output(rpc_tx_view))
+                } else {
+                    let tx_hash: H256 = tx.hash().unpack();
+                    let capacity: u64 = tx
+                        .outputs()
+                        .get(0)
+                        .map(|output| output.capacity().unpack())
+                        .unwrap_or(0);
+                    Ok(Output::new_output(serde_json::json!({
+                        "transaction_hash": tx_hash,
+                        "inputs": tx.inputs().len(),
+                        "capacity": format!("{:#}", HumanCapacity::from(capacity)),
+                        "tx_fee": format!("{:#}", HumanCapacity::from(tx_fee)),
+                    })))
+                }
+            }
+            ("consolidate", Some(m)) => {
+                let network_type = get_network_type(self.rpc_client)?;
+                let from_privkey: Option<PrivkeyWrapper> =
+                    PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
+                let from_account: Option<H160> = get_account(
+                    self.plugin_mgr.ckb_cli_dir(),
+                    Some(network_type),
+                    m,
+                    "from-account",
+                )?;
+                let to_address: Option<Address> = AddressParser::default()
+                    .set_network(network_type)
+                    .from_matches_opt(m, "to-address", false)?;
+                let cells_per_tx: usize =
+                    FromStrParser::<usize>::default().from_matches(m, "cells-per-tx")?;
+                let tx_fee: Option<u64> = CapacityParser
+                    .from_matches_opt(m, "tx-fee", false)?
+                    .map(Into::into);
+                let fee_rate: Option<u64> =
+                    FromStrParser::<u64>::default().from_matches_opt(m, "fee-rate", false)?;
+                let account_index: u32 =
+                    FromStrParser::<u32>::default().from_matches(m, "account-index")?;
+                if account_index > 0 && from_account.is_none() {
+                    return Err("account-index is only for from-account".to_string());
+                }
+                let dry_run = m.is_present("dry-run");
+                let args = ConsolidateArgs {
+                    from_privkey,
+                    from_account,
+                    account_index,
+                    to_address,
+                    cells_per_tx,
+                    tx_fee,
+                    fee_rate: fee_rate.unwrap_or(DEFAULT_FEE_RATE),
+                    dry_run,
+                };
+                let consolidate_txs = self.consolidate(args)?;
+                let total_inputs: usize = consolidate_txs.iter().map(|tx| tx.inputs).sum();
+                let total_capacity: u64 = consolidate_txs.iter().map(|tx| tx.capacity).sum();
+                let total_tx_fee: u64 = consolidate_txs.iter().map(|tx| tx.tx_fee).sum();
+                Ok(Output::new_output(serde_json::json!({
+                    "dry_run": dry_run,
+                    "transactions": consolidate_txs.len(),
+                    "inputs": total_inputs,
+                    "capacity": format!("{:#}", HumanCapacity::from(total_capacity)),
+                    "tx_fee": format!("{:#}", HumanCapacity::from(total_tx_fee)),
+                    "details": consolidate_txs.into_iter().map(|tx| {
+                        serde_json::json!({
+                            "transaction_hash": tx.tx_hash,
+                            "inputs": tx.inputs,
+                            "capacity": format!("{:#}", HumanCapacity::from(tx.capacity)),
+                            "tx_fee": format!("{:#}", HumanCapacity::from(tx.tx_fee)),
+                            "tx_size": tx.tx_size,
+                        })
+                    }).collect::<Vec<_>>(),
+                })))
+            }
+            ("scan-derived-addresses", Some(m)) => {
+                let network_type = get_network_type(self.rpc_client)?;
+                let account: H160 = get_account(
+                    self.plugin_mgr.ckb_cli_dir(),
+                    Some(network_type),
+                    m,
+                    "from-account",
+                )?
+                .ok_or_else(|| "<from-account> is required".to_string())?;
+                let gap_limit: u32 =
+                    FromStrParser::<u32>::default().from_matches(m, "gap-limit")?;
+                let account_index: u32 =
+                    FromStrParser::<u32>::default().from_matches(m, "account-index")?;
+                let password = if self.plugin_mgr.account_require_password(&account)
+                    && !self
+                        .plugin_mgr
+                        .keystore_handler()
+                        .is_watch_only(account.clone())?
+                {
+                    Some(read_password(false, None)?)
+                } else {
+                    None
+                };
+
+                let indices = self.scan_derived_indices(
+                    account.clone(),
+                    account_index,
+                    DerivedIndices::default(),
+                    gap_limit,
+                    password.clone(),
+                )?;
+                // Stored by the lock arg which transfers from
+                let (_, root_lock_arg) = self.plugin_mgr.keystore_handler().account_root(
+                    account.clone(),
+                    account_index,
+                    password.clone(),
+                )?;
+                let genesis_hash: H256 = self.genesis_info()?.header().hash().unpack();
+                DerivedIndicesStore::new(&self.index_dir, genesis_hash)
+                    .set(root_lock_arg, indices)?;
+
+                let key_set = self
+                    .plugin_mgr
+                    .keystore_handler()
+                    .derived_key_set_by_index(
+                        account,
+                        account_index,
+                        indices.external_next,
+                        1,
+                        indices.change_next,
+                        1,
+                        password,
+                    )?;
+                let to_address = |hash160: &H160| {
+                    Address::new(
+                        network_type,
+                        AddressPayload::from_pubkey_hash(hash160.clone()),
+                    )
+                    .to_string()
+                };
+                Ok(Output::new_output(serde_json::json!({
+                    "receiving_address_length": indices.external_next,
+                    "change_address_length": indices.change_next,
+                    "next_receiving_address": key_set.external.first().map(|(_, hash160)| to_address(hash160)),
+                    "next_change_address": key_set.change.first().map(|(_, hash160)| to_address(hash160)),
+                })))
+            }
             ("get-capacity", Some(m)) => {
                 let lock_hash_opt: Option<H256> =
                     FixedHashParser::<H256>::default().from_matches_opt(m, "lock-hash", false)?;
@@ -628,6 +1552,8 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                         .from_matches(m, "derive-receiving-address-length")?;
                     let change_address_length: u32 = FromStrParser::<u32>::default()
                         .from_matches(m, "derive-change-address-length")?;
+                    let account_index: u32 =
+                        FromStrParser::<u32>::default().from_matches(m, "account-index")?;
                     let address_payload = if let Some(address_str) = m.value_of("address") {
                         AddressParser::default()
                             .set_network(network_type)
@@ -640,17 +1566,39 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                     let mut lock_hashes = vec![Script::from(&address_payload).calc_script_hash()];
                     if m.is_present("derived") {
                         let lock_arg = H160::from_slice(address_payload.args().as_ref()).unwrap();
+                        // The account level key requires the password when the account index
+                        // is not added by `account add-account-index`
+                        let password = if account_index > 0
+                            && !self
+                                .plugin_mgr
+                                .keystore_handler()
+                                .is_watch_only(lock_arg.clone())?
+                            && self.plugin_mgr.account_require_password(&lock_arg)
+                        {
+                            Some(read_password(false, None)?)
+                        } else {
+                            None
+                        };
+                        if account_index > 0 {
+                            let (_, root_lock_arg) = self
+                                .plugin_mgr
+                                .keystore_handler()
+                                .account_root(lock_arg.clone(), account_index, password.clone())?;
+                            let payload = AddressPayload::from_pubkey_hash(root_lock_arg);
+                            lock_hashes.push(Script::from(&payload).calc_script_hash());
+                        }
 
                         let key_set = self
                             .plugin_mgr
                             .keystore_handler()
                             .derived_key_set_by_index(
                                 lock_arg,
+                                account_index,
                                 0,
                                 receiving_address_length,
                                 0,
                                 change_address_length,
-                                None,
+                                password,
                             )?;
                         for (_, hash160) in key_set.external.iter().chain(key_set.change.iter()) {
                             let payload = AddressPayload::from_pubkey_hash(hash160.clone());
@@ -660,18 +1608,23 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                     lock_hashes
                 };
 
-                let (total, immature, dao) = self.get_capacity(lock_hashes)?;
+                let breakdown = self.get_capacity(lock_hashes)?;
 
-                let mut resp =
-                    serde_json::json!({ "total": format!("{:#}", HumanCapacity::from(total)) });
-                if immature > 0 {
-                    resp["immature"] =
-                        serde_json::json!(format!("{:#}", HumanCapacity::from(immature)));
-                }
-                if dao > 0 {
-                    let free = total - dao;
-                    resp["dao"] = serde_json::json!(format!("{:#}", HumanCapacity::from(dao)));
-                    resp["free"] = serde_json::json!(format!("{:#}", HumanCapacity::from(free)));
+                let mut resp = serde_json::json!({
+                    "total": format!("{:#}", HumanCapacity::from(breakdown.total)),
+                    "free": format!("{:#}", HumanCapacity::from(breakdown.free)),
+                });
+                for (name, capacity) in &[
+                    ("immature", breakdown.immature),
+                    ("time_locked", breakdown.time_locked),
+                    ("dao_deposited", breakdown.dao_deposited),
+                    ("dao_prepared", breakdown.dao_prepared),
+                    ("occupied", breakdown.occupied),
+                ] {
+                    if *capacity > 0 {
+                        resp[*name] =
+                            serde_json::json!(format!("{:#}", HumanCapacity::from(*capacity)));
+                    }
                 }
                 Ok(Output::new_output(resp))
             }
@@ -762,6 +1715,108 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
 
                 Ok(Output::new_output(resp))
             }
+            ("get-transactions", Some(m)) => {
+                let lock_hash_opt: Option<H256> =
+                    FixedHashParser::<H256>::default().from_matches_opt(m, "lock-hash", false)?;
+                let limit: usize = FromStrParser::<usize>::default().from_matches(m, "limit")?;
+                let from_number_opt: Option<u64> =
+                    FromStrParser::<u64>::default().from_matches_opt(m, "from", false)?;
+                let to_number_opt: Option<u64> =
+                    FromStrParser::<u64>::default().from_matches_opt(m, "to", false)?;
+                let cursor_opt = m.value_of("cursor").map(parse_tx_cursor).transpose()?;
+
+                let lock_hash = if let Some(lock_hash) = lock_hash_opt {
+                    lock_hash.pack()
+                } else {
+                    let network_type = get_network_type(self.rpc_client)?;
+                    let address: Address = AddressParser::default()
+                        .set_network(network_type)
+                        .from_matches(m, "address")?;
+                    Script::from(address.payload()).calc_script_hash()
+                };
+                let (start_number_opt, infos) = self.with_db(|db| {
+                    let infos = db.get_transactions_by_lock(
+                        lock_hash,
+                        cursor_opt,
+                        from_number_opt,
+                        to_number_opt,
+                        limit,
+                    );
+                    (db.explorer_start_number(), infos)
+                })?;
+                // The records before the explorer enabled (or all records after it once disabled)
+                // are missing, report it instead of returning an incomplete history
+                let query_from = cursor_opt
+                    .map(|(number, _)| number)
+                    .max(from_number_opt)
+                    .unwrap_or(0);
+                match start_number_opt {
+                    None => {
+                        return Err(format!(
+                            "Transaction history is not recorded, delete the index database in {:?} and restart ckb-cli with --enable-explorer to rebuild it",
+                            self.index_dir
+                        ));
+                    }
+                    Some(start_number) if query_from < start_number => {
+                        return Err(format!(
+                            "Transaction history is only recorded since block {} (use --from {}), delete the index database in {:?} and restart ckb-cli with --enable-explorer to rebuild it",
+                            start_number, start_number, self.index_dir
+                        ));
+                    }
+                    _ => {}
+                }
+                let next_cursor = if infos.len() == limit {
+                    infos
+                        .last()
+                        .map(|info| format!("{}:{}", info.block_number, info.tx_index))
+                } else {
+                    None
+                };
+                let resp = serde_json::json!({
+                    "transactions": infos.iter().map(|info| {
+                        let delta = info.capacity_delta();
+                        let (sign, delta_abs) = if delta < 0 {
+                            ("-", (-delta) as u64)
+                        } else {
+                            ("+", delta as u64)
+                        };
+                        serde_json::json!({
+                            "block_number": info.block_number,
+                            "tx_index": info.tx_index,
+                            "tx_hash": info.tx_hash,
+                            "capacity_delta": format!("{}{:#}", sign, HumanCapacity::from(delta_abs)),
+                            "input_capacity": format!("{:#}", HumanCapacity::from(info.input_capacity)),
+                            "output_capacity": format!("{:#}", HumanCapacity::from(info.output_capacity)),
+                        })
+                    }).collect::<Vec<_>>(),
+                    "count": infos.len(),
+                    "next_cursor": next_cursor,
+                });
+                Ok(Output::new_output(resp))
+            }
+            ("pending", Some(m)) => {
+                let rebroadcast = m.is_present("rebroadcast");
+                let wait_confirmations: Option<u64> = FromStrParser::<u64>::default()
+                    .from_matches_opt(m, "wait-confirmations", false)?;
+                let pending_txs = self.pending_txs()?;
+                let mut txs = Vec::with_capacity(pending_txs.len());
+                for pending_tx in pending_txs {
+                    let info = check_tx_status(
+                        self.rpc_client,
+                        &pending_tx.tx_hash,
+                        rebroadcast,
+                        wait_confirmations,
+                    )?;
+                    let mut value = serde_json::to_value(&info).map_err(|err| err.to_string())?;
+                    value["sent_at"] = serde_json::json!(pending_tx.sent_at);
+                    txs.push(value);
+                }
+                let resp = serde_json::json!({
+                    "count": txs.len(),
+                    "transactions": txs,
+                });
+                Ok(Output::new_output(resp))
+            }
             ("top-capacity", Some(m)) => {
                 let n: usize = m
                     .value_of("number")
@@ -792,7 +1847,22 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
     }
 }
 
-fn get_keystore_signer(
+fn watch_only_error(account: &H160) -> String {
+    format!(
+        "Account {:#x} is watch-only and can not sign, use --dry-run to build the unsigned transaction",
+        account
+    )
+}
+
+fn build_type_id_script(args: &[u8]) -> Script {
+    Script::new_builder()
+        .code_hash(TYPE_ID_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(args.to_vec()).pack())
+        .build()
+}
+
+pub(crate) fn get_keystore_signer(
     keystore: KeyStoreHandler,
     mut client: HttpRpcClient,
     change_path: DerivationPath,
@@ -862,15 +1932,346 @@ fn get_keystore_signer(
 pub struct TransferArgs {
     pub privkey_path: Option<String>,
     pub from_account: Option<String>,
+    // BIP-44 account index of from_account, default is 0
+    pub account_index: Option<String>,
     pub from_locked_address: Option<String>,
     pub password: Option<String>,
     pub derive_receiving_address_length: Option<String>,
     pub derive_change_address: Option<String>,
+    // Search all derived receiving and change addresses (conflicts with derive_change_address)
+    pub derive_change_address_length: Option<String>,
+    pub tx_fee: Option<String>,
+    // Unit: shannons/KB
+    pub fee_rate: Option<String>,
+    // Default collect live cells by block order
+    pub coin_selection: Option<String>,
+    pub outputs: Vec<TransferOutput>,
+    pub is_type_id: bool,
+    // Allow transfer to lock scripts other than sighash and multisig
+    pub allow_unknown_lock: bool,
+    // Transfer all mature live cells to the only output, the output capacity is ignored
+    pub transfer_all: bool,
+    // Live cells with less capacity are not collected
+    pub min_cell_capacity: Option<String>,
+    // Also spend the outputs of pending transactions sent by ckb-cli (e.g. the change)
+    pub use_pending_change: bool,
+}
+
+pub struct ConsolidateArgs {
+    pub from_privkey: Option<PrivkeyWrapper>,
+    pub from_account: Option<H160>,
+    // BIP-44 account index of from_account
+    pub account_index: u32,
+    // Default to the from address
+    pub to_address: Option<Address>,
+    pub cells_per_tx: usize,
+    // The fee of each transaction, calculated by fee_rate when not given
+    pub tx_fee: Option<u64>,
+    // Unit: shannons/KB
+    pub fee_rate: u64,
+    pub dry_run: bool,
+}
+
+pub struct ConsolidateTx {
+    // None when dry run
+    pub tx_hash: Option<H256>,
+    pub inputs: usize,
+    pub capacity: u64,
+    pub tx_fee: u64,
+    pub tx_size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct TransferOutput {
+    // One of to_address and to_lock_script is required
+    pub to_address: Option<String>,
+    pub to_lock_script: Option<json_types::Script>,
     pub capacity: String,
-    pub tx_fee: String,
-    pub to_address: String,
     pub to_data: Option<Bytes>,
-    pub is_type_id: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReprTransferOutput {
+    address: Option<String>,
+    lock_script: Option<json_types::Script>,
+    capacity: String,
+    data: Option<String>,
+}
+
+impl TryFrom<ReprTransferOutput> for TransferOutput {
+    type Error = String;
+    fn try_from(repr: ReprTransferOutput) -> Result<Self, Self::Error> {
+        let to_data = repr
+            .data
+            .map(|data| HexParser.parse(&data).map(Bytes::from))
+            .transpose()?;
+        Ok(TransferOutput {
+            to_address: repr.address,
+            to_lock_script: repr.lock_script,
+            capacity: repr.capacity,
+            to_data,
+        })
+    }
+}
+
+// Cursor of transaction history: <block-number>:<tx-index>
+fn parse_tx_cursor(input: &str) -> Result<(u64, u32), String> {
+    let mut parts = input.split(':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(number), Some(tx_index), None) => {
+            let number = FromStrParser::<u64>::default().parse(number)?;
+            let tx_index = FromStrParser::<u32>::default().parse(tx_index)?;
+            Ok((number, tx_index))
+        }
+        _ => Err(format!(
+            "Invalid cursor: {}, expected <block-number>:<tx-index>",
+            input
+        )),
+    }
+}
+
+/// Read transfer outputs from a JSON or CSV file.
+///
+/// JSON: `[{"address": "ckt1...", "capacity": "100.5", "data": "0x..."}]`, the `address` can be
+/// replaced by `"lock_script": {"code_hash": "0x...", "hash_type": "type", "args": "0x..."}`
+/// CSV: `address,capacity[,data]` per line, empty lines and lines start with `#` are ignored
+pub fn read_transfer_outputs(
+    path: &str,
+    network: NetworkType,
+) -> Result<Vec<TransferOutput>, String> {
+    let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    parse_transfer_outputs(&content, network)
+        .map_err(|err| format!("Invalid outputs file {}: {}", path, err))
+}
+
+// The address and capacity of every row are checked here, so a bad row is reported with its
+// position before any live cell is collected
+fn parse_transfer_outputs(
+    content: &str,
+    network: NetworkType,
+) -> Result<Vec<TransferOutput>, String> {
+    let reprs: Vec<(usize, ReprTransferOutput)> = if content.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<ReprTransferOutput>>(content)
+            .map_err(|err| err.to_string())?
+            .into_iter()
+            .enumerate()
+            .map(|(idx, repr)| (idx + 1, repr))
+            .collect()
+    } else {
+        let mut reprs = Vec::new();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            // Skip the optional header line
+            if reprs.is_empty() && fields[0] == "address" {
+                continue;
+            }
+            if fields.len() < 2 || fields.len() > 3 {
+                return Err(format!(
+                    "line {}: expected `address,capacity[,data]`",
+                    idx + 1
+                ));
+            }
+            let repr = ReprTransferOutput {
+                address: Some(fields[0].to_string()),
+                lock_script: None,
+                capacity: fields[1].to_string(),
+                data: fields
+                    .get(2)
+                    .filter(|data| !data.is_empty())
+                    .map(|data| data.to_string()),
+            };
+            reprs.push((idx + 1, repr));
+        }
+        reprs
+    };
+    if reprs.is_empty() {
+        return Err("no output found".to_string());
+    }
+    reprs
+        .into_iter()
+        .map(|(position, repr)| {
+            if let Some(address) = repr.address.as_ref() {
+                AddressParser::default()
+                    .set_network(network)
+                    .parse(address)
+                    .map_err(|err| format!("output {}: {}", position, err))?;
+            }
+            CapacityParser
+                .parse(&repr.capacity)
+                .map_err(|err| format!("output {}: {}", position, err))?;
+            TransferOutput::try_from(repr).map_err(|err| format!("output {}: {}", position, err))
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PreviewCell {
+    // Only inputs have out point
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out_point: Option<String>,
+    pub address: String,
+    pub capacity: String,
+    pub data_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_script: Option<json_types::Script>,
+}
+
+// The unsigned transfer transaction in human readable form
+#[derive(Clone, Debug, Serialize)]
+pub struct TransferPreview {
+    pub inputs: Vec<PreviewCell>,
+    pub outputs: Vec<PreviewCell>,
+    pub change: Option<PreviewCell>,
+    pub tx_fee: String,
+    // Unit: shannons/KB
+    pub fee_rate: u64,
+    pub tx_size: usize,
+}
+
+impl TransferPreview {
+    fn new<F>(
+        helper: &TxHelper,
+        network: NetworkType,
+        outputs_len: usize,
+        tx_fee: u64,
+        tx_size: usize,
+        mut get_live_cell: F,
+    ) -> Result<TransferPreview, String>
+    where
+        F: FnMut(OutPoint, bool) -> Result<CellOutput, String>,
+    {
+        let tx = helper.transaction();
+        let preview_cell = |output: &CellOutput, data_size: usize| {
+            let capacity: u64 = output.capacity().unpack();
+            PreviewCell {
+                out_point: None,
+                address: Address::new(network, AddressPayload::from(output.lock())).to_string(),
+                capacity: format!("{:#}", HumanCapacity::from(capacity)),
+                data_size,
+                type_script: output.type_().to_opt().map(Into::into),
+            }
+        };
+        let mut inputs = Vec::new();
+        for input in tx.inputs().into_iter() {
+            let out_point = input.previous_output();
+            let output = get_live_cell(out_point.clone(), false)?;
+            let tx_hash: H256 = out_point.tx_hash().unpack();
+            let index: u32 = out_point.index().unpack();
+            let mut cell = preview_cell(&output, 0);
+            cell.out_point = Some(format!("{:#x}-{}", tx_hash, index));
+            inputs.push(cell);
+        }
+        let mut outputs = tx
+            .outputs_with_data_iter()
+            .map(|(output, data)| preview_cell(&output, data.len()))
+            .collect::<Vec<_>>();
+        // The change output is always the last one
+        let change = if outputs.len() > outputs_len {
+            outputs.pop()
+        } else {
+            None
+        };
+        Ok(TransferPreview {
+            inputs,
+            outputs,
+            change,
+            tx_fee: format!("{:#}", HumanCapacity::from(tx_fee)),
+            fee_rate: calculate_fee_rate(tx_fee, tx_size),
+            tx_size,
+        })
+    }
+}
+
+impl fmt::Display for TransferPreview {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "inputs:")?;
+        for cell in &self.inputs {
+            writeln!(
+                f,
+                "  {} {} ({})",
+                cell.out_point.as_ref().map(String::as_str).unwrap_or(""),
+                cell.capacity,
+                cell.address
+            )?;
+        }
+        writeln!(f, "outputs:")?;
+        for cell in &self.outputs {
+            let type_mark = if cell.type_script.is_some() {
+                " [type script]"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "  {} {} (data: {} bytes){}",
+                cell.address, cell.capacity, cell.data_size, type_mark
+            )?;
+        }
+        if let Some(cell) = self.change.as_ref() {
+            writeln!(f, "change:\n  {} {}", cell.address, cell.capacity)?;
+        }
+        write!(
+            f,
+            "tx_fee: {}, fee_rate: {} shannons/KB, tx_size: {} bytes",
+            self.tx_fee, self.fee_rate, self.tx_size
+        )
+    }
+}
+
+// Every live cell is counted in exactly one of the categories except `total`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CapacityBreakdown {
+    pub total: u64,
+    // Mature cells without type script and data, can be spent by transfer
+    pub free: u64,
+    // Cellbase cells not mature yet, excluding time-locked and NervosDAO cells
+    pub immature: u64,
+    // Cells locked by a multisig lock whose since is not reached yet, excluding NervosDAO cells
+    pub time_locked: u64,
+    pub dao_deposited: u64,
+    pub dao_prepared: u64,
+    // Cells with a type script or data, including NervosDAO cells with invalid data
+    pub occupied: u64,
+}
+
+impl CapacityBreakdown {
+    pub fn dao(&self) -> u64 {
+        self.dao_deposited + self.dao_prepared
+    }
+}
+
+// The lock args of a multisig lock with since: blake160(multisig script) | since (u64 LE)
+fn is_time_locked(payload: &AddressPayload, tip_header: &HeaderView) -> bool {
+    let args = payload.args();
+    if payload.code_hash() != MULTISIG_TYPE_HASH.pack() || args.len() != 28 {
+        return false;
+    }
+    let mut since_bytes = [0u8; 8];
+    since_bytes.copy_from_slice(&args[20..]);
+    let since = Since::from_raw_value(u64::from_le_bytes(since_bytes));
+    if !since.flags_is_valid() || !since.is_absolute() {
+        return false;
+    }
+    match since.extract_metric() {
+        Some((SinceType::BlockNumber, number)) => number > tip_header.number(),
+        Some((SinceType::EpochNumberWithFraction, value)) => {
+            let epoch = EpochNumberWithFraction::from_full_value(value);
+            let tip_epoch = tip_header.epoch();
+            epoch.number() > tip_epoch.number()
+                || (epoch.number() == tip_epoch.number()
+                    && epoch.index() * tip_epoch.length() > tip_epoch.index() * epoch.length())
+        }
+        // Since timestamp is the median time in seconds, tip timestamp is a close estimate
+        Some((SinceType::Timestamp, timestamp)) => {
+            timestamp.saturating_mul(1000) > tip_header.timestamp()
+        }
+        None => false,
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -885,3 +2286,95 @@ pub struct LiveCell {
     pub info: LiveCellInfo,
     pub mature: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(network: NetworkType, byte: u8) -> String {
+        let payload = AddressPayload::from_pubkey_hash(H160::from_slice(&[byte; 20]).unwrap());
+        Address::new(network, payload).to_string()
+    }
+
+    #[test]
+    fn test_parse_tx_cursor() {
+        assert_eq!(parse_tx_cursor("100:2"), Ok((100, 2)));
+        assert_eq!(parse_tx_cursor("0:0"), Ok((0, 0)));
+        assert!(parse_tx_cursor("100").is_err());
+        assert!(parse_tx_cursor("100:2:3").is_err());
+        assert!(parse_tx_cursor("100:-1").is_err());
+        assert!(parse_tx_cursor("a:1").is_err());
+    }
+
+    #[test]
+    fn test_parse_transfer_outputs_csv() {
+        let addr1 = address(NetworkType::Testnet, 1);
+        let addr2 = address(NetworkType::Testnet, 2);
+        let content = format!(
+            "address,capacity,data\n# comment\n\n{},100.5\n {} , 61 , 0x1234 \n",
+            addr1, addr2
+        );
+        let outputs = parse_transfer_outputs(&content, NetworkType::Testnet).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].to_address, Some(addr1));
+        assert_eq!(outputs[0].capacity, "100.5");
+        assert_eq!(outputs[0].to_data, None);
+        assert_eq!(outputs[1].to_address, Some(addr2));
+        assert_eq!(outputs[1].capacity, "61");
+        assert_eq!(outputs[1].to_data, Some(Bytes::from(vec![0x12, 0x34])));
+    }
+
+    #[test]
+    fn test_parse_transfer_outputs_json() {
+        let addr = address(NetworkType::Testnet, 1);
+        let content = format!(
+            r#"[
+                {{"address": "{}", "capacity": "100"}},
+                {{"lock_script": {{"code_hash": "0x{}", "hash_type": "data", "args": "0x"}}, "capacity": "200", "data": "0xab"}}
+            ]"#,
+            addr,
+            "00".repeat(32)
+        );
+        let outputs = parse_transfer_outputs(&content, NetworkType::Testnet).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].to_address, Some(addr.clone()));
+        assert!(outputs[0].to_lock_script.is_none());
+        assert!(outputs[1].to_address.is_none());
+        assert!(outputs[1].to_lock_script.is_some());
+        assert_eq!(outputs[1].to_data, Some(Bytes::from(vec![0xab])));
+
+        // The capacity must be a string, a JSON number may lose precision
+        let content = format!(r#"[{{"address": "{}", "capacity": 100}}]"#, addr);
+        assert!(parse_transfer_outputs(&content, NetworkType::Testnet).is_err());
+        // Unknown fields are rejected
+        let content = format!(
+            r#"[{{"address": "{}", "capacity": "100", "amount": "1"}}]"#,
+            addr
+        );
+        assert!(parse_transfer_outputs(&content, NetworkType::Testnet).is_err());
+    }
+
+    #[test]
+    fn test_parse_transfer_outputs_invalid() {
+        let testnet_addr = address(NetworkType::Testnet, 1);
+        let mainnet_addr = address(NetworkType::Mainnet, 1);
+        let parse = |content: String| parse_transfer_outputs(&content, NetworkType::Testnet);
+
+        assert!(parse(String::new()).is_err());
+        assert!(parse("# only comment\n".to_string()).is_err());
+
+        let err = parse(format!("{},100\n{}\n", testnet_addr, testnet_addr)).unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+        let err = parse(format!("{},100,0x,extra\n", testnet_addr)).unwrap_err();
+        assert!(err.starts_with("line 1:"), "{}", err);
+
+        let err = parse(format!("{},100\n{},100\n", testnet_addr, mainnet_addr)).unwrap_err();
+        assert!(err.starts_with("output 2:"), "{}", err);
+        let err = parse("not-an-address,100\n".to_string()).unwrap_err();
+        assert!(err.starts_with("output 1:"), "{}", err);
+        let err = parse(format!("{},abc\n", testnet_addr)).unwrap_err();
+        assert!(err.starts_with("output 1:"), "{}", err);
+        let err = parse(format!("{},100,0xzz\n", testnet_addr)).unwrap_err();
+        assert!(err.starts_with("output 1:"), "{}", err);
+    }
+}
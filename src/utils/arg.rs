@@ -2,6 +2,7 @@ use crate::utils::arg_parser::{
     AddressParser, ArgParser, CapacityParser, FilePathParser, FixedHashParser, FromStrParser,
     HexParser, OutPointParser, PrivkeyPathParser, PubkeyHexParser,
 };
+use ckb_sdk::wallet::AccountMetadata;
 use ckb_types::{H160, H256};
 use clap::Arg;
 
@@ -71,6 +72,22 @@ pub fn derived<'a>() -> Arg<'a> {
         .about("Search derived address space (search 10000 addresses(change/receiving) max, required keystore password, see: BIP-44)")
 }
 
+pub fn account_index<'a>() -> Arg<'a> {
+    Arg::with_name("account-index")
+        .long("account-index")
+        .takes_value(true)
+        .default_value("0")
+        .validator(|input| {
+            let index: u32 = FromStrParser::<u32>::default().parse(input)?;
+            if index >= 0x8000_0000 {
+                Err(format!("account index too large: {}", index))
+            } else {
+                Ok(())
+            }
+        })
+        .about("BIP-44 account index, derived addresses are under m/44'/309'/<account-index>'")
+}
+
 pub fn lock_arg<'a>() -> Arg<'a> {
     Arg::with_name("lock-arg")
         .long("lock-arg")
@@ -86,14 +103,14 @@ pub fn from_account<'a>() -> Arg<'a> {
         .validator(|input| {
             FixedHashParser::<H160>::default()
                 .validate(input)
-                .or_else(|err| {
-                    AddressParser::default()
+                .or_else(|err| match AddressParser::default().validate(input) {
+                    Ok(()) => AddressParser::new_sighash()
                         .validate(input)
-                        .and_then(|()| AddressParser::new_sighash().validate(input))
-                        .map_err(|_| err)
+                        .map_err(|_| err),
+                    Err(_) => AccountMetadata::check_label(input).map_err(|_| err),
                 })
         })
-        .about("The account's lock-arg or sighash address (transfer from this account)")
+        .about("The account's lock-arg, sighash address or label (transfer from this account)")
 }
 
 pub fn from_locked_address<'a>() -> Arg<'a> {
@@ -144,6 +161,36 @@ pub fn tx_fee<'a>() -> Arg<'a> {
         .about("The transaction fee capacity (unit: CKB, format: 0.0001)")
 }
 
+pub fn fee_rate<'a>() -> Arg<'a> {
+    Arg::with_name("fee-rate")
+        .long("fee-rate")
+        .takes_value(true)
+        .validator(|input| FromStrParser::<u64>::default().validate(input))
+        .conflicts_with("tx-fee")
+        .about("Calculate the transaction fee by the serialized size of the signed transaction (unit: shannons/KB)")
+}
+
+pub fn min_cell_capacity<'a>() -> Arg<'a> {
+    Arg::with_name("min-cell-capacity")
+        .long("min-cell-capacity")
+        .takes_value(true)
+        .validator(|input| CapacityParser.validate(input))
+        .about("Exclude live cells with capacity less than this value (unit: CKB, format: 123.335)")
+}
+
+pub fn coin_selection<'a>() -> Arg<'a> {
+    Arg::with_name("coin-selection")
+        .long("coin-selection")
+        .takes_value(true)
+        .possible_values(&[
+            "largest-first",
+            "smallest-first",
+            "branch-and-bound",
+            "minimize-inputs",
+        ])
+        .about("Strategy to select live cells as inputs (default: collect by block order)")
+}
+
 pub fn type_hash<'a>() -> Arg<'a> {
     Arg::with_name("type-hash")
         .long("type-hash")
@@ -195,6 +242,20 @@ pub fn top_n<'a>() -> Arg<'a> {
         .about("Get top n capacity addresses")
 }
 
+pub fn rebroadcast<'a>() -> Arg<'a> {
+    Arg::with_name("rebroadcast")
+        .long("rebroadcast")
+        .about("Broadcast the transaction to peers again if it is still pending (not proposed)")
+}
+
+pub fn wait_confirmations<'a>() -> Arg<'a> {
+    Arg::with_name("wait-confirmations")
+        .long("wait-confirmations")
+        .takes_value(true)
+        .validator(|input| FromStrParser::<u64>::default().validate(input))
+        .about("Block until the transaction got N confirmations (the committed block counts as 1)")
+}
+
 pub fn out_point<'a>() -> Arg<'a> {
     Arg::with_name("out-point")
         .long("out-point")
@@ -4,6 +4,7 @@ use std::iter;
 use std::sync::Arc;
 
 use ansi_term::Colour::{Green, Red};
+use ckb_util::RwLock;
 use rustyline::completion::{extract_word, Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
@@ -29,12 +30,15 @@ static ESCAPE_CHAR: Option<char> = None;
 
 pub struct CkbCompleter<'a> {
     clap_app: Arc<clap::App<'a>>,
+    // Completions of `--from-account` value, updated after every command
+    account_labels: Arc<RwLock<Vec<String>>>,
 }
 
 impl<'a> CkbCompleter<'a> {
-    pub fn new(clap_app: clap::App<'a>) -> Self {
+    pub fn new(clap_app: clap::App<'a>, account_labels: Arc<RwLock<Vec<String>>>) -> Self {
         CkbCompleter {
             clap_app: Arc::new(clap_app),
+            account_labels,
         }
     }
 
@@ -124,12 +128,26 @@ impl<'a> Completer for CkbCompleter<'a> {
         let (start, word) = extract_word(line, pos, ESCAPE_CHAR, &DEFAULT_BREAK_CHARS);
         let args = shell_words::split(&line[..pos]).unwrap();
         let word_lower = word.to_lowercase();
-        let tmp_pair = Self::find_subcommand(
-            self.clap_app.clone(),
-            args.iter().map(String::as_str).peekable(),
-        )
-        .map(|current_app| Self::get_completions(&current_app, &args))
-        .unwrap_or_default();
+        // The argument name before the word being completed
+        let last_arg = if word.is_empty() {
+            args.last()
+        } else {
+            args.iter().rev().nth(1)
+        };
+        let tmp_pair = if last_arg.map(String::as_str) == Some("--from-account") {
+            self.account_labels
+                .read()
+                .iter()
+                .map(|label| (label.clone(), label.clone()))
+                .collect()
+        } else {
+            Self::find_subcommand(
+                self.clap_app.clone(),
+                args.iter().map(String::as_str).peekable(),
+            )
+            .map(|current_app| Self::get_completions(&current_app, &args))
+            .unwrap_or_default()
+        };
 
         if word_lower.is_empty() {
             let pairs = tmp_pair
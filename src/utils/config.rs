@@ -22,6 +22,7 @@ pub struct GlobalConfig {
     color: bool,
     debug: bool,
     no_sync: bool,
+    enable_explorer: bool,
     output_format: OutputFormat,
     path: PathBuf,
     completion_style: bool,
@@ -38,6 +39,7 @@ impl GlobalConfig {
             color: true,
             debug: false,
             no_sync: false,
+            enable_explorer: false,
             output_format: OutputFormat::Yaml,
             path: env::current_dir().unwrap(),
             completion_style: true,
@@ -159,6 +161,10 @@ impl GlobalConfig {
         self.no_sync = value;
     }
 
+    pub fn set_enable_explorer(&mut self, value: bool) {
+        self.enable_explorer = value;
+    }
+
     pub fn set_output_format(&mut self, value: OutputFormat) {
         self.output_format = value;
     }
@@ -183,6 +189,10 @@ impl GlobalConfig {
         self.no_sync
     }
 
+    pub fn enable_explorer(&self) -> bool {
+        self.enable_explorer
+    }
+
     pub fn output_format(&self) -> OutputFormat {
         self.output_format
     }
@@ -200,6 +210,7 @@ impl GlobalConfig {
         let color = self.color.to_string();
         let debug = self.debug.to_string();
         let no_sync = self.no_sync.to_string();
+        let enable_explorer = self.enable_explorer.to_string();
         let output_format = self.output_format.to_string();
         let completion_style = if self.completion_style {
             "List"
@@ -222,6 +233,7 @@ impl GlobalConfig {
             ("color", color.as_str()),
             ("debug", debug.as_str()),
             ("no-sync", no_sync.as_str()),
+            ("enable-explorer", enable_explorer.as_str()),
             ("output format", output_format.as_str()),
             ("completion style", completion_style),
             ("edit style", edit_style),
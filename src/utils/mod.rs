@@ -5,6 +5,7 @@ pub mod config;
 pub mod index;
 pub mod json_color;
 pub mod other;
+pub mod pending_tx;
 pub mod printer;
 
 #[allow(clippy::cast_lossless)]
@@ -1,6 +1,6 @@
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read, Write};
 use std::path::PathBuf;
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -13,14 +13,16 @@ use ckb_sdk::{
     constants::{CELLBASE_MATURITY, MIN_SECP_CELL_CAPACITY, ONE_CKB},
     rpc::AlertMessage,
     wallet::{KeyStore, ScryptType},
-    Address, AddressPayload, CodeHashIndex, GenesisInfo, HttpRpcClient, NetworkType, SignerFn,
-    SECP256K1,
+    Address, AddressPayload, CodeHashIndex, GenesisInfo, HttpRpcClient, HumanCapacity, NetworkType,
+    SignerFn, SECP256K1,
 };
 use ckb_types::{
     bytes::Bytes,
-    core::{service::Request, BlockView, Capacity, EpochNumberWithFraction, TransactionView},
+    core::{
+        service::Request, BlockView, Capacity, EpochNumberWithFraction, HeaderView, TransactionView,
+    },
     h256,
-    packed::{CellOutput, OutPoint},
+    packed::{self, Byte32, CellOutput, OutPoint},
     prelude::*,
     H160, H256,
 };
@@ -48,6 +50,18 @@ pub fn read_password(repeat: bool, prompt: Option<&str>) -> Result<String, Strin
     Ok(pass)
 }
 
+// Ask user a yes/no question, default is no
+pub fn read_confirm(prompt: &str) -> Result<bool, String> {
+    print!("{} [y/N]: ", prompt);
+    io::stdout().flush().map_err(|err| err.to_string())?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|err| err.to_string())?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
 pub fn get_key_store(ckb_cli_dir: &PathBuf) -> Result<KeyStore, String> {
     let mut keystore_dir = ckb_cli_dir.clone();
     keystore_dir.push("keystore");
@@ -58,6 +72,41 @@ pub fn get_key_store(ckb_cli_dir: &PathBuf) -> Result<KeyStore, String> {
         })
 }
 
+// The account can be given as lock arg, sighash address or the label of an account in keystore
+pub fn parse_account(
+    ckb_cli_dir: &PathBuf,
+    network: Option<NetworkType>,
+    input: &str,
+) -> Result<H160, String> {
+    FixedHashParser::<H160>::default()
+        .parse(input)
+        .or_else(|err| {
+            let result: Result<Address, String> = AddressParser::new_sighash()
+                .set_network_opt(network)
+                .parse(input);
+            match result {
+                Ok(address) => Ok(H160::from_slice(&address.payload().args()).unwrap()),
+                Err(_) => get_key_store(ckb_cli_dir)?
+                    .find_by_label(input)
+                    .ok_or_else(|| format!("{}, and no account is labeled {:?}", err, input)),
+            }
+        })
+}
+
+pub fn get_account(
+    ckb_cli_dir: &PathBuf,
+    network: Option<NetworkType>,
+    m: &ArgMatches,
+    name: &str,
+) -> Result<Option<H160>, String> {
+    m.value_of(name)
+        .map(|input| {
+            parse_account(ckb_cli_dir, network, input)
+                .map_err(|err| format!("Invalid value for '--{}': {}", name, err))
+        })
+        .transpose()
+}
+
 pub fn get_address(network: Option<NetworkType>, m: &ArgMatches) -> Result<AddressPayload, String> {
     let address_opt: Option<Address> = AddressParser::default()
         .set_network_opt(network)
@@ -80,7 +129,7 @@ pub fn get_signer(
     require_password: bool,
 ) -> impl Fn(&H160, &H256, &rpc_types::Transaction) -> Result<[u8; 65], String> + 'static {
     move |lock_arg: &H160, message: &H256, _tx: &rpc_types::Transaction| {
-        let password = if require_password {
+        let password = if require_password && !keystore.is_unlocked(lock_arg.clone())? {
             let prompt = format!("Password for [{:x}]", lock_arg);
             Some(read_password(false, Some(prompt.as_str()))?)
         } else {
@@ -201,6 +250,27 @@ pub fn get_live_cell(
         })
 }
 
+// Get a committed transaction and the header of the block which committed it
+pub fn get_tx_with_header(
+    client: &mut HttpRpcClient,
+    tx_hash: Byte32,
+) -> Result<(TransactionView, HeaderView), String> {
+    let tx_hash: H256 = tx_hash.unpack();
+    let tx_status = client
+        .get_transaction(tx_hash.clone())?
+        .ok_or_else(|| format!("Transaction not found: {:#x}", tx_hash))?;
+    let block_hash = tx_status
+        .tx_status
+        .block_hash
+        .ok_or_else(|| format!("Transaction is not committed: {:#x}", tx_hash))?;
+    let header: HeaderView = client
+        .get_header(block_hash)?
+        .ok_or_else(|| format!("Block header of transaction not found: {:#x}", tx_hash))?
+        .into();
+    let tx: packed::Transaction = tx_status.transaction.inner.into();
+    Ok((tx.into_view(), header))
+}
+
 // Get max mature block number
 pub fn get_max_mature_number(rpc_client: &mut HttpRpcClient) -> Result<u64, String> {
     let tip_epoch = rpc_client
@@ -266,6 +336,24 @@ pub fn check_capacity(capacity: u64, to_data_len: usize) -> Result<(), String> {
     Ok(())
 }
 
+// Check the output capacity can hold its lock script, type script and data
+pub fn check_output_capacity(output: &CellOutput, data_len: usize) -> Result<(), String> {
+    let data_capacity = Capacity::bytes(data_len).map_err(|err| err.to_string())?;
+    let occupied_capacity = output
+        .occupied_capacity(data_capacity)
+        .map_err(|err| err.to_string())?
+        .as_u64();
+    let capacity: u64 = output.capacity().unpack();
+    if capacity < occupied_capacity {
+        return Err(format!(
+            "Capacity can not less than occupied capacity: {} < {}",
+            HumanCapacity::from(capacity),
+            HumanCapacity::from(occupied_capacity),
+        ));
+    }
+    Ok(())
+}
+
 pub fn check_lack_of_capacity(transaction: &TransactionView) -> Result<(), String> {
     for (output, output_data) in transaction.outputs_with_data_iter() {
         let exact = output
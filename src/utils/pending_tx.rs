@@ -0,0 +1,503 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ckb_index::{CellIndex, LiveCellInfo};
+use ckb_jsonrpc_types::{self as json_types, JsonBytes, Status};
+use ckb_sdk::HttpRpcClient;
+use ckb_types::{
+    bytes::Bytes,
+    core::TransactionView,
+    packed::{self, CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+use serde_derive::{Deserialize, Serialize};
+
+const PENDING_TXS_FILENAME: &str = "pending-txs.json";
+const PENDING_TXS_LOCK_FILENAME: &str = "pending-txs.json.lock";
+const PENDING_TXS_TMP_FILENAME: &str = "pending-txs.json.tmp";
+// The lock file is treated as left by a crashed process after this duration
+const LOCK_STALE_TIMEOUT: Duration = Duration::from_secs(30);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+// Give up when the lock is still held by another process after this duration
+const LOCK_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+// Interval of polling the transaction status when waiting for confirmations
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// A transaction sent by ckb-cli which is not committed (or not indexed) yet
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingTx {
+    pub tx_hash: H256,
+    // Unix timestamp in seconds
+    pub sent_at: u64,
+    pub inputs: Vec<json_types::OutPoint>,
+    pub outputs: Vec<json_types::CellOutput>,
+    pub outputs_data: Vec<JsonBytes>,
+}
+
+impl PendingTx {
+    pub fn new(tx: &TransactionView) -> PendingTx {
+        let sent_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        PendingTx {
+            tx_hash: tx.hash().unpack(),
+            sent_at,
+            inputs: tx
+                .input_pts_iter()
+                .map(json_types::OutPoint::from)
+                .collect(),
+            outputs: tx
+                .outputs()
+                .into_iter()
+                .map(json_types::CellOutput::from)
+                .collect(),
+            outputs_data: tx
+                .outputs_data()
+                .into_iter()
+                .map(|data| JsonBytes::from_bytes(data.raw_data()))
+                .collect(),
+        }
+    }
+
+    pub fn input_out_points(&self) -> Vec<OutPoint> {
+        self.inputs.iter().cloned().map(OutPoint::from).collect()
+    }
+
+    pub fn output_cells(&self) -> Vec<(OutPoint, CellOutput, Bytes)> {
+        self.outputs
+            .iter()
+            .zip(self.outputs_data.iter())
+            .enumerate()
+            .map(|(index, (output, data))| {
+                let out_point = OutPoint::new(self.tx_hash.pack(), index as u32);
+                (out_point, output.clone().into(), data.clone().into_bytes())
+            })
+            .collect()
+    }
+
+    // The outputs are not in index database, the block number and cell index are placeholders
+    // which sort them after all committed cells (and never be treated as cellbase).
+    pub fn output_live_cell_infos(&self) -> Vec<LiveCellInfo> {
+        self.output_cells()
+            .into_iter()
+            .map(|(out_point, output, data)| {
+                let output_index: u32 = out_point.index().unpack();
+                LiveCellInfo {
+                    tx_hash: self.tx_hash.clone(),
+                    output_index,
+                    data_bytes: data.len() as u64,
+                    lock_hash: output.lock().calc_script_hash().unpack(),
+                    type_hashes: output.type_().to_opt().map(|script| {
+                        (
+                            script.code_hash().unpack(),
+                            script.calc_script_hash().unpack(),
+                        )
+                    }),
+                    capacity: output.capacity().unpack(),
+                    number: u64::max_value(),
+                    index: CellIndex {
+                        tx_index: u32::max_value(),
+                        output_index,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+// Persisted pending transactions under ckb-cli home directory, it prevents the inputs of an
+// unconfirmed transaction being spent again by the next command.
+pub struct PendingTxStore {
+    path: PathBuf,
+    lock_path: PathBuf,
+    tmp_path: PathBuf,
+}
+
+// Advisory lock of a store file shared by all ckb-cli processes, the lock file is removed when
+// dropped.
+pub(crate) struct StoreLock {
+    path: PathBuf,
+}
+
+impl StoreLock {
+    pub(crate) fn acquire(path: &PathBuf) -> Result<StoreLock, String> {
+        StoreLock::acquire_with(path, LOCK_STALE_TIMEOUT, LOCK_WAIT_TIMEOUT)
+    }
+
+    fn acquire_with(
+        path: &PathBuf,
+        stale_timeout: Duration,
+        wait_timeout: Duration,
+    ) -> Result<StoreLock, String> {
+        let start = Instant::now();
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(_) => return Ok(StoreLock { path: path.clone() }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    let is_stale = fs::metadata(path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .map(|elapsed| elapsed > stale_timeout)
+                        .unwrap_or(false);
+                    if is_stale {
+                        let _ = fs::remove_file(path);
+                    } else if start.elapsed() >= wait_timeout {
+                        return Err(format!(
+                            "Lock file {} is held by another ckb-cli process, remove it if no other process is running",
+                            path.to_string_lossy()
+                        ));
+                    } else {
+                        thread::sleep(LOCK_RETRY_INTERVAL);
+                    }
+                }
+                Err(err) => {
+                    return Err(format!(
+                        "Create lock file {} failed: {}",
+                        path.to_string_lossy(),
+                        err
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl PendingTxStore {
+    pub fn new(ckb_cli_dir: &PathBuf) -> PendingTxStore {
+        PendingTxStore {
+            path: ckb_cli_dir.join(PENDING_TXS_FILENAME),
+            lock_path: ckb_cli_dir.join(PENDING_TXS_LOCK_FILENAME),
+            tmp_path: ckb_cli_dir.join(PENDING_TXS_TMP_FILENAME),
+        }
+    }
+
+    pub fn load(&self) -> Result<BTreeMap<H256, PendingTx>, String> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::default());
+        }
+        let file = fs::File::open(&self.path).map_err(|err| err.to_string())?;
+        serde_json::from_reader(&file)
+            .map_err(|err| format!("Invalid file {}: {}", self.path.to_string_lossy(), err))
+    }
+
+    fn save(&self, txs: &BTreeMap<H256, PendingTx>) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(txs).map_err(|err| err.to_string())?;
+        write_file_atomically(&self.path, &self.tmp_path, content.as_bytes())
+    }
+
+    // Load, modify then save the transactions while holding the lock
+    fn update<F>(&self, func: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut BTreeMap<H256, PendingTx>),
+    {
+        let _lock = StoreLock::acquire(&self.lock_path)?;
+        let mut txs = self.load()?;
+        func(&mut txs);
+        self.save(&txs)
+    }
+
+    // Called after the transaction is accepted by `send_transaction`, the transaction is already
+    // sent so the error is only logged.
+    pub fn add(&self, tx: &TransactionView) {
+        let result = self.update(|txs| {
+            let pending_tx = PendingTx::new(tx);
+            txs.insert(pending_tx.tx_hash.clone(), pending_tx);
+        });
+        if let Err(err) = result {
+            log::warn!("Record pending transaction failed: {}", err);
+        }
+    }
+
+    // Remove the transactions which are committed and indexed (block number <= indexed_number),
+    // or dropped from the tx pool. Returns the rest pending transactions.
+    pub fn refresh(
+        &self,
+        rpc_client: &mut HttpRpcClient,
+        indexed_number: Option<u64>,
+    ) -> Result<Vec<PendingTx>, String> {
+        self.refresh_by(|tx_hash| {
+            let is_pending = match rpc_client.get_transaction(tx_hash.clone())? {
+                None => false,
+                Some(tx_with_status) => match tx_with_status.tx_status.block_hash {
+                    None => true,
+                    Some(block_hash) => {
+                        let committed_number = rpc_client
+                            .get_header(block_hash)?
+                            .map(|header| header.inner.number.0);
+                        match (committed_number, indexed_number) {
+                            (Some(committed), Some(indexed)) => committed > indexed,
+                            _ => true,
+                        }
+                    }
+                },
+            };
+            Ok(is_pending)
+        })
+    }
+
+    fn refresh_by<F>(&self, mut is_pending: F) -> Result<Vec<PendingTx>, String>
+    where
+        F: FnMut(&H256) -> Result<bool, String>,
+    {
+        let txs = self.load()?;
+        if txs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut rest_txs = BTreeMap::default();
+        let mut done_txs = HashSet::new();
+        for (tx_hash, pending_tx) in txs.iter() {
+            if is_pending(tx_hash)? {
+                rest_txs.insert(tx_hash.clone(), pending_tx.clone());
+            } else {
+                done_txs.insert(tx_hash.clone());
+            }
+        }
+        // Reload under the lock, transactions added by other processes meanwhile are kept
+        if !done_txs.is_empty() {
+            self.update(|txs| {
+                for tx_hash in &done_txs {
+                    txs.remove(tx_hash);
+                }
+            })?;
+        }
+        Ok(rest_txs.into_iter().map(|(_, tx)| tx).collect())
+    }
+}
+
+// Write to a temporary file then rename it, so the file is never left half written.
+pub(crate) fn write_file_atomically(
+    path: &PathBuf,
+    tmp_path: &PathBuf,
+    content: &[u8],
+) -> Result<(), String> {
+    let mut file = fs::File::create(tmp_path).map_err(|err| err.to_string())?;
+    file.write_all(content)
+        .and_then(|_| file.sync_all())
+        .map_err(|err| err.to_string())?;
+    fs::rename(tmp_path, path).map_err(|err| err.to_string())
+}
+
+// The inputs spent by pending transactions
+pub fn reserved_inputs(pending_txs: &[PendingTx]) -> HashSet<OutPoint> {
+    pending_txs
+        .iter()
+        .flat_map(PendingTx::input_out_points)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxState {
+    // Not in the tx pool nor the chain of the node
+    Unknown,
+    Pending,
+    Proposed,
+    Committed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TxStatusInfo {
+    pub tx_hash: H256,
+    pub status: TxState,
+    pub block_number: Option<u64>,
+    // The committed block itself counts as 1 confirmation
+    pub confirmations: Option<u64>,
+    pub rebroadcast: bool,
+}
+
+// Query the status of a transaction, the transaction is also returned when the node knows it.
+pub fn query_tx_status(
+    rpc_client: &mut HttpRpcClient,
+    tx_hash: &H256,
+) -> Result<(TxStatusInfo, Option<packed::Transaction>), String> {
+    let mut info = TxStatusInfo {
+        tx_hash: tx_hash.clone(),
+        status: TxState::Unknown,
+        block_number: None,
+        confirmations: None,
+        rebroadcast: false,
+    };
+    let tx_with_status = match rpc_client.get_transaction(tx_hash.clone())? {
+        Some(tx_with_status) => tx_with_status,
+        None => return Ok((info, None)),
+    };
+    info.status = match tx_with_status.tx_status.status {
+        Status::Pending => TxState::Pending,
+        Status::Proposed => TxState::Proposed,
+        Status::Committed => TxState::Committed,
+    };
+    if let Some(block_hash) = tx_with_status.tx_status.block_hash {
+        let block_number = rpc_client
+            .get_header(block_hash.clone())?
+            .map(|header| header.inner.number.0)
+            .ok_or_else(|| format!("Block header not found: {:#x}", block_hash))?;
+        let tip_number = rpc_client.get_tip_block_number()?;
+        info.block_number = Some(block_number);
+        info.confirmations = Some(tip_number.saturating_sub(block_number) + 1);
+    }
+    Ok((info, Some(tx_with_status.transaction.inner.into())))
+}
+
+// Report the status of a transaction. A transaction still pending (not proposed yet) is
+// broadcast to peers again when `rebroadcast` is set, and the call blocks until the transaction
+// got enough confirmations when `wait_confirmations` is given.
+pub fn check_tx_status(
+    rpc_client: &mut HttpRpcClient,
+    tx_hash: &H256,
+    rebroadcast: bool,
+    wait_confirmations: Option<u64>,
+) -> Result<TxStatusInfo, String> {
+    let (mut info, tx_opt) = query_tx_status(rpc_client, tx_hash)?;
+    if let (true, TxState::Pending, Some(tx)) = (rebroadcast, info.status, tx_opt) {
+        rpc_client
+            .broadcast_transaction(tx)
+            .map_err(|err| format!("Broadcast transaction error: {}", err))?;
+        info.rebroadcast = true;
+    }
+    if let Some(confirmations) = wait_confirmations {
+        while info.confirmations.unwrap_or(0) < confirmations {
+            if info.status == TxState::Unknown {
+                return Err(format!(
+                    "Transaction {:#x} not found, it may be dropped from the tx pool",
+                    tx_hash
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+            let rebroadcast = info.rebroadcast;
+            info = query_tx_status(rpc_client, tx_hash)?.0;
+            info.rebroadcast = rebroadcast;
+        }
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_types::{core::TransactionBuilder, h256, packed::CellInput};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "ckb-cli-pending-tx-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn spend_tx(index: u32) -> TransactionView {
+        let out_point = OutPoint::new(h256!("0x1").pack(), index);
+        TransactionBuilder::default()
+            .input(CellInput::new(out_point, 0))
+            .output(
+                CellOutput::new_builder()
+                    .capacity(100_000_000_000u64.pack())
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .build()
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = test_dir("save");
+        let store = PendingTxStore::new(&dir);
+        assert!(store.load().unwrap().is_empty());
+
+        let tx = spend_tx(0);
+        store.add(&tx);
+        store.add(&spend_tx(1));
+        let txs = store.load().unwrap();
+        assert_eq!(txs.len(), 2);
+        let tx_hash: H256 = tx.hash().unpack();
+        let pending_tx = &txs[&tx_hash];
+        assert_eq!(
+            pending_tx.input_out_points(),
+            vec![OutPoint::new(h256!("0x1").pack(), 0)]
+        );
+        assert_eq!(pending_tx.output_cells().len(), 1);
+        let pending_txs = txs.values().cloned().collect::<Vec<_>>();
+        assert!(reserved_inputs(&pending_txs).contains(&OutPoint::new(h256!("0x1").pack(), 1)));
+
+        // Neither the temporary file nor the lock file is left
+        assert!(!dir.join(PENDING_TXS_TMP_FILENAME).exists());
+        assert!(!dir.join(PENDING_TXS_LOCK_FILENAME).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_refresh_removes_done_txs() {
+        let dir = test_dir("refresh");
+        let store = PendingTxStore::new(&dir);
+        let pending: H256 = spend_tx(0).hash().unpack();
+        let committed: H256 = spend_tx(1).hash().unpack();
+        store.add(&spend_tx(0));
+        store.add(&spend_tx(1));
+
+        let rest_txs = store.refresh_by(|tx_hash| Ok(tx_hash == &pending)).unwrap();
+        assert_eq!(
+            rest_txs
+                .into_iter()
+                .map(|tx| tx.tx_hash)
+                .collect::<Vec<_>>(),
+            vec![pending.clone()]
+        );
+        let txs = store.load().unwrap();
+        assert!(txs.contains_key(&pending));
+        assert!(!txs.contains_key(&committed));
+
+        // The store is not changed when the status query fails
+        assert!(store.refresh_by(|_| Err("rpc error".to_string())).is_err());
+        assert_eq!(store.load().unwrap().len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_store_lock() {
+        let dir = test_dir("lock");
+        let lock_path = dir.join(PENDING_TXS_LOCK_FILENAME);
+        let lock = StoreLock::acquire(&lock_path).unwrap();
+        assert!(lock_path.exists());
+        // Held by a live process, give up after the wait timeout
+        let result = StoreLock::acquire_with(
+            &lock_path,
+            Duration::from_secs(60),
+            Duration::from_millis(100),
+        );
+        assert!(result.is_err());
+        drop(lock);
+        assert!(!lock_path.exists());
+
+        // The lock left by a crashed process is taken over after it is stale
+        fs::File::create(&lock_path).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let lock = StoreLock::acquire_with(
+            &lock_path,
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+        )
+        .unwrap();
+        assert!(lock_path.exists());
+        drop(lock);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}